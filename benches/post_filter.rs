@@ -197,6 +197,8 @@ fn seed_data(num: u64) -> (Vec<Post>, AHashSet<String>) {
             extension: Extension::guess_format(&ext),
             rating,
             tags,
+            score: None,
+            ugoira_frames: Vec::new(),
         };
 
         v2.push(pst)
@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use ibdl_common::log::debug;
+use ibdl_core::{export::read_post_list, session::PostSource};
+
+use crate::error::CliError;
+
+#[derive(Debug, Args)]
+pub struct Import {
+    /// Post list file written with `--export posts`
+    #[clap(value_parser, value_name = "FILE")]
+    pub post_list: PathBuf,
+}
+
+impl Import {
+    /// Reads the post list, which is sent to the download queue as it is.
+    ///
+    /// The imageboard API is not contacted at all, only the file servers.
+    pub async fn post_source(&self) -> Result<PostSource, CliError> {
+        let posts = read_post_list(&self.post_list).await?;
+
+        debug!(
+            "Imported {} posts from {}",
+            posts.len(),
+            self.post_list.display()
+        );
+
+        Ok(PostSource::List { posts, filtered: 0 })
+    }
+}
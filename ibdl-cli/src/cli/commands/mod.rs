@@ -1,3 +1,6 @@
+pub mod import;
 pub mod pool;
 pub mod post;
+pub mod replay;
 pub mod search;
+pub mod stats;
@@ -1,24 +1,13 @@
 use std::path::PathBuf;
 
 use clap::Args;
-use ibdl_common::{
-    log::warn,
-    post::Post as Pst,
-    reqwest::Client,
-    tokio::{
-        fs,
-        sync::mpsc::{Sender, UnboundedSender},
-    },
-    ImageBoards,
-};
-use ibdl_extractors::imageboards::{
-    danbooru::DanbooruExtractor, e621::E621Extractor, gelbooru::GelbooruExtractor,
-};
+use ibdl_common::{log::warn, tokio::fs};
+use ibdl_core::session::PostSource;
 use ibdl_extractors::prelude::*;
 use owo_colors::OwoColorize;
 
 use crate::{
-    cli::{extra::auth_imgboard, Cli},
+    cli::{extra::load_auth, Cli},
     error::CliError,
 };
 
@@ -31,7 +20,7 @@ pub struct Post {
         conflicts_with("post_file"),
         required = true
     )]
-    posts: Vec<u32>,
+    pub posts: Vec<u32>,
 
     /// Download a list of posts from a file (one post id per line)
     #[clap(
@@ -40,210 +29,62 @@ pub struct Post {
         value_parser,
         conflicts_with("posts")
     )]
-    post_file: Option<PathBuf>,
+    pub post_file: Option<PathBuf>,
 }
 
 impl Post {
-    pub async fn init_extractor(
-        &self,
-        args: &Cli,
-        channel_tx: UnboundedSender<Pst>,
-        length_tx: Sender<u64>,
-    ) -> Result<(ExtractorThreadHandle, Client), CliError> {
-        match args.imageboard.server {
-            ImageBoards::Danbooru => {
-                let mut unit = DanbooruExtractor::new_with_config(
-                    &[""],
-                    &[],
-                    true,
-                    true,
-                    args.imageboard.clone(),
-                );
-                auth_imgboard(args.auth, &mut unit).await?;
-
-                let client = unit.client();
-
-                let ext_thd = {
-                    if !self.posts.is_empty() {
-                        unit.setup_async_post_fetch(
-                            channel_tx,
-                            PostFetchMethod::Multiple(self.posts.clone()),
-                            length_tx,
-                        )
-                    } else if let Some(path) = &self.post_file {
-                        let posts = fs::read_to_string(&path).await?;
-                        let ids = Vec::from_iter(posts.lines().filter_map(|line| {
-                            line.parse::<u32>().map_or_else(
-                                |_| {
-                                    warn!(
-                                        "Failed to parse line {} into a post id",
-                                        line.bright_blue().bold()
-                                    );
-                                    None
-                                },
-                                Some,
-                            )
-                        }));
-
-                        if ids.is_empty() {
-                            return Err(CliError::NoPostsInInput);
-                        }
-
-                        unit.setup_async_post_fetch(
-                            channel_tx,
-                            PostFetchMethod::Multiple(ids),
-                            length_tx,
-                        )
-                    } else {
-                        return Err(CliError::NoPostsInInput);
-                    }
-                };
-
-                Ok((ext_thd, client))
-            }
-            ImageBoards::E621 => {
-                let mut unit =
-                    E621Extractor::new_with_config(&[""], &[], true, true, args.imageboard.clone());
-                auth_imgboard(args.auth, &mut unit).await?;
-
-                let client = unit.client();
-                let ext_thd = {
-                    if !self.posts.is_empty() {
-                        unit.setup_async_post_fetch(
-                            channel_tx,
-                            PostFetchMethod::Multiple(self.posts.clone()),
-                            length_tx,
-                        )
-                    } else if let Some(path) = &self.post_file {
-                        let posts = fs::read_to_string(&path).await?;
-                        let ids = Vec::from_iter(posts.lines().filter_map(|line| {
-                            line.parse::<u32>().map_or_else(
-                                |_| {
-                                    warn!(
-                                        "Failed to parse line {} into a post id",
-                                        line.bright_blue().bold()
-                                    );
-                                    None
-                                },
-                                Some,
-                            )
-                        }));
-
-                        if ids.is_empty() {
-                            return Err(CliError::NoPostsInInput);
-                        }
-
-                        unit.setup_async_post_fetch(
-                            channel_tx,
-                            PostFetchMethod::Multiple(ids),
-                            length_tx,
-                        )
-                    } else {
-                        return Err(CliError::NoPostsInInput);
-                    }
-                };
-
-                Ok((ext_thd, client))
-            }
-            ImageBoards::GelbooruV0_2 | ImageBoards::Gelbooru => {
-                let unit = GelbooruExtractor::new_with_config(
-                    &[""],
-                    &[],
-                    true,
-                    true,
-                    args.imageboard.clone(),
-                );
-
-                let client = unit.client();
-                let ext_thd = {
-                    if !self.posts.is_empty() {
-                        unit.setup_async_post_fetch(
-                            channel_tx,
-                            PostFetchMethod::Multiple(self.posts.clone()),
-                            length_tx,
-                        )
-                    } else if let Some(path) = &self.post_file {
-                        let posts = fs::read_to_string(&path).await?;
-                        let ids = Vec::from_iter(posts.lines().filter_map(|line| {
-                            line.parse::<u32>().map_or_else(
-                                |_| {
-                                    warn!(
-                                        "Failed to parse line {} into a post id",
-                                        line.bright_blue().bold()
-                                    );
-                                    None
-                                },
-                                Some,
-                            )
-                        }));
-
-                        if ids.is_empty() {
-                            return Err(CliError::NoPostsInInput);
-                        }
-
-                        unit.setup_async_post_fetch(
-                            channel_tx,
-                            PostFetchMethod::Multiple(ids),
-                            length_tx,
-                        )
-                    } else {
-                        return Err(CliError::NoPostsInInput);
-                    }
-                };
-
-                Ok((ext_thd, client))
-
-                // let mut unit = GelbooruExtractor::new(
-                //     &args.tags,
-                //     &ratings,
-                //     args.disable_blacklist,
-                //     !args.no_animated,
-                // );
-
-                // unit.exclude_tags(&args.exclude)
-                //     .set_imageboard(*args.imageboard);
-
-                // if let Some(ext) = args.get_extension() {
-                //     unit.force_extension(ext);
-                // }
+    pub async fn post_source(&self, args: &Cli) -> Result<PostSource, CliError> {
+        if !args
+            .imageboard
+            .extractor_features()
+            .contains(ExtractorFeatures::SinglePostFetch)
+        {
+            return Err(CliError::ExtractorUnsupportedMode);
+        }
 
-                // let client = unit.client();
+        let mut builder = ExtractorBuilder::new(args.imageboard.clone()).blacklist(false);
 
-                // let ext_thd = unit.setup_fetch_thread(
-                //     channel_tx,
-                //     args.start_page,
-                //     args.limit,
-                //     Some(length_tx),
-                // );
+        if let Some(auth) = load_auth(args.auth, &args.imageboard).await? {
+            builder = builder.auth(auth);
+        }
 
-                // Ok((ext_thd, client))
+        let unit = builder
+            .build()
+            .await
+            .map_err(|source| CliError::ExtractorSetupFail { source })?
+            .extractor;
+
+        let ids = {
+            if !self.posts.is_empty() {
+                self.posts.clone()
+            } else if let Some(path) = &self.post_file {
+                let posts = fs::read_to_string(&path).await?;
+                let ids = Vec::from_iter(posts.lines().filter_map(|line| {
+                    line.parse::<u32>().map_or_else(
+                        |_| {
+                            warn!(
+                                "Failed to parse line {} into a post id",
+                                line.bright_blue().bold()
+                            );
+                            None
+                        },
+                        Some,
+                    )
+                }));
+
+                if ids.is_empty() {
+                    return Err(CliError::NoPostsInInput);
+                }
+
+                ids
+            } else {
+                return Err(CliError::NoPostsInInput);
             }
-            ImageBoards::Moebooru => {
-                Err(CliError::ExtractorUnsupportedMode)
-
-                // let mut unit = MoebooruExtractor::new(
-                //     &args.tags,
-                //     &ratings,
-                //     args.disable_blacklist,
-                //     !args.no_animated,
-                // );
-                // let client = unit.client();
-
-                // unit.exclude_tags(&args.exclude);
-
-                // if let Some(ext) = args.get_extension() {
-                //     unit.force_extension(ext);
-                // }
+        };
 
-                // let ext_thd = unit.setup_fetch_thread(
-                //     channel_tx,
-                //     args.start_page,
-                //     args.limit,
-                //     Some(length_tx),
-                // );
-
-                // Ok((ext_thd, client))
-            }
-        }
+        Ok(PostSource::Posts {
+            extractor: unit,
+            ids,
+        })
     }
 }
@@ -0,0 +1,123 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use clap::Args;
+use ibdl_common::{
+    log::debug,
+    post::Post,
+    tokio::fs::{read_dir, read_to_string},
+};
+use ibdl_core::session::PostSource;
+use ibdl_extractors::prelude::*;
+use ibdl_extractors::{blacklist::BlacklistFilter, error::ExtractorError};
+
+use crate::{
+    cli::{Cli, filters::PostFilters},
+    error::CliError,
+};
+
+#[derive(Debug, Args)]
+pub struct Replay {
+    /// Saved API response, or a directory containing multiple `.json` responses
+    ///
+    /// Both post list and single post responses are accepted.
+    #[clap(value_parser, value_name = "PATH")]
+    pub path: PathBuf,
+
+    #[clap(flatten)]
+    pub filters: PostFilters,
+}
+
+impl Replay {
+    /// Returns all the response files to be replayed, sorted by name.
+    async fn response_files(&self) -> Result<Vec<PathBuf>, CliError> {
+        if !self.path.is_dir() {
+            return Ok(vec![self.path.clone()]);
+        }
+
+        let mut files = Vec::new();
+        let mut entries = read_dir(&self.path).await?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+                files.push(path);
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    }
+
+    /// Maps a single response through the extractor of the selected imageboard.
+    fn map_response(unit: &AnyExtractor, raw_json: String) -> Result<Vec<Post>, ExtractorError> {
+        unit.map_posts(raw_json.clone()).or_else(|error| {
+            if unit.supports(ExtractorFeatures::SinglePostFetch) {
+                unit.map_post(raw_json).map(|post| vec![post])
+            } else {
+                Err(error)
+            }
+        })
+    }
+
+    /// Maps all saved API responses into posts, filters them with the blacklist and the selected options
+    /// and spawns a task that feeds the remaining posts into the download queue.
+    ///
+    /// The imageboard API is not contacted at all, only the file servers.
+    pub async fn post_source(&self, args: &Cli) -> Result<PostSource, CliError> {
+        let ratings = self.filters.selected_ratings();
+        let files = self.response_files().await?;
+
+        // Posts are only mapped here, the blacklist is applied to all of them at once below.
+        let unit = ExtractorBuilder::new(args.imageboard.clone())
+            .ratings(&ratings)
+            .blacklist(false)
+            .build()
+            .await
+            .map_err(|source| CliError::ExtractorSetupFail { source })?
+            .extractor;
+
+        let mut seen = HashSet::new();
+        let mut posts = Vec::new();
+
+        for file in &files {
+            let raw_json = read_to_string(file).await?;
+
+            let mapped =
+                Self::map_response(&unit, raw_json).map_err(|source| CliError::ReplayMapFail {
+                    file: file.display().to_string(),
+                    source,
+                })?;
+
+            debug!("Mapped {} posts from {}", mapped.len(), file.display());
+
+            posts.extend(mapped.into_iter().filter(|post| seen.insert(post.id)));
+        }
+
+        if posts.is_empty() {
+            return Err(CliError::NoPostsInInput);
+        }
+
+        let blacklist = BlacklistFilter::new(
+            args.imageboard.clone(),
+            &self.filters.exclude,
+            &ratings,
+            self.filters.disable_blacklist,
+            self.filters.no_animated,
+            self.filters.extension().as_slice(),
+        )
+        .await?;
+
+        let (removed, posts) = blacklist.filter(posts);
+
+        debug!(
+            "Replaying {} posts from {}",
+            posts.len(),
+            self.path.display()
+        );
+
+        Ok(PostSource::List {
+            posts,
+            filtered: removed,
+        })
+    }
+}
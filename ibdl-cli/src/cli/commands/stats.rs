@@ -0,0 +1,117 @@
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use ibdl_core::stats::{TagReport, TagStats, read_tag_stats};
+use owo_colors::OwoColorize;
+
+use crate::error::CliError;
+
+#[derive(Debug, Args)]
+pub struct Stats {
+    /// Post list written with `--export posts`, or an output dir with a `metadata.jsonl`, WebDataset shards or captions
+    #[clap(value_parser, value_name = "PATH")]
+    pub path: PathBuf,
+}
+
+impl Stats {
+    /// Counts the posts saved in the path and writes the report next to it.
+    pub async fn run(&self, top: usize) -> Result<(TagReport, PathBuf), CliError> {
+        let stats = read_tag_stats(&self.path)
+            .await
+            .map_err(|source| CliError::TagStatsFail { source })?;
+
+        let path = tag_stats_path(&self.path, self.path.is_file());
+        let report = write_tag_stats(&stats, top, &path).await?;
+        Ok((report, path))
+    }
+}
+
+/// Where the report of `save_path` is saved: inside it, or next to it if it's a file.
+///
+/// The CSV report is saved with the same name and the `csv` extension.
+pub fn tag_stats_path(save_path: &Path, is_file: bool) -> PathBuf {
+    if is_file {
+        return save_path.with_extension("tag_stats.json");
+    }
+
+    save_path.join("tag_stats.json")
+}
+
+/// Writes the report of `stats` to `path` as JSON and next to it as CSV.
+pub async fn write_tag_stats(
+    stats: &TagStats,
+    top: usize,
+    path: &Path,
+) -> Result<TagReport, CliError> {
+    let report = stats.report(top);
+
+    let write = async {
+        report.write_json(path).await?;
+        report.write_csv(&path.with_extension("csv")).await
+    };
+    write
+        .await
+        .map_err(|source| CliError::TagStatsFail { source })?;
+
+    Ok(report)
+}
+
+/// Prints the `top` most frequent tags of each type, the ratings, the extensions and the most frequent pairs.
+pub fn print_tag_report(report: &TagReport, top: usize, path: &Path) {
+    println!(
+        "{} {} {}",
+        "Tag statistics of".bold(),
+        report.posts.to_string().bold().blue(),
+        "posts".bold()
+    );
+
+    for (tag_type, tags) in &report.tags {
+        println!(
+            "{}",
+            format!("{tag_type:?} ({} tags):", tags.len())
+                .bold()
+                .green()
+        );
+        for tag in tags.iter().take(top) {
+            println!(" {:>8}  {}", tag.count.to_string().blue(), tag.tag);
+        }
+    }
+
+    let ratings: Vec<String> = report
+        .ratings
+        .iter()
+        .map(|(rating, count)| format!("{rating} {count}"))
+        .collect();
+    if !ratings.is_empty() {
+        println!("{} {}", "Ratings:".bold().green(), ratings.join(", "));
+    }
+
+    let extensions: Vec<String> = report
+        .extensions
+        .iter()
+        .map(|(extension, count)| format!("{extension} {count}"))
+        .collect();
+    if !extensions.is_empty() {
+        println!("{} {}", "Extensions:".bold().green(), extensions.join(", "));
+    }
+
+    if !report.co_occurrence.is_empty() {
+        println!("{}", "Most frequent pairs:".bold().green());
+        for pair in report.co_occurrence.iter().take(top) {
+            println!(
+                " {:>8}  {} + {}",
+                pair.count.to_string().blue(),
+                pair.a,
+                pair.b
+            );
+        }
+    }
+
+    println!(
+        "{} {} {} {}",
+        "Report saved to".bold(),
+        path.display().bold().blue(),
+        "and".bold(),
+        path.with_extension("csv").display().bold().blue()
+    );
+}
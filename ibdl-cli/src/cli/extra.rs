@@ -8,12 +8,14 @@ use crate::error::CliError;
 use dialoguer::{theme::ColorfulTheme, Input, Password};
 use ibdl_common::{
     bincode::deserialize,
+    client,
     directories::ProjectDirs, // Keep for get_servers
     log::{debug, info, warn}, // Added error and info
+    reqwest::Client,
     tokio::fs::{create_dir_all, read, remove_file, write}, // Added create_dir_all, write
     ImageBoards,
 };
-use ibdl_extractors::prelude::{Auth, Extractor};
+use ibdl_extractors::prelude::ExtractorFeatures;
 use ibdl_extractors::{
     auth::ImageboardConfig,
     extractor_config::{serialize::read_server_cfg_file, ServerConfig, DEFAULT_SERVERS},
@@ -23,9 +25,8 @@ use std::fs;
 
 use super::AVAILABLE_SERVERS;
 
-// The `auth_prompt` function is removed as its logic is now integrated into `auth_imgboard`.
-
-/// Handles authentication for an imageboard extractor.
+/// Loads the credentials used to authenticate with `server`, to be passed to
+/// [`ExtractorBuilder::auth`](ibdl_extractors::prelude::ExtractorBuilder::auth).
 ///
 /// It first tries to load existing credentials from the cache.
 /// If credentials are not found in the cache and `prompt_for_auth` is true,
@@ -35,40 +36,35 @@ use super::AVAILABLE_SERVERS;
 /// # Arguments
 /// * `prompt_for_auth`: If true, the function will prompt for credentials if none are cached.
 ///   If false, it will only attempt to use cached credentials.
-/// * `extractor`: A mutable reference to an extractor that implements `Auth` and `Extractor`.
+/// * `server`: The imageboard to authenticate with. Returns `None` if it doesn't support authentication.
 ///
 /// # Errors
-/// Returns a `CliError` if any step (cache reading, user input, authentication, cache writing,
-/// or applying auth to extractor) fails.
-pub async fn auth_imgboard<E>(prompt_for_auth: bool, extractor: &mut E) -> Result<(), CliError>
-where
-    E: Auth + Extractor + Send,
-{
-    let imageboard_server_config = extractor.config(); // This is &ServerConfig
-    let client = extractor.client();
+/// Returns a `CliError` if any step (cache reading, user input, authentication or cache writing) fails.
+pub async fn load_auth(
+    prompt_for_auth: bool,
+    server: &ServerConfig,
+) -> Result<Option<ImageboardConfig>, CliError> {
+    if !server
+        .extractor_features()
+        .contains(ExtractorFeatures::Auth)
+    {
+        return Ok(None);
+    }
 
     // Try to load credentials from cache first
-    match read_config_from_fs(&imageboard_server_config).await {
+    match read_config_from_fs(server).await {
         Ok(Some(cached_creds)) => {
-            debug!(
-                "Using cached credentials for {}",
-                imageboard_server_config.name
-            );
-            // Assuming CliError can convert from ExtractorError
-            extractor.auth(cached_creds).await?;
-            return Ok(());
+            debug!("Using cached credentials for {}", server.name);
+            return Ok(Some(cached_creds));
         }
         Ok(None) => {
-            debug!(
-                "No cached credentials found for {}.",
-                imageboard_server_config.name
-            );
+            debug!("No cached credentials found for {}.", server.name);
             // Proceed to prompt if allowed
         }
         Err(e) => {
             warn!(
                 "Failed to read auth cache for {}: {}. Proceeding without cached auth.",
-                imageboard_server_config.name, e
+                server.name, e
             );
             // Proceed to prompt if allowed, otherwise run unauthenticated.
             // The error 'e' here is an io::Error. If it's critical, it should be returned.
@@ -76,62 +72,60 @@ where
         }
     }
 
-    // If cache not found/invalid AND prompt_for_auth is true, then prompt the user
-    if prompt_for_auth {
-        info!(
-            "{} {}",
-            "Attempting to log into:".bold(),
-            imageboard_server_config.name.green().bold()
-        );
-
-        let username: String = Input::with_theme(&ColorfulTheme::default())
-            .with_prompt("Username")
-            .interact()?;
-
-        let api_key: String = Password::with_theme(&ColorfulTheme::default())
-            .with_prompt("API Key")
-            .interact()?;
-
-        let mut fresh_config = ImageboardConfig::new(
-            imageboard_server_config.clone(), // Clone the ServerConfig from extractor
-            username.trim().to_string(),
-            api_key.trim().to_string(),
-        );
-
-        fresh_config.authenticate(&client).await?;
-
-        info!(
-            "Successfully authenticated as user: {}",
-            fresh_config.user_data.name
-        );
-
-        // Serialize and write the new config to cache
-        let bytes = fresh_config.to_bincode_bytes()?;
-        let pretty_name = fresh_config.server_pretty_name();
-        let cache_dir = ImageBoards::auth_cache_dir()?;
-
-        if !cache_dir.exists() {
-            create_dir_all(&cache_dir).await?;
-        }
-
-        let config_file_path = cache_dir.join(pretty_name);
-        write(&config_file_path, &bytes).await?;
-        info!(
-            "Auth cache for {} saved to {}",
-            pretty_name,
-            config_file_path.display()
-        );
-
-        // Use the freshly authenticated and cached config for the extractor
-        extractor.auth(fresh_config).await?;
-    } else {
+    if !prompt_for_auth {
         debug!(
             "Not prompting for auth and no cache found for {}. Running unauthenticated.",
-            imageboard_server_config.name
+            server.name
         );
+        return Ok(None);
     }
 
-    Ok(())
+    info!(
+        "{} {}",
+        "Attempting to log into:".bold(),
+        server.name.green().bold()
+    );
+
+    let username: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Username")
+        .interact()?;
+
+    let api_key: String = Password::with_theme(&ColorfulTheme::default())
+        .with_prompt("API Key")
+        .interact()?;
+
+    let mut fresh_config = ImageboardConfig::new(
+        server.clone(),
+        username.trim().to_string(),
+        api_key.trim().to_string(),
+    );
+
+    let client = client!(server);
+    fresh_config.authenticate(&client).await?;
+
+    info!(
+        "Successfully authenticated as user: {}",
+        fresh_config.user_data.name
+    );
+
+    // Serialize and write the new config to cache
+    let bytes = fresh_config.to_bincode_bytes()?;
+    let pretty_name = fresh_config.server_pretty_name();
+    let cache_dir = ImageBoards::auth_cache_dir()?;
+
+    if !cache_dir.exists() {
+        create_dir_all(&cache_dir).await?;
+    }
+
+    let config_file_path = cache_dir.join(pretty_name);
+    write(&config_file_path, &bytes).await?;
+    info!(
+        "Auth cache for {} saved to {}",
+        pretty_name,
+        config_file_path.display()
+    );
+
+    Ok(Some(fresh_config))
 }
 
 /// Reads and parses the authentication cache from the path provided by `auth_cache_dir`.
@@ -202,3 +196,25 @@ pub fn validate_imageboard(input: &str) -> Result<ServerConfig, String> {
         |server| Ok(server.clone()),
     )
 }
+
+/// Parses a size like `500M` or `2G` into bytes. Suffixes are powers of 1024 and a plain number is in bytes.
+#[cfg(feature = "cbz")]
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let (number, multiplier) = match input.char_indices().last() {
+        Some((index, 'k' | 'K')) => (&input[..index], 1024),
+        Some((index, 'm' | 'M')) => (&input[..index], 1024 * 1024),
+        Some((index, 'g' | 'G')) => (&input[..index], 1024 * 1024 * 1024),
+        _ => (input, 1),
+    };
+
+    number
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .filter(|size| *size > 0)
+        .ok_or_else(|| {
+            format!("Invalid size: {input}. Use a number of bytes or a size like 500M or 2G")
+        })
+}
@@ -0,0 +1,104 @@
+use clap::Args;
+use ibdl_common::post::{extension::Extension, rating::Rating};
+use ibdl_extractors::prelude::VideoPolicy;
+
+use crate::RatingArg;
+
+/// Post filtering flags shared by every mode that selects posts by itself.
+#[derive(Debug, Args)]
+pub struct PostFilters {
+    /// Disable blacklist filtering
+    ///
+    /// Can't be combined with `--exclude` or `--no-animated`, since both are applied by the blacklist.
+    #[clap(long, value_parser, default_value_t = false, help_heading = "GENERAL")]
+    pub disable_blacklist: bool,
+
+    /// Exclude posts with these tags
+    #[clap(short, long, value_parser, help_heading = "GENERAL")]
+    pub exclude: Vec<String>,
+
+    /// Force the extractor to only fetch posts with the selected extension
+    #[clap(long, value_parser, help_heading = "DOWNLOAD", global = true)]
+    pub force_extension: Option<String>,
+
+    /// Do not download animated gifs or video files
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = false,
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub no_animated: bool,
+
+    /// Download images from the safe version of the selected Imageboard.
+    ///
+    /// Useful if you only want to download posts with "safe" rating.
+    #[clap(
+        long,
+        action,
+        default_value_t = false,
+        help_heading = "GENERAL",
+        global = true
+    )]
+    pub safe_mode: bool,
+
+    /// Download posts with the selected rating. Can be used multiple times to download posts with other ratings
+    #[clap(
+        short,
+        long,
+        value_parser,
+        help_heading = "GENERAL",
+        conflicts_with("safe_mode"),
+        global = true
+    )]
+    pub rating: Vec<RatingArg>,
+
+    /// Do not download posts with an unknown rating
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = false,
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub ignore_unknown: bool,
+}
+
+impl PostFilters {
+    #[inline]
+    pub fn selected_ratings(&self) -> Vec<Rating> {
+        let mut ratings: Vec<Rating> = Vec::with_capacity(4);
+        if self.rating.is_empty() {
+            ratings.push(Rating::Safe);
+
+            if !self.safe_mode {
+                ratings.push(Rating::Questionable);
+                ratings.push(Rating::Explicit)
+            }
+        } else {
+            self.rating.iter().for_each(|item| ratings.push(item.0));
+        };
+
+        if !self.ignore_unknown {
+            ratings.push(Rating::Unknown);
+        }
+        ratings
+    }
+
+    #[inline]
+    pub const fn video_policy(&self) -> VideoPolicy {
+        if self.no_animated {
+            VideoPolicy::Exclude
+        } else {
+            VideoPolicy::Include
+        }
+    }
+
+    #[inline]
+    pub fn extension(&self) -> Option<Extension> {
+        self.force_extension
+            .as_ref()
+            .map(|ext| Extension::guess_format(ext))
+    }
+}
@@ -0,0 +1,111 @@
+use std::path::{Path, PathBuf};
+
+use ibdl_core::job::JobState;
+
+use crate::error::CliError;
+
+/// Options for the extractor of a single run, which may come from a resumed job instead of the command line.
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractorJob {
+    pub start_page: Option<u16>,
+    pub limit: Option<u16>,
+}
+
+/// Where the state of an interrupted download into `save_path` is saved: inside it, or next to it if it's an
+/// archive.
+pub fn job_state_path(save_path: &Path, is_archive: bool) -> PathBuf {
+    if is_archive {
+        return save_path.with_extension("job.json");
+    }
+
+    save_path.join(".ibdl_job.json")
+}
+
+/// Loads the job state at `path` and checks that it was saved by a run of the same `query` on `server`.
+pub async fn load_job_state(
+    path: &Path,
+    server: &str,
+    query: &[String],
+) -> Result<JobState, CliError> {
+    if !path.is_file() {
+        return Err(CliError::JobStateNotFound {
+            file: path.display().to_string(),
+        });
+    }
+
+    let state = JobState::load(path)
+        .await
+        .map_err(|source| CliError::JobStateReadFail { source })?;
+
+    if state.server != server || state.query != query {
+        return Err(CliError::JobStateMismatch {
+            file: path.display().to_string(),
+        });
+    }
+
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ibdl_core::job::JobState;
+
+    use super::{job_state_path, load_job_state};
+    use crate::error::CliError;
+
+    fn query() -> Vec<String> {
+        vec![String::from("search"), String::from("kroos_(arknights)")]
+    }
+
+    #[test]
+    fn state_is_saved_next_to_archives() {
+        assert_eq!(
+            job_state_path(Path::new("out/danbooru/kroos.cbz"), true),
+            Path::new("out/danbooru/kroos.job.json")
+        );
+        assert_eq!(
+            job_state_path(Path::new("out/danbooru/kroos"), false),
+            Path::new("out/danbooru/kroos/.ibdl_job.json")
+        );
+    }
+
+    #[tokio::test]
+    async fn resumes_the_state_of_a_cbz_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = job_state_path(&dir.path().join("kroos.cbz"), true);
+
+        let state = JobState {
+            server: String::from("danbooru"),
+            query: query(),
+            cursor: Some(3),
+            limit: None,
+            pending: Vec::new(),
+        };
+        state.save(&path).await.unwrap();
+
+        let loaded = load_job_state(&path, "danbooru", &query()).await.unwrap();
+        assert_eq!(loaded.cursor, Some(3));
+
+        let other_search = load_job_state(&path, "danbooru", &[String::from("search")]).await;
+        assert!(matches!(
+            other_search,
+            Err(CliError::JobStateMismatch { .. })
+        ));
+        let other_server = load_job_state(&path, "e621", &query()).await;
+        assert!(matches!(
+            other_server,
+            Err(CliError::JobStateMismatch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn missing_state_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = job_state_path(&dir.path().join("kroos.cbz"), true);
+
+        let result = load_job_state(&path, "danbooru", &query()).await;
+        assert!(matches!(result, Err(CliError::JobStateNotFound { .. })));
+    }
+}
@@ -1,21 +1,52 @@
 // 20002709
-use ibdl_common::post::{NameType, extension::Extension};
+use ibdl_common::post::{NameType, channel::DEFAULT_CHANNEL_SIZE, extension::Extension};
+#[cfg(feature = "image")]
+use ibdl_core::process::{Buckets, DEFAULT_QUALITY, Fit, ImageProcessor, OutputFormat};
+#[cfg(feature = "cbz")]
+use ibdl_core::sink::{ExistingArchive, VolumeLimits, ZipCompression};
+#[cfg(feature = "s3")]
+use ibdl_core::sink::{S3Config, S3Sink};
+#[cfg(feature = "tar")]
+use ibdl_core::sink::{TarCompression, TarSink, WebDatasetSink};
+#[cfg(feature = "image")]
+use ibdl_core::ugoira::UgoiraFormat;
+#[cfg(feature = "s3")]
+use ibdl_core::error::QueueError;
+use ibdl_core::{
+    caption::{CaptionConfig, TagOrder},
+    export::ExportFormat,
+    job::JobState,
+    sink::ImageFolderSink,
+};
 use ibdl_extractors::extractor_config::ServerConfig;
 use once_cell::sync::OnceCell;
-use std::{collections::HashMap, path::PathBuf};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
-#[cfg(feature = "cbz")]
-use std::path::Path;
+use clap::{Parser, Subcommand, ValueEnum};
 
-use clap::{Parser, Subcommand};
+use crate::{
+    TagTypeArg,
+    error::CliError,
+    progress_bars::{JsonProgressHandler, ProgressMode},
+};
 
+#[cfg(feature = "cbz")]
+use self::extra::parse_size;
 use self::{
-    commands::{pool::Pool, post::Post, search::TagSearch},
+    commands::{
+        import::Import, pool::Pool, post::Post, replay::Replay, search::TagSearch, stats::Stats,
+    },
     extra::validate_imageboard,
 };
 
 pub mod commands;
 pub(crate) mod extra;
+pub mod filters;
+pub mod job;
 
 pub static AVAILABLE_SERVERS: OnceCell<HashMap<String, ServerConfig>> = OnceCell::new();
 
@@ -27,6 +58,73 @@ pub enum Commands {
     Pool(Pool),
     /// Download a single or multiple specific posts
     Post(Post),
+    /// Download posts from a list previously saved with `--export posts`
+    Import(Import),
+    /// Download posts from saved API responses of the selected imageboard
+    Replay(Replay),
+    /// Print the tag, rating and extension distribution of an existing output, without downloading anything
+    Stats(Stats),
+}
+
+/// Archive formats that can be selected with `--archive`.
+#[cfg(any(feature = "cbz", feature = "tar"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ArchiveFormat {
+    /// Zip archive, like `--cbz` but compressed with `--compression`
+    #[cfg(feature = "cbz")]
+    Zip,
+    /// Uncompressed tar archive
+    #[cfg(feature = "tar")]
+    Tar,
+    /// Tar archive compressed with zstd
+    #[cfg(feature = "tar")]
+    TarZst,
+}
+
+#[cfg(any(feature = "cbz", feature = "tar"))]
+impl ArchiveFormat {
+    /// Extension of the archive file.
+    pub const fn extension(self) -> &'static str {
+        match self {
+            #[cfg(feature = "cbz")]
+            Self::Zip => "zip",
+            #[cfg(feature = "tar")]
+            Self::Tar => "tar",
+            #[cfg(feature = "tar")]
+            Self::TarZst => "tar.zst",
+        }
+    }
+}
+
+/// Dataset layouts that can be selected with `--dataset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DatasetFormat {
+    /// WebDataset tar shards, with a caption and a JSON file for every image
+    #[cfg(feature = "tar")]
+    Webdataset,
+    /// A folder of images with a `metadata.jsonl`, for the Hugging Face `imagefolder` loader
+    Imagefolder,
+}
+
+/// Tag orders that can be selected with `--caption-order`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CaptionOrder {
+    /// Keep the order given by the imageboard
+    AsIs,
+    /// Sort tags alphabetically within each tag type
+    Alphabetical,
+    /// Shuffle tags, keeping the first `--caption-keep-first` in front
+    Shuffle,
+}
+
+/// Compression methods that can be selected with `--compression`.
+#[cfg(feature = "cbz")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompressionMethod {
+    /// Save files as they are. Default for cbz files
+    Stored,
+    /// Compress files with deflate. Default for zip files
+    Deflated,
 }
 
 #[derive(Parser, Debug)]
@@ -63,6 +161,18 @@ pub struct Cli {
     )]
     pub simultaneous_downloads: u8,
 
+    /// Maximum number of found posts waiting to be downloaded.
+    ///
+    /// The search pauses while the queue is full, keeping memory usage low on big downloads. Set to 0 to remove the limit.
+    #[clap(
+        long,
+        value_name = "POSTS",
+        default_value_t = DEFAULT_CHANNEL_SIZE,
+        help_heading = "DOWNLOAD",
+        global = true
+    )]
+    pub queue_size: usize,
+
     /// Authenticate to the imageboard website.
     ///
     /// This flag only needs to be set a single time.
@@ -85,7 +195,7 @@ pub struct Cli {
 
     /// Save posts inside a cbz file.
     ///
-    /// Will ask to overwrite the destination file.
+    /// If the file already exists, new posts are appended to it and the ones already inside are skipped.
     #[cfg(feature = "cbz")]
     #[clap(
         long,
@@ -96,6 +206,172 @@ pub struct Cli {
     )]
     pub cbz: bool,
 
+    /// Split the cbz file into volumes of at most this many posts, named like `name_001.cbz`
+    #[cfg(feature = "cbz")]
+    #[clap(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        requires = "cbz",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub cbz_max_posts: Option<usize>,
+
+    /// Split the cbz file into volumes of at most this size, like `500M` or `2G`
+    ///
+    /// A post bigger than the limit is saved alone in its own volume.
+    #[cfg(feature = "cbz")]
+    #[clap(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        requires = "cbz",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub cbz_max_size: Option<u64>,
+
+    /// Save posts inside an archive of this format.
+    ///
+    /// Zip files are appended to like cbz files. Tar files can't be appended to, so an existing one is replaced.
+    #[cfg(any(feature = "cbz", feature = "tar"))]
+    #[cfg_attr(feature = "cbz", clap(conflicts_with = "cbz"))]
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub archive: Option<ArchiveFormat>,
+
+    /// How files are compressed inside cbz and zip files
+    #[cfg(feature = "cbz")]
+    #[clap(
+        long,
+        value_enum,
+        value_name = "METHOD",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub compression: Option<CompressionMethod>,
+
+    /// Compression level, from 0 to 9 for deflated zip files and from 1 to 22 for tar-zst files
+    #[cfg(any(feature = "cbz", feature = "tar"))]
+    #[clap(
+        long,
+        value_name = "LEVEL",
+        value_parser = clap::value_parser!(i32).range(0..=22),
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub compression_level: Option<i32>,
+
+    /// Save posts as a dataset ready to be loaded by training frameworks.
+    ///
+    /// Every post is saved with its caption, tags by type, rating and score.
+    #[cfg_attr(feature = "cbz", clap(conflicts_with = "cbz"))]
+    #[cfg_attr(
+        any(feature = "cbz", feature = "tar"),
+        clap(conflicts_with = "archive")
+    )]
+    #[cfg_attr(feature = "s3", clap(conflicts_with = "s3_bucket"))]
+    #[clap(
+        long,
+        value_enum,
+        value_name = "LAYOUT",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub dataset: Option<DatasetFormat>,
+
+    /// Number of samples in each WebDataset shard
+    #[cfg(feature = "tar")]
+    #[clap(
+        long,
+        value_name = "NUMBER",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        default_value_t = ibdl_core::sink::DEFAULT_SHARD_SIZE,
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub shard_size: usize,
+
+    /// Upload posts into this bucket of an S3-compatible object storage instead of saving them locally.
+    ///
+    /// Credentials are set with `--s3-access-key` and `--s3-secret-key`.
+    /// Files already uploaded with the same MD5 hash are skipped.
+    #[cfg(feature = "s3")]
+    #[cfg_attr(feature = "cbz", clap(conflicts_with = "cbz"))]
+    #[cfg_attr(
+        any(feature = "cbz", feature = "tar"),
+        clap(conflicts_with = "archive")
+    )]
+    #[clap(long, value_name = "BUCKET", help_heading = "S3", global = true)]
+    pub s3_bucket: Option<String>,
+
+    /// URL of the object storage. Defaults to AWS S3 in the selected region
+    #[cfg(feature = "s3")]
+    #[clap(
+        long,
+        value_name = "URL",
+        env = "IBDL_S3_ENDPOINT",
+        requires = "s3_bucket",
+        help_heading = "S3",
+        global = true
+    )]
+    pub s3_endpoint: Option<String>,
+
+    /// Prepended to the name of every uploaded file, like `umbreon/`
+    #[cfg(feature = "s3")]
+    #[clap(
+        long,
+        value_name = "PREFIX",
+        default_value = "",
+        requires = "s3_bucket",
+        help_heading = "S3",
+        global = true
+    )]
+    pub s3_prefix: String,
+
+    /// Region of the bucket, used to sign requests
+    #[cfg(feature = "s3")]
+    #[clap(
+        long,
+        value_name = "REGION",
+        env = "AWS_REGION",
+        default_value = "us-east-1",
+        help_heading = "S3",
+        global = true
+    )]
+    pub s3_region: String,
+
+    /// Access key of the object storage
+    #[cfg(feature = "s3")]
+    #[clap(
+        long,
+        value_name = "KEY",
+        env = "AWS_ACCESS_KEY_ID",
+        requires = "s3_bucket",
+        help_heading = "S3",
+        global = true
+    )]
+    pub s3_access_key: Option<String>,
+
+    /// Secret key of the object storage. Prefer the environment variable, since arguments can be seen by other users
+    #[cfg(feature = "s3")]
+    #[clap(
+        long,
+        value_name = "KEY",
+        env = "AWS_SECRET_ACCESS_KEY",
+        hide_env_values = true,
+        requires = "s3_bucket",
+        help_heading = "S3",
+        global = true
+    )]
+    pub s3_secret_key: Option<String>,
+
     /// Write tags in a txt file next to the downloaded image (for Stable Diffusion training)
     #[clap(
         long,
@@ -106,7 +382,192 @@ pub struct Cli {
     )]
     pub annotate: bool,
 
-    /// Always overwrite output
+    /// Tag types written into captions, in this order.
+    ///
+    /// By default, character, species, general and uncategorized tags are written in the order given by the imageboard.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "TYPES",
+        value_delimiter = ',',
+        help_heading = "CAPTION",
+        global = true
+    )]
+    pub caption_types: Vec<TagTypeArg>,
+
+    /// Word written at the start of every caption
+    #[clap(long, value_name = "WORD", help_heading = "CAPTION", global = true)]
+    pub caption_trigger: Option<String>,
+
+    /// Tag written after the trigger word, with `{rating}` replaced by the rating of the post, like `rating:{rating}`
+    #[clap(long, value_name = "FORMAT", help_heading = "CAPTION", global = true)]
+    pub caption_rating: Option<String>,
+
+    /// Order of the tags in captions
+    #[clap(
+        long,
+        value_enum,
+        value_name = "ORDER",
+        default_value_t = CaptionOrder::AsIs,
+        help_heading = "CAPTION",
+        global = true
+    )]
+    pub caption_order: CaptionOrder,
+
+    /// Number of tags kept in front when shuffling them
+    #[clap(
+        long,
+        value_name = "NUMBER",
+        default_value_t = 0,
+        help_heading = "CAPTION",
+        global = true
+    )]
+    pub caption_keep_first: usize,
+
+    /// Maximum number of tags in captions, not counting the trigger word and the rating tag
+    #[clap(long, value_name = "NUMBER", help_heading = "CAPTION", global = true)]
+    pub caption_max_tags: Option<usize>,
+
+    /// Tags left out of captions, separated by commas
+    #[clap(
+        long,
+        value_name = "TAGS",
+        value_delimiter = ',',
+        help_heading = "CAPTION",
+        global = true
+    )]
+    pub caption_exclude: Vec<String>,
+
+    /// Tags matching this regular expression are left out of captions
+    #[clap(
+        long,
+        value_name = "REGEX",
+        value_parser = Regex::new,
+        help_heading = "CAPTION",
+        global = true
+    )]
+    pub caption_exclude_regex: Option<Regex>,
+
+    /// Write parentheses in captions as `\(` and `\)`
+    #[clap(long, value_parser, help_heading = "CAPTION", global = true)]
+    pub caption_escape: bool,
+
+    /// Keep underscores in captions instead of replacing them with spaces
+    #[clap(long, value_parser, help_heading = "CAPTION", global = true)]
+    pub caption_keep_underscores: bool,
+
+    /// Count the tags, ratings and extensions of every saved post.
+    ///
+    /// The report is printed at the end of the run and saved to `tag_stats.json` and `tag_stats.csv` inside the
+    /// output dir, or next to the output file when saving into an archive.
+    #[clap(long, value_parser, help_heading = "STATS", global = true)]
+    pub tag_stats: bool,
+
+    /// Number of tags of each type printed in the report, also used to count how often the most frequent tags
+    /// appear together
+    #[clap(
+        long,
+        value_name = "NUMBER",
+        default_value_t = 20,
+        help_heading = "STATS",
+        global = true
+    )]
+    pub stats_top: usize,
+
+    /// Downscale images so neither side is bigger than this, keeping their aspect ratio
+    #[cfg(feature = "image")]
+    #[clap(long, value_name = "PIXELS", help_heading = "IMAGE", global = true)]
+    pub max_side: Option<u32>,
+
+    /// Downscale images to at most this many pixels, keeping their aspect ratio
+    #[cfg(feature = "image")]
+    #[clap(long, value_name = "PIXELS", help_heading = "IMAGE", global = true)]
+    pub max_area: Option<u64>,
+
+    /// Resize images into aspect ratio buckets of at most this many pixels, like 1048576 for 1024x1024
+    #[cfg(feature = "image")]
+    #[clap(long, value_name = "PIXELS", help_heading = "IMAGE", global = true)]
+    pub bucket_area: Option<u64>,
+
+    /// Width and height of every bucket are multiples of this
+    #[cfg(feature = "image")]
+    #[clap(
+        long,
+        value_name = "PIXELS",
+        default_value_t = 64,
+        requires = "bucket_area",
+        help_heading = "IMAGE",
+        global = true
+    )]
+    pub bucket_step: u32,
+
+    /// How images are fitted into their bucket
+    #[cfg(feature = "image")]
+    #[clap(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t = Fit::Crop,
+        help_heading = "IMAGE",
+        global = true
+    )]
+    pub bucket_fit: Fit,
+
+    /// Re-encode images into this format
+    #[cfg(feature = "image")]
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help_heading = "IMAGE",
+        global = true
+    )]
+    pub image_format: Option<OutputFormat>,
+
+    /// Only re-encode images served in these formats, separated by commas, like `png` to convert lossless images
+    #[cfg(feature = "image")]
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMATS",
+        value_delimiter = ',',
+        requires = "image_format",
+        help_heading = "IMAGE",
+        global = true
+    )]
+    pub image_format_from: Vec<OutputFormat>,
+
+    /// Quality of re-encoded JPEG and AVIF images
+    #[cfg(feature = "image")]
+    #[clap(
+        long,
+        value_name = "1-100",
+        default_value_t = DEFAULT_QUALITY,
+        value_parser = clap::value_parser!(u8).range(1..=100),
+        help_heading = "IMAGE",
+        global = true
+    )]
+    pub image_quality: u8,
+
+    /// Convert Danbooru Ugoira zips into animations of this format, with the delay of every frame
+    #[cfg(feature = "image")]
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help_heading = "IMAGE",
+        global = true
+    )]
+    pub ugoira: Option<UgoiraFormat>,
+
+    /// Keep the original of every processed image or Ugoira inside an `originals` directory
+    #[cfg(feature = "image")]
+    #[clap(long, value_parser, help_heading = "IMAGE", global = true)]
+    pub keep_originals: bool,
+
+    /// Always overwrite output.
+    ///
+    /// Existing cbz and zip files are replaced instead of appended to, unless resuming with `--resume`
     #[clap(
         short = 'y',
         value_parser,
@@ -115,9 +576,276 @@ pub struct Cli {
         global = true
     )]
     pub overwrite: bool,
+
+    /// Don't download anything, write a list of the found posts instead.
+    ///
+    /// The list will be saved inside the output dir, unless `--export-file` is set.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub export: Option<ExportFormat>,
+
+    /// Where to save the list written by `--export`
+    #[clap(
+        long,
+        value_name = "FILE",
+        requires = "export",
+        help_heading = "SAVE",
+        global = true
+    )]
+    pub export_file: Option<PathBuf>,
+
+    /// How to report progress.
+    ///
+    /// `json` writes one JSON object per line for every event and a final summary to stdout, and never asks for confirmation.
+    #[clap(
+        long,
+        value_enum,
+        value_name = "MODE",
+        default_value_t = ProgressMode::Bars,
+        help_heading = "GENERAL",
+        global = true
+    )]
+    pub progress: ProgressMode,
+
+    /// Continue a download interrupted with Ctrl-C.
+    ///
+    /// Downloads the posts left by the previous run and continues the search from where it stopped.
+    /// Must be used with the same command, imageboard and output path.
+    #[clap(long, value_parser, help_heading = "DOWNLOAD", global = true)]
+    pub resume: bool,
+
+    /// Write the JSON progress to this file descriptor instead of stdout
+    #[cfg(unix)]
+    #[clap(long, value_name = "FD", help_heading = "GENERAL", global = true)]
+    pub progress_fd: Option<i32>,
 }
 
 impl Cli {
+    /// Returns `true` if posts should be saved inside a cbz or zip file.
+    pub fn save_as_cbz(&self) -> bool {
+        #[cfg(feature = "cbz")]
+        return self.cbz || self.archive == Some(ArchiveFormat::Zip);
+        #[cfg(not(feature = "cbz"))]
+        false
+    }
+
+    /// Returns the extension of the archive posts should be saved into, or `None` to save them into a folder.
+    pub fn archive_extension(&self) -> Option<&'static str> {
+        #[cfg(feature = "cbz")]
+        if self.cbz {
+            return Some("cbz");
+        }
+
+        #[cfg(any(feature = "cbz", feature = "tar"))]
+        return self.archive.map(ArchiveFormat::extension);
+        #[cfg(not(any(feature = "cbz", feature = "tar")))]
+        None
+    }
+
+    /// Whether an existing cbz or zip file is appended to or replaced with `--overwrite`.
+    ///
+    /// Resumed downloads always append to the archive saved by the interrupted run.
+    #[cfg(feature = "cbz")]
+    pub const fn existing_archive(&self, resumed: bool) -> ExistingArchive {
+        if self.overwrite && !resumed {
+            ExistingArchive::Replace
+        } else {
+            ExistingArchive::Append
+        }
+    }
+
+    /// How files are compressed inside cbz and zip files, as set with `--compression` and `--compression-level`.
+    #[cfg(feature = "cbz")]
+    pub fn zip_compression(&self) -> ZipCompression {
+        let default = if self.cbz {
+            CompressionMethod::Stored
+        } else {
+            CompressionMethod::Deflated
+        };
+
+        match self.compression.unwrap_or(default) {
+            CompressionMethod::Stored => ZipCompression::Stored,
+            CompressionMethod::Deflated => ZipCompression::Deflated {
+                level: self.compression_level.map(|level| i64::from(level.min(9))),
+            },
+        }
+    }
+
+    /// Creates the tar file at `path` if one was selected with `--archive`.
+    #[cfg(feature = "tar")]
+    pub async fn tar_sink(&self, path: &Path) -> Result<Option<TarSink>, CliError> {
+        let compression = match self.archive {
+            Some(ArchiveFormat::Tar) => TarCompression::None,
+            Some(ArchiveFormat::TarZst) => TarCompression::Zstd {
+                level: self.compression_level.unwrap_or(3).max(1),
+            },
+            _ => return Ok(None),
+        };
+
+        let by_rating = !matches!(self.mode, Commands::Pool(_));
+        let sink = TarSink::create(path.to_path_buf(), by_rating, compression)
+            .await
+            .map_err(|source| CliError::TarSetupFail { source })?;
+        Ok(Some(sink))
+    }
+
+    /// How captions are built, as set with the `--caption-*` flags.
+    ///
+    /// Tags in `--caption-exclude` and `--caption-exclude-regex` are matched as named by the imageboard, so
+    /// spaces are replaced with underscores.
+    pub fn caption_config(&self) -> CaptionConfig {
+        let order = match self.caption_order {
+            CaptionOrder::AsIs => TagOrder::AsIs,
+            CaptionOrder::Alphabetical => TagOrder::Alphabetical,
+            CaptionOrder::Shuffle => TagOrder::Shuffle {
+                keep_first: self.caption_keep_first,
+            },
+        };
+
+        CaptionConfig {
+            tag_types: self
+                .caption_types
+                .iter()
+                .map(|tag_type| tag_type.0)
+                .collect(),
+            trigger: self.caption_trigger.clone(),
+            rating_tag: self.caption_rating.clone(),
+            order,
+            max_tags: self.caption_max_tags,
+            exclude: self
+                .caption_exclude
+                .iter()
+                .map(|tag| tag.trim().replace(' ', "_"))
+                .collect(),
+            exclude_pattern: self.caption_exclude_regex.clone(),
+            escape_parentheses: self.caption_escape,
+            keep_underscores: self.caption_keep_underscores,
+        }
+    }
+
+    /// Creates the image folder dataset in `dir` if one was selected with `--dataset`.
+    pub async fn image_folder_sink(&self, dir: &Path) -> Result<Option<ImageFolderSink>, CliError> {
+        if self.dataset != Some(DatasetFormat::Imagefolder) {
+            return Ok(None);
+        }
+
+        let sink = ImageFolderSink::create(dir.to_path_buf(), self.name_type())
+            .await
+            .map_err(|source| CliError::DatasetSetupFail { source })?;
+        Ok(Some(sink.caption(self.caption_config())))
+    }
+
+    /// Creates the WebDataset shards in `dir` if they were selected with `--dataset`.
+    #[cfg(feature = "tar")]
+    pub async fn webdataset_sink(&self, dir: &Path) -> Result<Option<WebDatasetSink>, CliError> {
+        if self.dataset != Some(DatasetFormat::Webdataset) {
+            return Ok(None);
+        }
+
+        let sink = WebDatasetSink::create(dir.to_path_buf(), self.shard_size)
+            .await
+            .map_err(|source| CliError::DatasetSetupFail { source })?;
+        Ok(Some(sink.caption(self.caption_config())))
+    }
+
+    /// How images are processed, as set with the flags of the `IMAGE` section, or `None` if they are saved as served.
+    #[cfg(feature = "image")]
+    pub fn image_processor(&self) -> Option<ImageProcessor> {
+        if self.max_side.is_none()
+            && self.max_area.is_none()
+            && self.bucket_area.is_none()
+            && self.image_format.is_none()
+            && self.ugoira.is_none()
+        {
+            return None;
+        }
+
+        Some(ImageProcessor {
+            max_side: self.max_side,
+            max_area: self.max_area,
+            buckets: self.bucket_area.map(|area| Buckets {
+                area,
+                step: self.bucket_step,
+            }),
+            fit: self.bucket_fit,
+            format: self.image_format,
+            convert_from: self.image_format_from.clone(),
+            quality: Some(self.image_quality),
+            ugoira: self.ugoira,
+            keep_original: self.keep_originals,
+        })
+    }
+
+    /// Limits of each volume set with `--cbz-max-posts` and `--cbz-max-size`.
+    #[cfg(feature = "cbz")]
+    pub const fn cbz_volumes(&self) -> VolumeLimits {
+        VolumeLimits {
+            max_posts: self.cbz_max_posts,
+            max_bytes: self.cbz_max_size,
+        }
+    }
+
+    /// Returns `true` if posts should be uploaded into object storage.
+    pub const fn upload_to_s3(&self) -> bool {
+        #[cfg(feature = "s3")]
+        return self.s3_bucket.is_some();
+        #[cfg(not(feature = "s3"))]
+        false
+    }
+
+    /// Connects to the bucket selected with `--s3-bucket`, if any.
+    #[cfg(feature = "s3")]
+    pub async fn s3_sink(&self) -> Result<Option<S3Sink>, CliError> {
+        let Some(bucket) = &self.s3_bucket else {
+            return Ok(None);
+        };
+
+        let endpoint = self
+            .s3_endpoint
+            .clone()
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", self.s3_region));
+
+        let credential = |value: &Option<String>, flag: &str, var: &str| {
+            value.clone().ok_or_else(|| CliError::S3SetupFail {
+                source: QueueError::S3ConfigError {
+                    message: format!("no credentials, set {flag} or {var}"),
+                },
+            })
+        };
+
+        let config = S3Config {
+            endpoint,
+            bucket: bucket.clone(),
+            prefix: self.s3_prefix.clone(),
+            region: self.s3_region.clone(),
+            access_key: credential(&self.s3_access_key, "--s3-access-key", "AWS_ACCESS_KEY_ID")?,
+            secret_key: credential(
+                &self.s3_secret_key,
+                "--s3-secret-key",
+                "AWS_SECRET_ACCESS_KEY",
+            )?,
+        };
+
+        let sink = S3Sink::connect(config)
+            .await
+            .map_err(|source| CliError::S3SetupFail { source })?;
+        Ok(Some(sink))
+    }
+
+    /// Returns the size of the channel between the extractor and the queue, or `None` if it's unbounded.
+    pub const fn post_channel_size(&self) -> Option<usize> {
+        if self.queue_size == 0 {
+            None
+        } else {
+            Some(self.queue_size)
+        }
+    }
+
     pub const fn name_type(&self) -> NameType {
         if self.save_file_as_id {
             NameType::ID
@@ -128,40 +856,149 @@ impl Cli {
 
     pub fn get_extension(&self) -> Option<Extension> {
         match &self.mode {
-            Commands::Search(args) => {
-                if let Some(ext) = &args.force_extension {
-                    return Some(Extension::guess_format(ext));
-                }
+            Commands::Search(args) => args.filters.extension(),
+            Commands::Pool(args) => args.filters.extension(),
+            Commands::Replay(args) => args.filters.extension(),
+            Commands::Post(_) | Commands::Import(_) | Commands::Stats(_) => None,
+        }
+    }
+
+    /// Returns the start page selected with `--start-page`, in the modes that support it.
+    pub const fn start_page(&self) -> Option<u16> {
+        match &self.mode {
+            Commands::Search(args) => args.start_page,
+            Commands::Pool(args) => args.start_page,
+            Commands::Post(_) | Commands::Import(_) | Commands::Replay(_) | Commands::Stats(_) => {
+                None
+            }
+        }
+    }
+
+    /// Returns the post limit selected with `--limit`, in the modes that support it.
+    pub const fn limit(&self) -> Option<u16> {
+        match &self.mode {
+            Commands::Search(args) => args.limit,
+            Commands::Pool(args) => args.limit,
+            Commands::Post(_) | Commands::Import(_) | Commands::Replay(_) | Commands::Stats(_) => {
+                None
+            }
+        }
+    }
+
+    /// Identifies what is being downloaded, so a job state is only resumed by the same command.
+    pub fn job_query(&self) -> Vec<String> {
+        match &self.mode {
+            Commands::Search(args) => std::iter::once(String::from("search"))
+                .chain(args.tags.iter().cloned())
+                .collect(),
+            Commands::Pool(args) => vec![
+                String::from("pool"),
+                args.pool_id.to_string(),
+                args.latest_first.to_string(),
+            ],
+            Commands::Post(args) => match &args.post_file {
+                Some(path) => vec![String::from("post"), path.display().to_string()],
+                None => std::iter::once(String::from("post"))
+                    .chain(args.posts.iter().map(ToString::to_string))
+                    .collect(),
+            },
+            Commands::Import(args) => {
+                vec![String::from("import"), args.post_list.display().to_string()]
             }
-            Commands::Pool(args) => {
-                if let Some(ext) = &args.force_extension {
-                    return Some(Extension::guess_format(ext));
-                }
+            Commands::Replay(args) => {
+                vec![String::from("replay"), args.path.display().to_string()]
+            }
+            Commands::Stats(args) => {
+                vec![String::from("stats"), args.path.display().to_string()]
             }
-            Commands::Post(_) => {}
         }
-        None
+    }
+
+    /// Returns `true` if an interrupted download can be resumed with this output.
+    ///
+    /// Folders and zip-based archives are appended to, while tar archives are always replaced.
+    pub fn supports_resume(&self) -> bool {
+        self.archive_extension().is_none() || self.save_as_cbz()
+    }
+
+    /// Returns where the state of an interrupted download should be saved. See [`job::job_state_path`].
+    pub fn job_state_path(&self, save_path: &Path) -> PathBuf {
+        job::job_state_path(save_path, self.archive_extension().is_some())
+    }
+
+    /// Loads the job state saved by an interrupted run and checks that it belongs to the same download.
+    pub async fn load_job_state(&self, path: &Path) -> Result<JobState, CliError> {
+        if !self.supports_resume() {
+            return Err(CliError::ResumeArchiveUnsupported);
+        }
+
+        job::load_job_state(path, &self.imageboard.name, &self.job_query()).await
+    }
+
+    /// Creates the JSON progress handler, writing to `--progress-fd` if set.
+    pub fn json_progress_handler(&self) -> JsonProgressHandler {
+        #[cfg(unix)]
+        if let Some(fd) = self.progress_fd {
+            return JsonProgressHandler::from_fd(fd);
+        }
+
+        JsonProgressHandler::stdout()
+    }
+
+    /// Returns the path of the exported list and the directory the listed posts should be downloaded into.
+    pub fn export_paths(&self, format: ExportFormat) -> Result<(PathBuf, PathBuf), std::io::Error> {
+        let output_dir = if let Some(output_path) = &self.output {
+            std::path::absolute(output_path)?
+        } else {
+            std::env::current_dir()?
+        };
+
+        let list_path = self
+            .export_file
+            .clone()
+            .unwrap_or_else(|| output_dir.join(format.default_file_name()));
+
+        Ok((list_path, output_dir))
+    }
+
+    /// Returns where the report of `--tag-stats` should be saved. See [`tag_stats_path`](commands::stats::tag_stats_path).
+    pub fn tag_stats_path(&self, save_path: &Path) -> PathBuf {
+        commands::stats::tag_stats_path(save_path, self.archive_extension().is_some())
+    }
+
+    /// Returns where the list of posts that failed to download should be saved.
+    ///
+    /// The list is saved inside the output dir, or next to the output file when saving into an archive.
+    pub fn failed_list_path(&self, save_path: &Path) -> PathBuf {
+        if self.archive_extension().is_some() {
+            return save_path.with_extension("failed.jsonl");
+        }
+
+        save_path.join("failed.jsonl")
     }
 
     pub fn generate_save_path(&self) -> Result<PathBuf, std::io::Error> {
-        #[cfg(feature = "cbz")]
-        if self.cbz {
-            // CBZ mode is selected by the user and the feature is enabled.
-            // Determine the base path for the CBZ file name.
+        #[cfg(any(feature = "cbz", feature = "tar"))]
+        if let Some(extension) = self.archive_extension() {
+            // An archive is selected by the user and its feature is enabled.
+            // Determine the base path for the archive file name.
             let base_name_path = if let Some(output_path) = &self.output {
                 output_path.clone()
             } else {
                 // No -o provided, use a default name in the current directory.
                 std::env::current_dir()?.join("imageboard_download")
             };
-            // generate_output_path_precise is only compiled if 'cbz' feature is on.
-            // It will append ".cbz" to the base_name_path.
-            return Ok(generate_output_path_precise(&base_name_path, true));
+            // generate_output_path_precise is only compiled if an archive feature is on.
+            // It will append the extension of the archive to the base_name_path.
+            return Ok(generate_output_path_precise(
+                &base_name_path,
+                Some(extension),
+            ));
         }
 
         // This block is reached if:
-        // 1. The 'cbz' feature is disabled (the #[cfg(feature = "cbz")] block above is removed).
-        // 2. The 'cbz' feature is enabled, but self.cbz is false (user didn't pass --cbz).
+        // 1. No archive feature is enabled (the #[cfg] block above is removed).
+        // 2. An archive feature is enabled, but the user didn't pass --cbz or --archive.
         // In both cases, we are in folder download mode.
         if let Some(output_path) = &self.output {
             Ok(output_path.clone())
@@ -171,13 +1008,13 @@ impl Cli {
     }
 }
 
-#[cfg(feature = "cbz")]
+#[cfg(any(feature = "cbz", feature = "tar"))]
 /// This function creates the destination directory without creating additional ones related to
 /// the selected imageboard or tags used.
 #[inline]
-pub fn generate_output_path_precise(main_path: &Path, cbz_mode: bool) -> PathBuf {
-    if cbz_mode {
-        return PathBuf::from(&format!("{}.cbz", main_path.display()));
+pub fn generate_output_path_precise(main_path: &Path, extension: Option<&str>) -> PathBuf {
+    if let Some(extension) = extension {
+        return PathBuf::from(&format!("{}.{extension}", main_path.display()));
     }
     main_path.to_path_buf()
 }
@@ -1,5 +1,6 @@
 use std::io;
 
+use ibdl_core::error::QueueError;
 use ibdl_extractors::error::ExtractorError;
 use thiserror::Error;
 
@@ -36,6 +37,9 @@ pub enum CliError {
     #[error("This operation is currently unsupported for this imageboard")]
     ExtractorUnsupportedMode,
 
+    #[error("Failed to set up the extractor: {source}")]
+    ExtractorSetupFail { source: ExtractorError },
+
     #[error("Failed to read server config")]
     ServerConfigSerializeFail,
 
@@ -44,4 +48,42 @@ pub enum CliError {
 
     #[error("No posts given")]
     NoPostsInInput,
+
+    #[error("Failed to map saved response {file}: {source}")]
+    ReplayMapFail {
+        file: String,
+        source: ExtractorError,
+    },
+
+    #[error("No interrupted download to resume in {file}")]
+    JobStateNotFound { file: String },
+
+    #[error("The download saved in {file} was started with another command or imageboard")]
+    JobStateMismatch { file: String },
+
+    #[error("Failed to read job state: {source}")]
+    JobStateReadFail { source: QueueError },
+
+    #[error("Resuming downloads is not supported when saving into a tar archive")]
+    ResumeArchiveUnsupported,
+
+    #[cfg(feature = "tar")]
+    #[error("Failed to create tar archive: {source}")]
+    TarSetupFail { source: QueueError },
+
+    #[error("Failed to build tag statistics: {source}")]
+    TagStatsFail { source: QueueError },
+
+    #[error("Failed to create dataset: {source}")]
+    DatasetSetupFail { source: QueueError },
+
+    #[cfg(feature = "s3")]
+    #[error("Failed to connect to object storage: {source}")]
+    S3SetupFail { source: QueueError },
+
+    #[error("Failed to read post list: {source}")]
+    PostListReadFail {
+        #[from]
+        source: QueueError,
+    },
 }
@@ -1,7 +1,10 @@
 use std::ops::Deref;
 
 use clap::ValueEnum;
-use ibdl_common::{ImageBoards, post::rating::Rating};
+use ibdl_common::{
+    ImageBoards,
+    post::{rating::Rating, tags::TagType},
+};
 
 pub mod cli;
 pub mod error;
@@ -41,6 +44,38 @@ impl ValueEnum for RatingArg {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+#[repr(transparent)]
+pub struct TagTypeArg(pub TagType);
+
+impl ValueEnum for TagTypeArg {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Self(TagType::Author),
+            Self(TagType::Copyright),
+            Self(TagType::Character),
+            Self(TagType::Species),
+            Self(TagType::General),
+            Self(TagType::Lore),
+            Self(TagType::Meta),
+            Self(TagType::Any),
+        ]
+    }
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        let name = match self.0 {
+            TagType::Author => "author",
+            TagType::Copyright => "copyright",
+            TagType::Character => "character",
+            TagType::Species => "species",
+            TagType::General => "general",
+            TagType::Lore => "lore",
+            TagType::Meta => "meta",
+            TagType::Any => "any",
+        };
+        Some(clap::builder::PossibleValue::new(name))
+    }
+}
+
 impl Deref for RatingArg {
     type Target = Rating;
 
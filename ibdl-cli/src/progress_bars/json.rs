@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use ibdl_common::serde_json::{self, Value, json};
+use ibdl_core::{
+    events::{Event, EventListener, ExtractorEvent, SkipReason},
+    report::SessionReport,
+};
+
+/// Minimum amount of bytes between two `download_progress` events of the same file.
+const PROGRESS_STEP: u64 = 512 * 1024;
+
+/// Writes every event as a single-line JSON object, for programs driving the CLI.
+///
+/// This struct implements the `EventListener` trait from `ibdl_core`.
+pub struct JsonProgressHandler {
+    output: Mutex<Box<dyn Write + Send>>,
+    // Bytes reported in the last `download_progress` event of every running download
+    last_progress: Mutex<HashMap<u64, u64>>,
+}
+
+impl fmt::Debug for JsonProgressHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonProgressHandler").finish_non_exhaustive()
+    }
+}
+
+impl JsonProgressHandler {
+    pub fn new(output: Box<dyn Write + Send>) -> Self {
+        Self {
+            output: Mutex::new(output),
+            last_progress: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Writes to stdout.
+    pub fn stdout() -> Self {
+        Self::new(Box::new(io::stdout()))
+    }
+
+    /// Writes to an already open file descriptor, like one end of a pipe created by the parent process.
+    #[cfg(unix)]
+    pub fn from_fd(fd: i32) -> Self {
+        use std::{fs::File, os::fd::FromRawFd};
+
+        // SAFETY: The descriptor is handed to us by the parent process on the command line
+        // and is not used anywhere else in this program.
+        let file = unsafe { File::from_raw_fd(fd) };
+        Self::new(Box::new(file))
+    }
+
+    fn emit(&self, line: &Value) {
+        let mut output = self.output.lock().unwrap();
+        // A closed pipe must not abort the downloads, so write errors are ignored.
+        let _ = writeln!(output, "{line}").and_then(|()| output.flush());
+    }
+
+    /// Writes the final summary of a download run.
+    ///
+    /// `job_state` is where the interrupted download was saved, if it can be resumed.
+    pub fn summary(&self, session: &SessionReport, failed_list: &Path, job_state: Option<&Path>) {
+        let report = &session.download;
+        let failures: serde_json::Map<String, Value> = report
+            .failures_by_reason()
+            .into_iter()
+            .map(|(reason, posts)| {
+                let ids: Vec<u64> = posts.iter().map(|f| f.post.id).collect();
+                (reason, json!(ids))
+            })
+            .collect();
+
+        self.emit(&json!({
+            "event": "summary",
+            "downloaded": report.downloaded,
+            "skipped": session.skipped,
+            "blacklisted": session.filtered,
+            "failed": report.failed.len(),
+            "failures": failures,
+            "failed_list": (!report.failed.is_empty()).then(|| failed_list.display().to_string()),
+            "cancelled": report.cancelled,
+            "pending": report.pending.len(),
+            "bytes": session.bytes,
+            "duration_ms": u64::try_from(session.duration.as_millis()).unwrap_or(u64::MAX),
+            "job_state": job_state.map(|path| path.display().to_string()),
+        }));
+    }
+
+    /// Writes the final summary of an export run.
+    pub fn export_summary(&self, exported: u64, list_path: &Path) {
+        self.emit(&json!({
+            "event": "summary",
+            "exported": exported,
+            "list": list_path.display().to_string(),
+        }));
+    }
+}
+
+impl EventListener for JsonProgressHandler {
+    fn on_event(&self, event: Event) {
+        let line = match event {
+            Event::Extractor(ExtractorEvent::PageFetched { page, posts }) => {
+                json!({ "event": "page_fetched", "page": page, "posts": posts })
+            }
+            Event::Extractor(ExtractorEvent::PostDiscovered { post_id }) => {
+                json!({ "event": "post_discovered", "post_id": post_id })
+            }
+            Event::Extractor(ExtractorEvent::PostFiltered { post_id, rule }) => {
+                json!({ "event": "post_filtered", "post_id": post_id, "rule": rule.to_string() })
+            }
+            Event::Extractor(ExtractorEvent::Cancelled) => json!({ "event": "search_cancelled" }),
+            Event::PostsQueued(delta) => json!({ "event": "total", "delta": delta }),
+            Event::PostReceived { post_id } => {
+                json!({ "event": "post_received", "post_id": post_id })
+            }
+            Event::DownloadStarted {
+                post_id,
+                file_name,
+                size,
+            } => {
+                self.last_progress.lock().unwrap().insert(post_id, 0);
+                json!({
+                    "event": "download_started",
+                    "post_id": post_id,
+                    "file": file_name,
+                    "size": size,
+                })
+            }
+            Event::DownloadProgress {
+                post_id,
+                downloaded,
+            } => {
+                let mut last_progress = self.last_progress.lock().unwrap();
+                let last = last_progress.entry(post_id).or_default();
+                if downloaded - *last < PROGRESS_STEP {
+                    return;
+                }
+                *last = downloaded;
+                drop(last_progress);
+
+                json!({ "event": "download_progress", "post_id": post_id, "downloaded": downloaded })
+            }
+            Event::DownloadFinished { post_id, file_name } => {
+                self.last_progress.lock().unwrap().remove(&post_id);
+                json!({ "event": "download_finished", "post_id": post_id, "file": file_name })
+            }
+            Event::DownloadRetried {
+                post_id,
+                file_name,
+                error,
+            } => {
+                self.last_progress.lock().unwrap().remove(&post_id);
+                json!({
+                    "event": "download_retried",
+                    "post_id": post_id,
+                    "file": file_name,
+                    "kind": error.kind(),
+                    "message": error.to_string(),
+                })
+            }
+            Event::DownloadFailed {
+                post_id,
+                file_name,
+                error,
+            } => {
+                self.last_progress.lock().unwrap().remove(&post_id);
+                json!({
+                    "event": "download_failed",
+                    "post_id": post_id,
+                    "file": file_name,
+                    "kind": error.kind(),
+                    "status": error.status(),
+                    "message": error.to_string(),
+                })
+            }
+            Event::FileSkipped {
+                post_id,
+                file_name,
+                reason,
+            } => {
+                let (reason, from) = match reason {
+                    SkipReason::AlreadyExists => ("already_exists", None),
+                    SkipReason::Renamed { from } => ("renamed", Some(from)),
+                };
+                json!({
+                    "event": "file_skipped",
+                    "post_id": post_id,
+                    "file": file_name,
+                    "reason": reason,
+                    "from": from,
+                })
+            }
+            Event::FileReplaced {
+                post_id,
+                file_name,
+                removed,
+            } => json!({
+                "event": "file_replaced",
+                "post_id": post_id,
+                "file": file_name,
+                "removed": removed,
+            }),
+            Event::Finished => json!({ "event": "finished" }),
+        };
+
+        self.emit(&line);
+    }
+}
@@ -1,13 +1,28 @@
+use clap::ValueEnum;
 use ibdl_common::ImageBoards;
-// Import the progress traits from ibdl_core
-use ibdl_core::progress::{DownloadProgressUpdater, ProgressListener};
+// Import the event traits from ibdl_core
+use ibdl_core::events::{Event, EventListener};
 use indicatif::{
     HumanBytes, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressState, ProgressStyle,
 };
-use std::{fmt::Write, time::Duration};
+use std::{collections::HashMap, fmt::Write, sync::Mutex, time::Duration};
+
+mod json;
+
+pub use json::JsonProgressHandler;
 
 const PROGRESS_CHARS: &str = "━━";
 
+/// How the progress of a run is reported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ProgressMode {
+    /// Interactive progress bars
+    #[default]
+    Bars,
+    /// One JSON object per line for every event, for other programs to read
+    Json,
+}
+
 struct BarTemplates {
     pub main: &'static str,
     pub download: &'static str,
@@ -42,11 +57,12 @@ impl Default for BarTemplates {
 
 /// Handles CLI progress display using `indicatif`.
 ///
-/// This struct implements the `ProgressListener` trait from `ibdl_core`.
+/// This struct implements the `EventListener` trait from `ibdl_core`.
 #[derive(Debug)]
 pub struct IndicatifProgressHandler {
     main_bar: ProgressBar,
     multi_pb: MultiProgress,
+    download_bars: Mutex<HashMap<u64, ProgressBar>>,
     imageboard_theme: ImageBoards, // To select styles for download bars
 }
 
@@ -73,58 +89,14 @@ impl IndicatifProgressHandler {
         Self {
             main_bar: main,
             multi_pb: multi,
+            download_bars: Mutex::new(HashMap::new()),
             imageboard_theme: imageboard,
         }
     }
 }
 
-#[derive(Debug)]
-struct IndicatifDownloadProgressUpdater {
-    bar: ProgressBar,
-}
-
-impl DownloadProgressUpdater for IndicatifDownloadProgressUpdater {
-    fn set_progress(&self, bytes_downloaded: u64) {
-        self.bar.set_position(bytes_downloaded);
-    }
-
-    fn set_total_size(&self, total_size: u64) {
-        if self.bar.length().is_none() || self.bar.length() != Some(total_size) {
-            self.bar.set_length(total_size);
-        }
-    }
-
-    fn finish(&self) {
-        self.bar.finish_and_clear(); // Or just .finish() if you want it to remain
-    }
-}
-
-impl ProgressListener for IndicatifProgressHandler {
-    fn set_main_total(&self, total: u64) {
-        self.main_bar.set_length(total);
-    }
-
-    fn inc_main_total(&self, delta: u64) {
-        self.main_bar.inc_length(delta);
-    }
-
-    fn main_tick(&self) {
-        self.main_bar.inc(1);
-    }
-
-    fn main_inc_by(&self, delta: u64) {
-        self.main_bar.inc(delta);
-    }
-
-    fn main_done(&self) {
-        self.main_bar.finish_with_message("All posts processed.");
-    }
-
-    fn add_download_task(
-        &self,
-        name: String,
-        total_size: Option<u64>,
-    ) -> Box<dyn DownloadProgressUpdater> {
+impl IndicatifProgressHandler {
+    fn add_download_bar(&self, post_id: u64, name: String, total_size: Option<u64>) {
         let template = BarTemplates::new(self.imageboard_theme);
         let style = download_progress_style(&template);
 
@@ -135,11 +107,68 @@ impl ProgressListener for IndicatifProgressHandler {
 
         let managed_pb = self.multi_pb.add(pb);
 
-        Box::new(IndicatifDownloadProgressUpdater { bar: managed_pb })
+        self.download_bars
+            .lock()
+            .unwrap()
+            .insert(post_id, managed_pb);
     }
 
-    fn log_skip_message(&self, file_name: &str, reason: &str) {
-        self.main_bar.println(format!("{} {}", file_name, reason));
+    fn finish_download_bar(&self, post_id: u64) {
+        if let Some(bar) = self.download_bars.lock().unwrap().remove(&post_id) {
+            bar.finish_and_clear(); // Or just .finish() if you want it to remain
+        }
+    }
+}
+
+impl EventListener for IndicatifProgressHandler {
+    fn on_event(&self, event: Event) {
+        match event {
+            Event::PostsQueued(delta) => self.main_bar.inc_length(delta),
+            Event::PostReceived { .. } => self.main_bar.inc(1),
+            Event::DownloadStarted {
+                post_id,
+                file_name,
+                size,
+            } => self.add_download_bar(post_id, file_name, size),
+            Event::DownloadProgress {
+                post_id,
+                downloaded,
+            } => {
+                if let Some(bar) = self.download_bars.lock().unwrap().get(&post_id) {
+                    bar.set_position(downloaded);
+                }
+            }
+            Event::DownloadFinished { post_id, .. } => self.finish_download_bar(post_id),
+            Event::DownloadRetried {
+                post_id,
+                file_name,
+                error,
+            } => {
+                self.finish_download_bar(post_id);
+                self.main_bar
+                    .println(format!("{} {}, downloading it again", file_name, error));
+            }
+            Event::DownloadFailed {
+                post_id,
+                file_name,
+                error,
+            } => {
+                self.finish_download_bar(post_id);
+                self.main_bar
+                    .println(format!("{} failed: {}", file_name, error));
+            }
+            Event::FileSkipped {
+                file_name, reason, ..
+            } => self.main_bar.println(format!("{} {}", file_name, reason)),
+            Event::FileReplaced {
+                file_name, removed, ..
+            } => self.main_bar.println(format!(
+                "{} removed existing file {} (MD5 mismatch), will redownload",
+                file_name, removed
+            )),
+            Event::Finished => self.main_bar.finish_with_message("All posts processed."),
+            Event::Extractor(_) => {}
+        }
     }
 }
 
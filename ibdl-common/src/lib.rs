@@ -15,6 +15,7 @@ pub use reqwest;
 pub use serde;
 pub use serde_json;
 pub use tokio;
+pub use tokio_util;
 
 use directories::ProjectDirs;
 
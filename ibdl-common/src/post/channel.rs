@@ -0,0 +1,99 @@
+//! Channel used to send posts from an extractor to the download queue.
+//!
+//! A bounded channel makes the extractor wait whenever the queue falls behind, so a fast API and a slow disk
+//! don't end up with thousands of posts waiting in memory. The unbounded variant is still available for
+//! callers that want the extractor to run ahead of the downloads.
+use std::task::{Context, Poll};
+
+use tokio::sync::mpsc::{
+    channel, error::SendError, unbounded_channel, Receiver, Sender, UnboundedReceiver,
+    UnboundedSender,
+};
+
+use super::Post;
+
+/// Default number of posts that can wait in a bounded channel before the extractor stops fetching pages.
+pub const DEFAULT_CHANNEL_SIZE: usize = 256;
+
+/// Sending half of a post channel.
+#[derive(Debug, Clone)]
+pub enum PostSender {
+    Bounded(Sender<Post>),
+    Unbounded(UnboundedSender<Post>),
+}
+
+impl PostSender {
+    /// Sends a post, waiting for free space if the channel is bounded and full.
+    pub async fn send(&self, post: Post) -> Result<(), SendError<Post>> {
+        match self {
+            Self::Bounded(sender) => sender.send(post).await,
+            Self::Unbounded(sender) => sender.send(post),
+        }
+    }
+}
+
+impl From<Sender<Post>> for PostSender {
+    fn from(sender: Sender<Post>) -> Self {
+        Self::Bounded(sender)
+    }
+}
+
+impl From<UnboundedSender<Post>> for PostSender {
+    fn from(sender: UnboundedSender<Post>) -> Self {
+        Self::Unbounded(sender)
+    }
+}
+
+/// Receiving half of a post channel.
+#[derive(Debug)]
+pub enum PostReceiver {
+    Bounded(Receiver<Post>),
+    Unbounded(UnboundedReceiver<Post>),
+}
+
+impl PostReceiver {
+    /// Receives the next post, or `None` once all senders are dropped and the channel is empty.
+    pub async fn recv(&mut self) -> Option<Post> {
+        match self {
+            Self::Bounded(receiver) => receiver.recv().await,
+            Self::Unbounded(receiver) => receiver.recv().await,
+        }
+    }
+
+    /// Polls for the next post. Useful to turn the receiver into a `Stream`.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Option<Post>> {
+        match self {
+            Self::Bounded(receiver) => receiver.poll_recv(cx),
+            Self::Unbounded(receiver) => receiver.poll_recv(cx),
+        }
+    }
+}
+
+impl From<Receiver<Post>> for PostReceiver {
+    fn from(receiver: Receiver<Post>) -> Self {
+        Self::Bounded(receiver)
+    }
+}
+
+impl From<UnboundedReceiver<Post>> for PostReceiver {
+    fn from(receiver: UnboundedReceiver<Post>) -> Self {
+        Self::Unbounded(receiver)
+    }
+}
+
+/// Creates a post channel holding at most `size` posts, or an unbounded one if `size` is `None`.
+///
+/// # Panics
+/// Panics if `size` is `Some(0)`.
+pub fn post_channel(size: Option<usize>) -> (PostSender, PostReceiver) {
+    match size {
+        Some(size) => {
+            let (sender, receiver) = channel(size);
+            (sender.into(), receiver.into())
+        }
+        None => {
+            let (sender, receiver) = unbounded_channel();
+            (sender.into(), receiver.into())
+        }
+    }
+}
@@ -22,12 +22,18 @@ pub enum PostError {
         source: reqwest::Error,
     },
 
-    #[error("Post URL is valid but original file doesn't exist")]
-    RemoteFileNotFound,
+    #[error("Post URL is valid but original file doesn't exist (status {status})")]
+    RemoteFileNotFound { status: u16 },
+
+    #[error("File server failed to deliver the file (status {status})")]
+    RemoteServerError { status: u16 },
 
     #[error("Error while fetching chunk: {message}")]
     ChunkDownloadFail { message: String },
 
+    #[error("Downloaded file has MD5 hash {found} instead of {expected}")]
+    Md5Mismatch { expected: String, found: String },
+
     #[error("Failed to start thread for writing file to destination cbz: {msg}")]
     ZipThreadStartError { msg: String },
 
@@ -39,4 +45,59 @@ pub enum PostError {
 
     #[error("Post has an unknown extension: {message}")]
     UnknownExtension { message: String },
+
+    #[error("Failed to save file to storage: {message}")]
+    StorageFail { message: String },
+
+    #[error("Failed to process image: {message}")]
+    ProcessFail { message: String },
+
+    #[error("Download cancelled")]
+    Cancelled,
+}
+
+impl PostError {
+    /// Maps an unsuccessful HTTP status returned by the file server into an error.
+    pub fn from_status(status: reqwest::StatusCode) -> Self {
+        if status.is_server_error() {
+            Self::RemoteServerError {
+                status: status.as_u16(),
+            }
+        } else {
+            Self::RemoteFileNotFound {
+                status: status.as_u16(),
+            }
+        }
+    }
+
+    /// Short, stable name of the error, used to group failures in download reports.
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::CorrectFileExists => "file_exists",
+            Self::FileIOError { .. } => "io",
+            Self::ProgressBarPrintFail { .. } => "progress_bar",
+            Self::ConnectionFail { .. } => "connection",
+            Self::RemoteFileNotFound { .. } => "not_found",
+            Self::RemoteServerError { .. } => "server_error",
+            Self::ChunkDownloadFail { .. } => "interrupted",
+            Self::Md5Mismatch { .. } => "md5_mismatch",
+            Self::ZipThreadStartError { .. } | Self::ZipFileWriteError { .. } => "cbz_write",
+            Self::IntConversion(_) => "int_conversion",
+            Self::UnknownExtension { .. } => "unknown_extension",
+            Self::StorageFail { .. } => "storage",
+            Self::ProcessFail { .. } => "process",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// HTTP status returned by the file server, if the error was caused by one.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Self::RemoteFileNotFound { status } | Self::RemoteServerError { status } => {
+                Some(*status)
+            }
+            Self::ConnectionFail { source } => source.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
 }
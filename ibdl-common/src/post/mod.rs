@@ -13,6 +13,7 @@ use crate::ImageBoards;
 
 use self::{extension::Extension, rating::Rating, tags::Tag};
 
+pub mod channel;
 pub mod error;
 pub mod extension;
 pub mod rating;
@@ -85,6 +86,23 @@ pub struct Post {
     ///
     /// Used to exclude posts according to a blacklist
     pub tags: Vec<Tag>,
+    /// Score of the post given by the imageboard, if it provides one.
+    #[serde(default)]
+    pub score: Option<i64>,
+    /// Frames of a Pixiv Ugoira, in order, if the imageboard provides them.
+    ///
+    /// Only set for [`Extension::Ugoira`] posts, whose file is a zip archive with every frame.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ugoira_frames: Vec<UgoiraFrame>,
+}
+
+/// A frame of a Pixiv Ugoira.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UgoiraFrame {
+    /// Name of the frame inside the zip archive of the post.
+    pub file: String,
+    /// How long the frame is shown, in milliseconds.
+    pub delay: u32,
 }
 
 impl Debug for Post {
@@ -97,6 +115,8 @@ impl Debug for Post {
             .field("File Extension", &self.extension)
             .field("Rating", &self.rating)
             .field("Tag List", &self.tags)
+            .field("Score", &self.score)
+            .field("Ugoira Frames", &self.ugoira_frames)
             .finish()
     }
 }
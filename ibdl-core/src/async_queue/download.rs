@@ -0,0 +1,449 @@
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+
+use futures::{Stream, StreamExt};
+use ibdl_common::{
+    log::{debug, warn},
+    post::{NameType, Post, error::PostError},
+    reqwest::Client,
+    tokio::{select, task},
+    tokio_util::sync::CancellationToken,
+};
+
+use super::Queue;
+use crate::{
+    caption::CaptionConfig,
+    error::QueueError,
+    events::{Event, SharedEventListener},
+    report::FailedPost,
+    sink::{Existing, SinkItem, StorageSink},
+};
+
+/// Represents the outcome of a download attempt for a single post.
+#[derive(Debug)]
+enum DownloadTaskStatus {
+    Downloaded(Post), // Post was successfully downloaded
+    Skipped(Post),    // Post was skipped (e.g., already exists or renamed)
+}
+
+impl Queue {
+    /// Downloads every post received from `channel` into `sink`, then finishes the sink.
+    pub(crate) async fn download_to<S: StorageSink>(
+        &self,
+        sink: S,
+        channel: impl Stream<Item = Post>,
+        is_pool: bool,
+        downloaded_post_count: Arc<AtomicU64>,
+        failed_posts: Arc<Mutex<Vec<FailedPost>>>,
+        cancel: &CancellationToken,
+    ) -> Result<(), QueueError> {
+        let sink = Arc::new(sink);
+
+        channel
+            .map(|post_to_download| {
+                let nt_clone = self.name_type;
+                let annotate_clone = self.annotate;
+                let caption_clone = self.caption.clone();
+                let client_clone = self.client.clone();
+                let sink_clone = sink.clone();
+                let events_clone = self.events.clone();
+                let cancel_clone = cancel.clone();
+
+                // Increment main progress bar as soon as a post is received from the extractor channel
+                self.events.on_event(Event::PostReceived {
+                    post_id: post_to_download.id,
+                });
+
+                task::spawn(async move {
+                    let file_name = if is_pool {
+                        post_to_download.seq_file_name(6)
+                    } else {
+                        post_to_download.file_name(nt_clone)
+                    };
+
+                    match Self::save_post(
+                        client_clone,
+                        sink_clone.as_ref(),
+                        &post_to_download,
+                        &file_name,
+                        &events_clone,
+                        &cancel_clone,
+                    )
+                    .await
+                    {
+                        Ok(true) => {
+                            if annotate_clone {
+                                Self::write_caption(
+                                    sink_clone.as_ref(),
+                                    &post_to_download,
+                                    nt_clone,
+                                    &caption_clone,
+                                )
+                                .await;
+                            }
+                            Ok(DownloadTaskStatus::Downloaded(post_to_download))
+                        }
+                        Ok(false) => Ok(DownloadTaskStatus::Skipped(post_to_download)),
+                        Err(e) => Err(Self::download_failed(
+                            &events_clone,
+                            post_to_download,
+                            file_name,
+                            e,
+                        )),
+                    }
+                })
+            })
+            .buffer_unordered(self.sim_downloads as usize)
+            .for_each(
+                |task_join_result: Result<
+                    Result<DownloadTaskStatus, FailedPost>,
+                    task::JoinError,
+                >| {
+                    let downloaded_post_count_clone = downloaded_post_count.clone();
+                    let failed_posts_clone = failed_posts.clone();
+                    let tag_stats_clone = self.tag_stats.clone();
+
+                    async move {
+                        match task_join_result {
+                            Ok(Ok(DownloadTaskStatus::Downloaded(post))) => {
+                                downloaded_post_count_clone.fetch_add(1, Ordering::SeqCst);
+                                if let Some(stats) = tag_stats_clone {
+                                    stats.lock().unwrap().add_post(&post);
+                                }
+                            }
+                            Ok(Ok(DownloadTaskStatus::Skipped(skipped_post))) => {
+                                // Already reported with Event::FileSkipped.
+                                debug!("Post {} was skipped.", skipped_post.id);
+                                // The file is still part of the output.
+                                if let Some(stats) = tag_stats_clone {
+                                    stats.lock().unwrap().add_post(&skipped_post);
+                                }
+                            }
+                            Ok(Err(failed_post)) => {
+                                debug!(
+                                    "Failed to process post {}: {}",
+                                    failed_post.post.id, failed_post.message
+                                );
+                                failed_posts_clone.lock().unwrap().push(failed_post);
+                            }
+                            Err(join_error) => {
+                                // Task panicked or was cancelled
+                                debug!("Download task failed to execute: {}", join_error);
+                            }
+                        }
+                    }
+                },
+            )
+            .await;
+
+        // Every task is done, so this is the last reference to the sink.
+        let sink = Arc::try_unwrap(sink).map_err(|_| QueueError::SinkStillShared)?;
+        sink.finish().await
+    }
+
+    /// Checks if the post is already saved and downloads it otherwise.
+    ///
+    /// Returns `false` if the download was skipped.
+    async fn save_post<S: StorageSink>(
+        client: Client,
+        sink: &S,
+        post: &Post,
+        file_name: &str,
+        events: &SharedEventListener,
+        cancel: &CancellationToken,
+    ) -> Result<bool, PostError> {
+        match sink.exists(post, file_name).await? {
+            Existing::Missing => {}
+            Existing::Skip(reason) => {
+                events.on_event(Event::FileSkipped {
+                    post_id: post.id,
+                    file_name: file_name.to_string(),
+                    reason,
+                });
+                return Ok(false);
+            }
+            Existing::Replaced { removed } => {
+                events.on_event(Event::FileReplaced {
+                    post_id: post.id,
+                    file_name: file_name.to_string(),
+                    removed,
+                });
+            }
+        }
+
+        // Corrupted downloads are often caused by the connection, so they are worth a second try.
+        match Self::fetch(client.clone(), sink, post, file_name, events, cancel).await {
+            Err(error @ PostError::Md5Mismatch { .. }) => {
+                warn!("{error}: downloading {file_name} again");
+                events.on_event(Event::DownloadRetried {
+                    post_id: post.id,
+                    file_name: file_name.to_string(),
+                    error: Arc::new(error),
+                });
+                Self::fetch(client, sink, post, file_name, events, cancel).await?;
+            }
+            result => result?,
+        }
+        Ok(true)
+    }
+
+    /// Downloads the file of `post` into `sink`, checking it against the MD5 hash of the post as it's streamed.
+    ///
+    /// Files that don't match are discarded before they are saved.
+    async fn fetch<S: StorageSink>(
+        client: Client,
+        sink: &S,
+        post: &Post,
+        file_name: &str,
+        events: &SharedEventListener,
+        cancel: &CancellationToken,
+    ) -> Result<(), PostError> {
+        if cancel.is_cancelled() {
+            return Err(PostError::Cancelled);
+        }
+
+        debug!(
+            "Fetching {} for post ID {} into file {}",
+            &post.url, post.id, file_name
+        );
+
+        let res = client.get(&post.url).send().await?;
+
+        if res.status().is_client_error() || res.status().is_server_error() {
+            debug!(
+                "Image source for {} (file: {}) returned status {}. Skipping download.",
+                post.url,
+                file_name,
+                res.status().as_str()
+            );
+            return Err(PostError::from_status(res.status()));
+        }
+
+        events.on_event(Event::DownloadStarted {
+            post_id: post.id,
+            file_name: file_name.to_string(),
+            size: res.content_length(),
+        });
+        let mut downloaded_bytes = 0;
+        let mut hasher = md5::Context::new();
+
+        let mut stream = res.bytes_stream();
+        let mut item = sink.open(post, file_name).await?;
+
+        loop {
+            let chunk = select! {
+                chunk = stream.next() => chunk,
+                () = cancel.cancelled() => {
+                    debug!("Download of {} cancelled", file_name);
+                    item.abort().await?;
+                    return Err(PostError::Cancelled);
+                }
+            };
+
+            let Some(chunk) = chunk else {
+                break;
+            };
+
+            let written = match chunk {
+                Ok(chunk) => {
+                    downloaded_bytes += chunk.len() as u64;
+                    hasher.consume(&chunk);
+                    item.write(&chunk).await
+                }
+                Err(e) => Err(PostError::ChunkDownloadFail {
+                    message: e.to_string(),
+                }),
+            };
+
+            if let Err(error) = written {
+                item.abort().await?;
+                return Err(error);
+            }
+
+            events.on_event(Event::DownloadProgress {
+                post_id: post.id,
+                downloaded: downloaded_bytes,
+            });
+        }
+
+        let found = format!("{:x}", hasher.compute());
+        if !post.md5.is_empty() && !found.eq_ignore_ascii_case(&post.md5) {
+            item.abort().await?;
+            return Err(PostError::Md5Mismatch {
+                expected: post.md5.clone(),
+                found,
+            });
+        }
+
+        item.commit().await?;
+
+        events.on_event(Event::DownloadFinished {
+            post_id: post.id,
+            file_name: file_name.to_string(),
+        });
+        debug!("Finished downloading {} successfully.", file_name);
+        Ok(())
+    }
+
+    /// Saves the caption of the post next to its file. Failures are only logged.
+    async fn write_caption<S: StorageSink>(
+        sink: &S,
+        post: &Post,
+        name_type: NameType,
+        config: &CaptionConfig,
+    ) {
+        let caption = config.caption(post);
+        let caption_name = format!("{}.txt", post.name(name_type));
+
+        match sink.put(post, &caption_name, caption.into_bytes()).await {
+            Ok(()) => debug!("Wrote caption file for {}", post.file_name(name_type)),
+            Err(error) => debug!(
+                "Failed to write caption file for {}: {}",
+                post.file_name(name_type),
+                error
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibdl_common::tokio::fs::read;
+
+    use super::*;
+    use crate::{events::EventListener, sink::FolderSink, test};
+
+    const FILE: &[u8] = b"image";
+    const CORRUPTED: &[u8] = b"imagf";
+
+    /// Keeps the name of every event received, and the size of every download started.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        names: Mutex<Vec<String>>,
+        sizes: Mutex<Vec<Option<u64>>>,
+    }
+
+    impl EventListener for Recorder {
+        fn on_event(&self, event: Event) {
+            if let Event::DownloadStarted { size, .. } = &event {
+                self.sizes.lock().unwrap().push(*size);
+            }
+            let name = format!("{event:?}");
+            let name = name.split([' ', '{', '(']).next().unwrap_or_default();
+            self.names.lock().unwrap().push(name.to_string());
+        }
+    }
+
+    /// Downloads a post with the MD5 hash of [`FILE`] from a server answering with `bodies`.
+    async fn save(
+        bodies: Vec<&'static [u8]>,
+        md5: &str,
+    ) -> (Result<bool, PostError>, Vec<String>, Option<Vec<u8>>) {
+        let (result, recorder, saved) = save_from(test::serve(bodies).await, md5).await;
+        let events = recorder.names.lock().unwrap().clone();
+        (result, events, saved)
+    }
+
+    /// Downloads a post with the MD5 hash `md5` from `url`.
+    async fn save_from(
+        url: String,
+        md5: &str,
+    ) -> (Result<bool, PostError>, Arc<Recorder>, Option<Vec<u8>>) {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FolderSink::create(dir.path().to_path_buf(), NameType::ID)
+            .await
+            .unwrap();
+        let mut post = test::post(1, md5);
+        post.url = url;
+
+        let recorder = Arc::new(Recorder::default());
+        let events: SharedEventListener = recorder.clone();
+        let result = Queue::save_post(
+            Client::new(),
+            &sink,
+            &post,
+            "1.png",
+            &events,
+            &CancellationToken::new(),
+        )
+        .await;
+
+        (result, recorder, read(dir.path().join("1.png")).await.ok())
+    }
+
+    #[tokio::test]
+    async fn download_size_is_only_reported_when_known() {
+        let md5 = format!("{:x}", md5::compute(FILE));
+        let (result, recorder, _) = save_from(test::serve(vec![FILE]).await, &md5).await;
+        assert!(result.unwrap());
+        assert_eq!(*recorder.sizes.lock().unwrap(), [Some(FILE.len() as u64)]);
+
+        let (result, recorder, saved) =
+            save_from(test::serve_chunked(vec![FILE]).await, &md5).await;
+        assert!(result.unwrap());
+        assert_eq!(*recorder.sizes.lock().unwrap(), [None]);
+        assert_eq!(saved.as_deref(), Some(FILE));
+    }
+
+    #[tokio::test]
+    async fn corrupted_download_is_retried_once() {
+        let md5 = format!("{:x}", md5::compute(FILE));
+        let (result, events, saved) = save(vec![CORRUPTED, FILE], &md5).await;
+
+        assert!(result.unwrap());
+        assert_eq!(
+            events,
+            [
+                "DownloadStarted",
+                "DownloadProgress",
+                "DownloadRetried",
+                "DownloadStarted",
+                "DownloadProgress",
+                "DownloadFinished",
+            ]
+        );
+        assert_eq!(saved.as_deref(), Some(FILE));
+    }
+
+    #[tokio::test]
+    async fn corrupted_retry_fails_without_saving() {
+        let md5 = format!("{:x}", md5::compute(FILE));
+        let (result, events, saved) = save(vec![CORRUPTED, CORRUPTED, FILE], &md5).await;
+
+        let Err(PostError::Md5Mismatch { expected, found }) = result else {
+            panic!("expected a MD5 mismatch, got {result:?}");
+        };
+        assert_eq!(expected, md5);
+        assert_eq!(found, format!("{:x}", md5::compute(CORRUPTED)));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| *event == "DownloadRetried")
+                .count(),
+            1
+        );
+        assert!(!events.contains(&"DownloadFinished".to_string()));
+        assert_eq!(saved, None);
+    }
+
+    #[tokio::test]
+    async fn posts_without_md5_are_not_checked() {
+        let (result, events, saved) = save(vec![CORRUPTED], "").await;
+
+        assert!(result.unwrap());
+        assert!(!events.contains(&"DownloadRetried".to_string()));
+        assert_eq!(saved.as_deref(), Some(CORRUPTED));
+    }
+
+    #[tokio::test]
+    async fn missing_files_are_not_retried() {
+        let md5 = format!("{:x}", md5::compute(FILE));
+        let (result, events, saved) = save(Vec::new(), &md5).await;
+
+        assert!(matches!(result, Err(PostError::RemoteFileNotFound { .. })));
+        assert!(events.is_empty());
+        assert_eq!(saved, None);
+    }
+}
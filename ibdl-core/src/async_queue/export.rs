@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use ibdl_common::{
+    log::debug,
+    post::channel::PostReceiver,
+    tokio::{
+        fs::{create_dir_all, File},
+        io::{AsyncWriteExt, BufWriter},
+        spawn,
+        task::JoinHandle,
+    },
+};
+
+use crate::{
+    error::QueueError,
+    events::Event,
+    export::{export_file_name, ExportFormat},
+};
+
+use super::Queue;
+
+impl Queue {
+    /// Spawns a task that writes every received post into a download list instead of downloading it.
+    ///
+    /// # Arguments
+    /// * `format`: The [`ExportFormat`] of the list.
+    /// * `list_path`: Where the list will be written. Parent directories are created if needed.
+    /// * `output_dir`: The directory the posts should be downloaded into by the external tool.
+    /// * `channel_rx`: A receiver for `Post` objects to be exported.
+    ///
+    /// # Returns
+    /// A `JoinHandle` to the spawned task, which will return the total number of exported posts.
+    pub fn setup_async_exporter(
+        self,
+        format: ExportFormat,
+        list_path: PathBuf,
+        output_dir: PathBuf,
+        mut channel_rx: PostReceiver,
+    ) -> JoinHandle<Result<u64, QueueError>> {
+        spawn(async move {
+            debug!("Async exporter thread initialized");
+
+            if let Some(parent) = list_path.parent()
+                && let Err(error) = create_dir_all(parent).await
+            {
+                return Err(QueueError::DirCreationError {
+                    message: error.to_string(),
+                });
+            }
+
+            debug!("Writing {:?} list to {}", format, list_path.display());
+            let mut list_file = BufWriter::new(File::create(&list_path).await?);
+
+            let is_pool = self.download_fmt.download_pool();
+            let mut exported = 0;
+
+            while let Some(post) = channel_rx.recv().await {
+                self.events.on_event(Event::PostReceived { post_id: post.id });
+
+                let file_name = export_file_name(&post, self.name_type, is_pool);
+                let entry = format.format_entry(&post, &file_name, &output_dir)?;
+
+                list_file.write_all(entry.as_bytes()).await?;
+                exported += 1;
+            }
+
+            list_file.flush().await?;
+
+            self.events.on_event(Event::Finished);
+
+            Ok(exported)
+        })
+    }
+}
@@ -50,29 +50,35 @@
 //! }
 //! ```
 
-#[cfg(feature = "cbz")]
-mod cbz;
-
-mod folder;
+mod download;
+mod export;
 
+use crate::caption::CaptionConfig;
 use crate::error::QueueError;
-// Import the new progress listener traits and helpers
-use crate::progress::{no_op_progress_listener, SharedProgressListener};
+use crate::events::{no_op_event_listener, Event, SharedEventListener};
+#[cfg(feature = "image")]
+use crate::process::ImageProcessor;
+use crate::report::{DownloadReport, FailedPost};
+#[cfg(feature = "cbz")]
+use crate::sink::{CbzSink, ExistingArchive, VolumeLimits, ZipCompression};
+#[cfg(feature = "image")]
+use crate::sink::ProcessSink;
+use crate::sink::{FolderSink, StorageSink};
+use crate::stats::TagStats;
+use futures::{stream, StreamExt};
+use ibdl_common::client;
 use ibdl_common::log::debug;
 use ibdl_common::post::error::PostError;
-use ibdl_common::post::{NameType, Post};
+use ibdl_common::post::{channel::PostReceiver, NameType, Post};
 use ibdl_common::reqwest::Client;
 use ibdl_common::tokio::spawn;
-use ibdl_common::tokio::sync::mpsc::UnboundedReceiver; // Removed Receiver, channel
 use ibdl_common::tokio::task::JoinHandle;
-use ibdl_common::{client, tokio};
+use ibdl_common::tokio_util::sync::CancellationToken;
 use ibdl_extractors::extractor_config::ServerConfig;
-use std::path::{Path, PathBuf};
+use ibdl_extractors::prelude::PoolInfo;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use tokio::fs::{create_dir_all, OpenOptions};
-use tokio::io::AsyncWriteExt;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug, Copy, Clone)]
 enum DownloadFormat {
@@ -85,6 +91,7 @@ enum DownloadFormat {
 }
 
 impl DownloadFormat {
+    #[cfg(feature = "cbz")]
     #[inline]
     pub const fn download_cbz(&self) -> bool {
         match self {
@@ -129,8 +136,27 @@ pub struct Queue {
     download_fmt: DownloadFormat,
     name_type: NameType,
     annotate: bool,
+    caption: Arc<CaptionConfig>,
+    /// Counts every saved post if set.
+    tag_stats: Option<Arc<Mutex<TagStats>>>,
+    /// Saved in the summary of CBZ archives.
+    #[cfg_attr(not(feature = "cbz"), allow(dead_code))]
+    server: String,
+    #[cfg_attr(not(feature = "cbz"), allow(dead_code))]
+    query: Vec<String>,
+    #[cfg_attr(not(feature = "cbz"), allow(dead_code))]
+    pool: Option<PoolInfo>,
+    #[cfg(feature = "cbz")]
+    volumes: VolumeLimits,
+    #[cfg(feature = "cbz")]
+    compression: ZipCompression,
+    #[cfg(feature = "cbz")]
+    existing_archive: ExistingArchive,
+    /// Processes every image before it's saved, if set.
+    #[cfg(feature = "image")]
+    processor: Option<ImageProcessor>,
     // No imageboard field here, it's used for client creation only if needed
-    progress_listener: SharedProgressListener,
+    events: SharedEventListener,
 }
 
 impl Queue {
@@ -140,8 +166,9 @@ impl Queue {
         sim_downloads: u8,
         custom_client: Option<Client>,
         options: QueueOpts,
-        progress_listener: Option<SharedProgressListener>,
+        event_listener: Option<SharedEventListener>,
     ) -> Self {
+        let server = server_config.name.clone();
         let client = if let Some(cli) = custom_client {
             cli
         } else {
@@ -159,157 +186,277 @@ impl Queue {
             // If CBZ is disabled, pool download always goes to folder
             DownloadFormat::FolderPool
         } else {
+            #[cfg(feature = "cbz")]
+            if options.save_as_cbz {
+                DownloadFormat::Cbz
+            } else {
+                DownloadFormat::Folder
+            }
+            #[cfg(not(feature = "cbz"))]
             DownloadFormat::Folder
         };
 
-        let listener = progress_listener.unwrap_or_else(no_op_progress_listener);
+        let events = event_listener.unwrap_or_else(no_op_event_listener);
         Self {
             download_fmt,
             sim_downloads,
             annotate: options.annotate,
+            caption: Arc::new(CaptionConfig::default()),
+            tag_stats: None,
             client,
             name_type: options.name_type,
-            progress_listener: listener,
+            server,
+            query: Vec::new(),
+            pool: None,
+            #[cfg(feature = "cbz")]
+            volumes: VolumeLimits::default(),
+            #[cfg(feature = "cbz")]
+            compression: ZipCompression::default(),
+            #[cfg(feature = "cbz")]
+            existing_archive: ExistingArchive::default(),
+            #[cfg(feature = "image")]
+            processor: None,
+            events,
         }
     }
 
+    /// Describes what is being downloaded, like the searched tags. Saved in the summary of CBZ archives.
+    #[must_use]
+    pub fn query(mut self, query: Vec<String>) -> Self {
+        self.query = query;
+        self
+    }
+
+    /// Info of the pool being downloaded, saved in the `ComicInfo.xml` of pool CBZ archives.
+    #[must_use]
+    pub fn pool_info(mut self, pool: Option<PoolInfo>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Which tags are written into the caption of every post when [`QueueOpts::annotate`] is set.
+    #[must_use]
+    pub fn caption(mut self, config: CaptionConfig) -> Self {
+        self.caption = Arc::new(config);
+        self
+    }
+
+    /// Counts the tags, rating and extension of every saved post, returned in [`DownloadReport::tag_stats`].
+    ///
+    /// Posts that were already saved are counted too, as they are part of the output.
+    #[must_use]
+    pub fn tag_stats(mut self, enabled: bool) -> Self {
+        self.tag_stats = enabled.then(|| Arc::new(Mutex::new(TagStats::default())));
+        self
+    }
+
+    /// Splits CBZ archives into volumes. See [`CbzSink::create`].
+    #[cfg(feature = "cbz")]
+    #[must_use]
+    pub const fn volumes(mut self, limits: VolumeLimits) -> Self {
+        self.volumes = limits;
+        self
+    }
+
+    /// How files are compressed inside CBZ archives. See [`CbzSink::compression`].
+    #[cfg(feature = "cbz")]
+    #[must_use]
+    pub const fn compression(mut self, compression: ZipCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Whether an existing CBZ archive is appended to or replaced. See [`CbzSink::create`].
+    #[cfg(feature = "cbz")]
+    #[must_use]
+    pub const fn existing_archive(mut self, existing: ExistingArchive) -> Self {
+        self.existing_archive = existing;
+        self
+    }
+
+    /// Resizes and re-encodes every image before it's saved, wrapping the sink into a [`ProcessSink`].
+    #[cfg(feature = "image")]
+    #[must_use]
+    pub fn process(mut self, processor: Option<ImageProcessor>) -> Self {
+        self.processor = processor;
+        self
+    }
+
     /// Spawns the main asynchronous download task.
     ///
     /// # Arguments
     /// * `output_dir`: The base directory where files or CBZ archives will be saved.
-    /// * `channel_rx`: An unbounded receiver for `Post` objects to be downloaded.
+    /// * `channel_rx`: A receiver for `Post` objects to be downloaded. With a bounded channel, the extractor only
+    ///   fetches more posts as the downloads finish.
+    /// * `cancel`: Stops accepting new posts once cancelled. Running downloads are interrupted and their
+    ///   partial files removed, while an open CBZ file is still finalized.
     ///
     /// The caller is responsible for:
-    /// 1. Creating and configuring the `ProgressListener` (passed during `Queue::new`).
-    /// 2. Calling `set_main_total` and `inc_main_total` on the `ProgressListener` as posts are discovered.
+    /// 1. Creating and configuring the `EventListener` (passed during `Queue::new`).
+    /// 2. Emitting [`Event::PostsQueued`] on the `EventListener` as posts are discovered.
     /// 3. Sending `Post` objects into `channel_rx`.
     ///
     /// # Returns
-    /// A `JoinHandle` to the spawned task, which will return the total number of successfully
-    /// downloaded posts or a `QueueError`.
+    /// A `JoinHandle` to the spawned task, which will return a [`DownloadReport`] with the total number
+    /// of successfully downloaded posts and every post that failed to download, or a `QueueError`.
+    ///
+    /// If the run is cancelled, every post left in the channel or interrupted during the download is returned
+    /// in [`DownloadReport::pending`]. The channel is drained until all senders are dropped, so the extractor
+    /// must be cancelled with the same token.
     pub fn setup_async_downloader(
         self,
         output_dir: PathBuf,
-        channel_rx: UnboundedReceiver<Post>,
-    ) -> JoinHandle<Result<u64, QueueError>> {
-        let progress_listener = self.progress_listener.clone(); // Clone Arc for the spawned task
-
+        channel_rx: PostReceiver,
+        cancel: CancellationToken,
+    ) -> JoinHandle<Result<DownloadReport, QueueError>> {
         spawn(async move {
             debug!("Async Downloader thread initialized");
 
-            self.create_out(&output_dir).await?;
-
-            let post_channel = UnboundedReceiverStream::new(channel_rx);
-
-            // Counter for successfully downloaded posts
-            // This counter tracks posts that are fully downloaded and saved.
-            let downloaded_post_count = Arc::new(AtomicU64::new(0));
-
-            // The main progress bar (controlled by progress_listener.main_tick() and set_main_total())
-            // will now track posts as they are received from the extractor into the queue.
-            // The total for this bar is set by the caller (e.g., main.rs) after the extractor
-            // sends the total number of posts it expects to fetch.
-            // The condition now correctly checks if the 'cbz' feature is enabled AND
-            // if the download format is actually CBZ.
-            if cfg!(feature = "cbz") && self.download_fmt.download_cbz() {
-                #[cfg(feature = "cbz")]
-                self.cbz_path(
-                    output_dir,   // This is the CBZ file path itself
-                    post_channel, // Stream of posts from the extractor
-                    self.download_fmt.download_pool(),
-                    progress_listener.clone(), // Pass listener to internal methods
-                    downloaded_post_count.clone(), // Pass counter
-                )
-                .await?;
-            } else {
-                // This branch is taken if 'cbz' feature is disabled,
-                // OR if 'cbz' feature is enabled but folder download is selected.
-                self.download_channel(
-                    post_channel,
-                    output_dir, // This is the root directory for downloaded files
-                    self.download_fmt.download_pool(),
-                    progress_listener.clone(), // Pass listener to internal methods
-                    downloaded_post_count.clone(), // Pass counter
+            #[cfg(feature = "cbz")]
+            if self.download_fmt.download_cbz() {
+                let mut sink = CbzSink::create(
+                    output_dir,
+                    !self.download_fmt.download_pool(),
+                    self.server.clone(),
+                    self.query.clone(),
+                    self.volumes,
+                    self.existing_archive,
                 )
-                .await;
-            }
+                .await?
+                .compression(self.compression);
 
-            // Signal that the main processing is done via the listener
-            // This will finish the main progress bar, indicating all posts received
-            // from the extractor have been processed (attempted for download).
-            self.progress_listener.main_done();
+                if let Some(pool) = &self.pool {
+                    sink = sink.pool(pool.clone());
+                }
+                return self.download_into(sink, channel_rx, cancel).await;
+            }
 
-            let total_downloaded = downloaded_post_count.load(Ordering::SeqCst);
-            Ok(total_downloaded)
+            let sink = FolderSink::create(output_dir, self.name_type).await?;
+            self.download_into(sink, channel_rx, cancel).await
         })
     }
 
-    async fn create_out(&self, dir: &Path) -> Result<(), QueueError> {
-        if self.download_fmt.download_cbz() {
-            let output_file = dir.parent().unwrap().to_path_buf();
+    /// Same as [`setup_async_downloader`](Self::setup_async_downloader), but saves the files into `sink`.
+    pub fn setup_sink_downloader<S: StorageSink>(
+        self,
+        sink: S,
+        channel_rx: PostReceiver,
+        cancel: CancellationToken,
+    ) -> JoinHandle<Result<DownloadReport, QueueError>> {
+        spawn(async move {
+            debug!("Async Downloader thread initialized");
+            self.download_into(sink, channel_rx, cancel).await
+        })
+    }
 
-            match create_dir_all(&output_file).await {
-                Ok(_) => (),
-                Err(error) => {
-                    return Err(QueueError::DirCreationError {
-                        message: error.to_string(),
-                    })
-                }
-            };
-            return Ok(());
+    async fn download_into<S: StorageSink>(
+        &self,
+        sink: S,
+        channel_rx: PostReceiver,
+        cancel: CancellationToken,
+    ) -> Result<DownloadReport, QueueError> {
+        #[cfg(feature = "image")]
+        if let Some(processor) = &self.processor {
+            let sink = ProcessSink::new(sink, processor.clone());
+            return self.download_all(sink, channel_rx, cancel).await;
         }
 
-        debug!("Target dir: {}", dir.display());
-        match create_dir_all(&dir).await {
-            Ok(_) => (),
-            Err(error) => {
-                return Err(QueueError::DirCreationError {
-                    message: error.to_string(),
-                })
+        self.download_all(sink, channel_rx, cancel).await
+    }
+
+    async fn download_all<S: StorageSink>(
+        &self,
+        sink: S,
+        mut channel_rx: PostReceiver,
+        cancel: CancellationToken,
+    ) -> Result<DownloadReport, QueueError> {
+        let post_channel = stream::poll_fn(|cx| channel_rx.poll_recv(cx));
+
+        // Counter for successfully downloaded posts
+        // This counter tracks posts that are fully downloaded and saved.
+        let downloaded_post_count = Arc::new(AtomicU64::new(0));
+
+        // Every post that failed to download, along with the reason.
+        let failed_posts = Arc::new(Mutex::new(Vec::new()));
+
+        // The main progress bar (controlled by Event::PostReceived and Event::PostsQueued)
+        // will now track posts as they are received from the extractor into the queue.
+        // The total for this bar is set by the caller (e.g., main.rs) after the extractor
+        // sends the total number of posts it expects to fetch.
+        self.download_to(
+            sink,
+            // Stream of posts from the extractor
+            post_channel.take_until(cancel.cancelled()),
+            self.download_fmt.download_pool(),
+            downloaded_post_count.clone(), // Pass counter
+            failed_posts.clone(),
+            &cancel,
+        )
+        .await?;
+
+        // Signal that the main processing is done via the listener
+        // This will finish the main progress bar, indicating all posts received
+        // from the extractor have been processed (attempted for download).
+        self.events.on_event(Event::Finished);
+
+        let failed = std::mem::take(&mut *failed_posts.lock().unwrap());
+
+        // Interrupted downloads didn't fail, they are saved to be downloaded later.
+        let (interrupted, failed): (Vec<FailedPost>, Vec<FailedPost>) =
+            failed.into_iter().partition(FailedPost::is_cancelled);
+
+        let cancelled = cancel.is_cancelled();
+        let mut pending: Vec<Post> = interrupted.into_iter().map(|f| f.post).collect();
+
+        if cancelled {
+            // Wait for the extractor to stop and keep everything it sent after the cancellation.
+            while let Some(post) = channel_rx.recv().await {
+                pending.push(post);
             }
-        };
+            debug!("Download cancelled with {} pending posts", pending.len());
+        }
 
-        Ok(())
+        let tag_stats = self
+            .tag_stats
+            .as_ref()
+            .map(|stats| std::mem::take(&mut *stats.lock().unwrap()));
+
+        Ok(DownloadReport {
+            downloaded: downloaded_post_count.load(Ordering::SeqCst),
+            failed,
+            cancelled,
+            pending,
+            tag_stats,
+        })
     }
 
-    async fn write_caption(
-        post: &Post,
-        name_type: NameType,
-        output: &Path,
-    ) -> Result<(), PostError> {
-        let outpath = output.join(format!("{}.txt", post.name(name_type)));
-        let mut prompt_file = OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .open(outpath)
-            .await?;
-
-        let tag_list = Vec::from_iter(
-            post.tags
-                .iter()
-                .filter(|t| t.is_prompt_tag())
-                .map(|tag| tag.tag()),
-        );
-
-        let prompt = tag_list.join(", ");
-
-        let f1 = prompt.replace('_', " ");
-
-        prompt_file.write_all(f1.as_bytes()).await?;
-        debug!("Wrote caption file for {}", post.file_name(name_type));
-        Ok(())
+    /// Builds the [`FailedPost`] for `post` and emits an [`Event::DownloadFailed`] with the error.
+    fn download_failed(
+        events: &SharedEventListener,
+        post: Post,
+        file_name: String,
+        error: PostError,
+    ) -> FailedPost {
+        let failed = FailedPost::new(post, &error);
+
+        events.on_event(Event::DownloadFailed {
+            post_id: failed.post.id,
+            file_name,
+            error: Arc::new(error),
+        });
+
+        failed
     }
 
     // NOTE on Progress Handling:
     //
-    // The `SharedProgressListener` is used for two main types of progress:
+    // The `SharedEventListener` is used for two main types of progress:
     // 1. Main Progress (Extractor Output):
-    //    - Total: Set by the CLI (`main.rs`) when the extractor communicates the total number of posts it will fetch.
+    //    - Total: Emitted by the CLI (`main.rs`) as `Event::PostsQueued` when the extractor communicates the total number of posts it will fetch.
     //             This happens via the `length_tx` channel passed to the extractor.
-    //    - Ticks: `progress_listener.main_tick()` is called within `cbz_path` or `download_channel`
+    //    - Ticks: `Event::PostReceived` is emitted within `download_to`
     //             as soon as a `Post` is received from the `post_channel` (i.e., from the extractor).
     // 2. Download Progress (Per-File):
-    //    - Handled by `progress_listener.add_download_task(...)` and its associated `DownloadProgressUpdater` methods.
+    //    - Reported with `Event::DownloadStarted`, `Event::DownloadProgress` and `Event::DownloadFinished`/`Event::DownloadFailed`.
     //    - `downloaded_post_count` (Arc<AtomicU64>) is incremented after a post's file is successfully downloaded.
 }
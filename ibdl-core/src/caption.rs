@@ -0,0 +1,257 @@
+//! Captions saved along with every post, for training image models.
+use std::collections::HashSet;
+
+use ibdl_common::post::{Post, rating::Rating, tags::TagType};
+use rand::seq::SliceRandom;
+use regex::Regex;
+
+/// Replaced by the rating of the post in [`CaptionConfig::rating_tag`].
+#[allow(clippy::literal_string_with_formatting_args)]
+pub const RATING_PLACEHOLDER: &str = "{rating}";
+
+/// How the tags of a caption are ordered.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TagOrder {
+    /// In the order given by the imageboard.
+    #[default]
+    AsIs,
+    /// Alphabetically, within each tag type.
+    Alphabetical,
+    /// Randomly, except for the first `keep_first` tags, which stay in front.
+    Shuffle { keep_first: usize },
+}
+
+/// Which tags go into the caption of a post and how they are written.
+///
+/// The default caption is made of the prompt tags of the post ([`Tag::is_prompt_tag`](ibdl_common::post::tags::Tag::is_prompt_tag))
+/// joined with `", "`, with spaces instead of underscores.
+#[derive(Debug, Clone, Default)]
+pub struct CaptionConfig {
+    /// Tag types to include, in this order. If empty, the prompt tags are included in the order given by the
+    /// imageboard.
+    pub tag_types: Vec<TagType>,
+    /// Written before every other tag.
+    pub trigger: Option<String>,
+    /// Written after the trigger word, with `{rating}` replaced by the rating of the post, like `rating:{rating}`.
+    /// Skipped for posts without a rating.
+    pub rating_tag: Option<String>,
+    pub order: TagOrder,
+    /// Maximum number of tags of the post, not counting the trigger word and the rating tag.
+    pub max_tags: Option<usize>,
+    /// Tags left out of the caption, as named by the imageboard, like `long_hair`.
+    pub exclude: HashSet<String>,
+    /// Tags matching this pattern are left out of the caption.
+    pub exclude_pattern: Option<Regex>,
+    /// Writes parentheses as `\(` and `\)`, so prompt parsers don't read them as emphasis.
+    pub escape_parentheses: bool,
+    /// Keeps underscores in tags instead of replacing them with spaces.
+    pub keep_underscores: bool,
+}
+
+impl CaptionConfig {
+    /// Builds the caption of `post`.
+    #[must_use]
+    pub fn caption(&self, post: &Post) -> String {
+        let mut tags = self.select_tags(post);
+
+        match self.order {
+            TagOrder::AsIs => {}
+            TagOrder::Alphabetical => {
+                tags.sort_by(|(type_a, a), (type_b, b)| type_a.cmp(type_b).then_with(|| a.cmp(b)));
+            }
+            TagOrder::Shuffle { keep_first } => {
+                let keep_first = keep_first.min(tags.len());
+                tags[keep_first..].shuffle(&mut rand::rng());
+            }
+        }
+
+        if let Some(max) = self.max_tags {
+            tags.truncate(max);
+        }
+
+        let rating_tag = match (&self.rating_tag, post.rating) {
+            (Some(_), Rating::Unknown) | (None, _) => None,
+            (Some(format), rating) => {
+                Some(format.replace(RATING_PLACEHOLDER, &rating.to_string().to_lowercase()))
+            }
+        };
+
+        self.trigger
+            .iter()
+            .cloned()
+            .chain(rating_tag)
+            .chain(tags.into_iter().map(|(_, tag)| self.format_tag(&tag)))
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+
+    /// Tags of `post` that go into the caption, along with the position of their type in
+    /// [`tag_types`](Self::tag_types), or the type itself if it's empty.
+    fn select_tags(&self, post: &Post) -> Vec<(usize, String)> {
+        let included = post.tags.iter().filter_map(|tag| {
+            if self.tag_types.is_empty() {
+                return tag
+                    .is_prompt_tag()
+                    .then_some((tag.tag_type() as usize, tag.tag()));
+            }
+            let position = self
+                .tag_types
+                .iter()
+                .position(|tag_type| *tag_type == tag.tag_type())?;
+            Some((position, tag.tag()))
+        });
+
+        let mut tags: Vec<(usize, String)> = included
+            .filter(|(_, tag)| !tag.is_empty() && !self.exclude.contains(tag))
+            .filter(|(_, tag)| {
+                self.exclude_pattern
+                    .as_ref()
+                    .is_none_or(|pattern| !pattern.is_match(tag))
+            })
+            .collect();
+
+        // Group tags by the selected types, keeping the order of the imageboard within each type.
+        if !self.tag_types.is_empty() {
+            tags.sort_by_key(|(position, _)| *position);
+        }
+        tags
+    }
+
+    fn format_tag(&self, tag: &str) -> String {
+        let mut tag = if self.keep_underscores {
+            tag.to_string()
+        } else {
+            tag.replace('_', " ")
+        };
+
+        if self.escape_parentheses {
+            tag = tag.replace('(', "\\(").replace(')', "\\)");
+        }
+        tag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibdl_common::post::tags::Tag;
+
+    use super::*;
+    use crate::test::{md5_of, post};
+
+    fn tagged_post() -> Post {
+        let mut post = post(1, &md5_of(1));
+        post.rating = Rating::Explicit;
+        post.tags = vec![
+            Tag::new("kroos_(arknights)", TagType::Character),
+            Tag::new("long_hair", TagType::General),
+            Tag::new("artist_name", TagType::Author),
+            Tag::new("arknights", TagType::Copyright),
+            Tag::new("bow", TagType::General),
+            Tag::new("highres", TagType::Meta),
+            Tag::new("", TagType::General),
+        ];
+        post
+    }
+
+    #[test]
+    fn default_caption_has_prompt_tags_in_order() {
+        let caption = CaptionConfig::default().caption(&tagged_post());
+        assert_eq!(caption, "kroos (arknights), long hair, bow");
+    }
+
+    #[test]
+    fn tags_are_grouped_by_the_selected_types() {
+        let config = CaptionConfig {
+            tag_types: vec![TagType::Author, TagType::General, TagType::Character],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.caption(&tagged_post()),
+            "artist name, long hair, bow, kroos (arknights)"
+        );
+    }
+
+    #[test]
+    fn alphabetical_order_sorts_within_each_type() {
+        let config = CaptionConfig {
+            tag_types: vec![TagType::General, TagType::Character],
+            order: TagOrder::Alphabetical,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.caption(&tagged_post()),
+            "bow, long hair, kroos (arknights)"
+        );
+    }
+
+    #[test]
+    fn shuffle_keeps_the_first_tags() {
+        let config = CaptionConfig {
+            tag_types: vec![TagType::Character, TagType::General],
+            order: TagOrder::Shuffle { keep_first: 1 },
+            ..Default::default()
+        };
+
+        let caption = config.caption(&tagged_post());
+        let (first, rest) = caption.split_once(", ").unwrap();
+        assert_eq!(first, "kroos (arknights)");
+
+        let mut rest: Vec<&str> = rest.split(", ").collect();
+        rest.sort_unstable();
+        assert_eq!(rest, ["bow", "long hair"]);
+    }
+
+    #[test]
+    fn excluded_tags_are_left_out() {
+        let config = CaptionConfig {
+            exclude: HashSet::from(["long_hair".to_string()]),
+            exclude_pattern: Some(Regex::new(r"^kroos_\(.*\)$").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(config.caption(&tagged_post()), "bow");
+    }
+
+    #[test]
+    fn trigger_and_rating_come_first_and_are_not_counted() {
+        let config = CaptionConfig {
+            trigger: Some("kroos".to_string()),
+            rating_tag: Some(format!("rating:{RATING_PLACEHOLDER}")),
+            max_tags: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.caption(&tagged_post()),
+            "kroos, rating:explicit, kroos (arknights), long hair"
+        );
+
+        let mut unrated = tagged_post();
+        unrated.rating = Rating::Unknown;
+        assert_eq!(
+            config.caption(&unrated),
+            "kroos, kroos (arknights), long hair"
+        );
+    }
+
+    #[test]
+    fn max_tags_of_zero_keeps_only_the_trigger() {
+        let config = CaptionConfig {
+            trigger: Some("kroos".to_string()),
+            max_tags: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(config.caption(&tagged_post()), "kroos");
+    }
+
+    #[test]
+    fn parentheses_are_escaped_and_underscores_kept() {
+        let config = CaptionConfig {
+            escape_parentheses: true,
+            keep_underscores: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.caption(&tagged_post()),
+            "kroos_\\(arknights\\), long_hair, bow"
+        );
+    }
+}
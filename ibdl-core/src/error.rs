@@ -1,6 +1,7 @@
 use std::{io, num::TryFromIntError};
 
-use ibdl_common::post::error::PostError;
+use ibdl_common::{post::error::PostError, tokio::task::JoinError};
+use ibdl_extractors::error::ExtractorError;
 use thiserror::Error;
 
 #[allow(clippy::enum_variant_names)]
@@ -28,12 +29,47 @@ pub enum QueueError {
         source: zip::result::ZipError,
     },
 
+    #[cfg(feature = "s3")]
+    #[error("Invalid S3 configuration: {message}")]
+    S3ConfigError { message: String },
+
     #[error("Summary file in {file} not found or corrupted")]
     ZipSummaryReadError { file: String },
 
+    #[error("Failed to serialize post into list: {error}")]
+    PostListEncodeFail { error: String },
+
+    #[error("Failed to decode post list at line {line}: {error}")]
+    PostListDecodeFail { line: usize, error: String },
+
+    #[error("Failed to serialize list of processed files: {error}")]
+    ProcessedListEncodeFail { error: String },
+
+    #[error("Failed to read list of processed files at line {line}: {error}")]
+    ProcessedListDecodeFail { line: usize, error: String },
+
+    #[error("Failed to read dataset metadata in {file} at line {line}: {error}")]
+    MetadataDecodeFail {
+        file: String,
+        line: usize,
+        error: String,
+    },
+
+    #[error("Failed to serialize job state: {error}")]
+    JobStateEncodeFail { error: String },
+
+    #[error("Failed to read job state in {file}: {error}")]
+    JobStateDecodeFail { file: String, error: String },
+
     #[error("No posts to download!")]
     NoPostsInQueue,
 
+    #[error("Failed to serialize tag stats: {error}")]
+    StatsSerializeFail { error: String },
+
+    #[error("No post list, dataset records or captions found in {path}")]
+    NoTagStats { path: String },
+
     #[error("Failed to print line to Progress Bar: {message}")]
     ProgressBarPrintFail { message: String },
 
@@ -45,4 +81,29 @@ pub enum QueueError {
 
     #[error("Failed to get exclusive ownership of ZipWriter Arc. This may indicate that some tasks are still holding references.")]
     MutexLockReleaseError,
+
+    #[error("Failed to get exclusive ownership of the storage sink. Some tasks are still holding references to it.")]
+    SinkStillShared,
+}
+
+/// Errors of a [`Session`](crate::session::Session).
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("Failed to find posts: {source}")]
+    ExtractorFail {
+        #[from]
+        source: ExtractorError,
+    },
+
+    #[error("Failed to download posts: {source}")]
+    QueueFail {
+        #[from]
+        source: QueueError,
+    },
+
+    #[error("Session task stopped unexpectedly: {source}")]
+    TaskFail {
+        #[from]
+        source: JoinError,
+    },
 }
@@ -0,0 +1,116 @@
+//! Typed events emitted while searching and downloading posts.
+//!
+//! The [`Queue`](crate::async_queue::Queue) reports everything it does to an [`EventListener`]. The same listener
+//! can also receive the events emitted by the extractors by wrapping it with [`extractor_listener`] and
+//! passing it to [`EmitEvents::set_event_listener`](ibdl_extractors::prelude::EmitEvents::set_event_listener).
+//!
+//! Listeners that only care about the progress bars can keep implementing [`ProgressListener`](crate::progress::ProgressListener)
+//! and be wrapped with [`progress_events`](crate::progress::progress_events).
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
+
+use ibdl_common::post::error::PostError;
+pub use ibdl_extractors::events::{ExtractorEvent, FilterRule};
+use ibdl_extractors::events::{ExtractorListener, SharedExtractorListener};
+
+/// Why a post wasn't downloaded even though it didn't fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SkipReason {
+    /// A file with the same name and MD5 hash already exists.
+    AlreadyExists,
+    /// A file with the same MD5 hash existed with the other naming scheme and was renamed.
+    Renamed { from: String },
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AlreadyExists => write!(f, "already exists and is identical (MD5 match)"),
+            Self::Renamed { from } => write!(f, "renamed from {from} (MD5 match)"),
+        }
+    }
+}
+
+/// Everything that can happen during a run.
+///
+/// Downloads are identified by `post_id`, which is unique inside a single run.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// Forwarded from the extractor.
+    Extractor(ExtractorEvent),
+    /// The extractor found more posts that will be sent to the queue.
+    PostsQueued(u64),
+    /// The queue received a post from the extractor.
+    PostReceived { post_id: u64 },
+    /// The file server answered and the file is being downloaded.
+    DownloadStarted {
+        post_id: u64,
+        file_name: String,
+        size: Option<u64>,
+    },
+    /// Total bytes downloaded so far for a post.
+    DownloadProgress { post_id: u64, downloaded: u64 },
+    /// The post was downloaded and saved.
+    DownloadFinished { post_id: u64, file_name: String },
+    /// The downloaded file was corrupted, so it was discarded and is being downloaded again.
+    DownloadRetried {
+        post_id: u64,
+        file_name: String,
+        error: Arc<PostError>,
+    },
+    /// The post couldn't be downloaded.
+    DownloadFailed {
+        post_id: u64,
+        file_name: String,
+        error: Arc<PostError>,
+    },
+    /// The post was not downloaded because the file is already saved.
+    FileSkipped {
+        post_id: u64,
+        file_name: String,
+        reason: SkipReason,
+    },
+    /// An existing file with the wrong MD5 hash was removed and will be downloaded again.
+    FileReplaced {
+        post_id: u64,
+        file_name: String,
+        removed: String,
+    },
+    /// All posts received by the queue were processed.
+    Finished,
+}
+
+/// Receives every [`Event`] emitted during a run. All methods should be thread-safe.
+pub trait EventListener: Send + Sync + Debug {
+    fn on_event(&self, event: Event);
+}
+
+/// Convenience type alias for a shared, thread-safe event listener.
+pub type SharedEventListener = Arc<dyn EventListener>;
+
+/// An [`EventListener`] that ignores all events.
+#[derive(Debug, Clone)]
+pub struct NoOpEventListener;
+
+impl EventListener for NoOpEventListener {
+    fn on_event(&self, _event: Event) {}
+}
+
+/// Returns a shared instance of a `NoOpEventListener`.
+pub fn no_op_event_listener() -> SharedEventListener {
+    Arc::new(NoOpEventListener)
+}
+
+#[derive(Debug)]
+struct ExtractorForwarder(SharedEventListener);
+
+impl ExtractorListener for ExtractorForwarder {
+    fn on_extractor_event(&self, event: ExtractorEvent) {
+        self.0.on_event(Event::Extractor(event));
+    }
+}
+
+/// Wraps `listener` so it can receive the events emitted by an extractor as [`Event::Extractor`].
+pub fn extractor_listener(listener: SharedEventListener) -> SharedExtractorListener {
+    Arc::new(ExtractorForwarder(listener))
+}
@@ -0,0 +1,253 @@
+//! Export found posts into download lists for external tools and read them back.
+//!
+//! # Formats
+//! * [`ExportFormat::Aria2`]: An [aria2c input file](https://aria2.github.io/manual/en/html/aria2c.html#input-file)
+//!   with the output name, directory and MD5 checksum of every post. Use it with `aria2c -i <file>`.
+//!   Posts without an MD5 hash are listed without a checksum.
+//! * [`ExportFormat::Wget`]: A plain list of URLs, one per line. Use it with `wget -i <file>`.
+//!   Since wget can't rename files from an input list, they will be saved with the name the server gives them,
+//!   ignoring the selected [`NameType`] and the sequential names of pools.
+//! * [`ExportFormat::Posts`]: A list of serialized [`Post`]s, one JSON object per line.
+//!   This list can be fed back to the downloader with [`read_post_list`] to download the posts without contacting the imageboard API again.
+//!
+//! [`read_post_list`] also accepts the list of failed posts written by [`DownloadReport::write_failed_list`](crate::report::DownloadReport::write_failed_list).
+use std::path::Path;
+
+use clap::ValueEnum;
+use ibdl_common::{
+    post::{NameType, Post},
+    serde::Deserialize,
+    serde_json,
+    tokio::fs::read_to_string,
+};
+
+use crate::{error::QueueError, report::FailedPost};
+
+/// All the formats a post list can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// aria2c input file with output name, directory and MD5 checksum
+    Aria2,
+    /// Plain URL list compatible with `wget -i`. Files keep the name given by the server
+    Wget,
+    /// Serialized post list that can be downloaded later with the `import` command
+    Posts,
+}
+
+impl ExportFormat {
+    /// Default file name used when the user doesn't specify where to save the list.
+    #[inline]
+    pub const fn default_file_name(&self) -> &'static str {
+        match self {
+            Self::Aria2 => "download_list.aria2",
+            Self::Wget => "download_list.txt",
+            Self::Posts => "posts.jsonl",
+        }
+    }
+
+    /// Formats a single post into an entry of this list format.
+    ///
+    /// # Arguments
+    /// * `post`: The post to be exported.
+    /// * `file_name`: The name the file should have when downloaded, already following the selected [`NameType`].
+    /// * `output_dir`: The directory the file should be downloaded into.
+    pub fn format_entry(
+        &self,
+        post: &Post,
+        file_name: &str,
+        output_dir: &Path,
+    ) -> Result<String, QueueError> {
+        match self {
+            Self::Aria2 => {
+                let mut entry = format!(
+                    "{}\n  out={}\n  dir={}\n",
+                    post.url,
+                    file_name,
+                    output_dir.display()
+                );
+                if !post.md5.is_empty() {
+                    entry.push_str(&format!("  checksum=md5={}\n", post.md5));
+                }
+                Ok(entry)
+            }
+            Self::Wget => Ok(format!("{}\n", post.url)),
+            Self::Posts => {
+                let mut line = serde_json::to_string(post).map_err(|error| {
+                    QueueError::PostListEncodeFail {
+                        error: error.to_string(),
+                    }
+                })?;
+                line.push('\n');
+                Ok(line)
+            }
+        }
+    }
+}
+
+/// Returns the name a post would be saved with by the [`Queue`](crate::async_queue::Queue).
+#[inline]
+pub(crate) fn export_file_name(post: &Post, name_type: NameType, is_pool: bool) -> String {
+    if is_pool {
+        post.seq_file_name(6)
+    } else {
+        post.file_name(name_type)
+    }
+}
+
+/// A single line of a post list.
+#[derive(Deserialize)]
+#[serde(crate = "ibdl_common::serde", untagged)]
+enum PostListEntry {
+    Post(Post),
+    Failed(FailedPost),
+}
+
+impl From<PostListEntry> for Post {
+    fn from(entry: PostListEntry) -> Self {
+        match entry {
+            PostListEntry::Post(post) => post,
+            PostListEntry::Failed(failed) => failed.post,
+        }
+    }
+}
+
+/// Reads a post list written with [`ExportFormat::Posts`] or a list of failed posts from a previous run.
+///
+/// Empty lines are ignored.
+pub async fn read_post_list(path: &Path) -> Result<Vec<Post>, QueueError> {
+    let contents = read_to_string(path).await?;
+
+    let mut posts = Vec::with_capacity(contents.lines().count());
+
+    for (idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let entry = serde_json::from_str::<PostListEntry>(line).map_err(|error| {
+            QueueError::PostListDecodeFail {
+                line: idx + 1,
+                error: error.to_string(),
+            }
+        })?;
+
+        posts.push(entry.into());
+    }
+
+    if posts.is_empty() {
+        return Err(QueueError::NoPostsInQueue);
+    }
+
+    Ok(posts)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use ibdl_common::{
+        post::{Post, error::PostError},
+        serde_json,
+    };
+
+    use super::{ExportFormat, read_post_list};
+    use crate::{
+        error::QueueError,
+        report::FailedPost,
+        test::{md5_of, post},
+    };
+
+    #[test]
+    fn aria2_entry_has_name_dir_and_checksum() {
+        let entry = ExportFormat::Aria2
+            .format_entry(&post(1234, "abcd"), "1234.png", Path::new("/out"))
+            .unwrap();
+
+        assert_eq!(
+            entry,
+            "https://cdn.donmai.us/original/abcd.png\n  out=1234.png\n  dir=/out\n  checksum=md5=abcd\n"
+        );
+    }
+
+    #[test]
+    fn aria2_entry_without_md5_has_no_checksum() {
+        let entry = ExportFormat::Aria2
+            .format_entry(&post(1234, ""), "1234.png", Path::new("/out"))
+            .unwrap();
+
+        assert!(!entry.contains("checksum"));
+        assert!(entry.ends_with("  dir=/out\n"));
+    }
+
+    #[test]
+    fn wget_entry_is_only_the_url() {
+        let entry = ExportFormat::Wget
+            .format_entry(&post(1234, "abcd"), "1234.png", Path::new("/out"))
+            .unwrap();
+
+        assert_eq!(entry, "https://cdn.donmai.us/original/abcd.png\n");
+    }
+
+    #[test]
+    fn posts_entry_is_a_single_json_line() {
+        let original = post(1234, "abcd");
+        let entry = ExportFormat::Posts
+            .format_entry(&original, "1234.png", Path::new("/out"))
+            .unwrap();
+
+        assert_eq!(entry.matches('\n').count(), 1);
+        let decoded: Post = serde_json::from_str(entry.trim_end()).unwrap();
+        assert_eq!(decoded.id, original.id);
+        assert_eq!(decoded.md5, original.md5);
+        assert_eq!(decoded.url, original.url);
+    }
+
+    #[tokio::test]
+    async fn read_post_list_accepts_posts_and_failed_posts() {
+        let failed = FailedPost::new(
+            post(2, &md5_of(2)),
+            &PostError::RemoteFileNotFound { status: 404 },
+        );
+        let contents = format!(
+            "{}\n\n{}\n",
+            serde_json::to_string(&post(1, &md5_of(1))).unwrap(),
+            serde_json::to_string(&failed).unwrap()
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("posts.jsonl");
+        std::fs::write(&path, contents).unwrap();
+
+        let posts = read_post_list(&path).await.unwrap();
+        assert_eq!(posts.iter().map(|p| p.id).collect::<Vec<_>>(), [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn read_post_list_reports_the_bad_line() {
+        let contents = format!(
+            "{}\nnot json\n",
+            serde_json::to_string(&post(1, &md5_of(1))).unwrap()
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("posts.jsonl");
+        std::fs::write(&path, contents).unwrap();
+
+        let error = read_post_list(&path).await.unwrap_err();
+        assert!(matches!(
+            error,
+            QueueError::PostListDecodeFail { line: 2, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn read_post_list_rejects_empty_lists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("posts.jsonl");
+        std::fs::write(&path, "\n\n").unwrap();
+
+        let error = read_post_list(&path).await.unwrap_err();
+        assert!(matches!(error, QueueError::NoPostsInQueue));
+    }
+}
@@ -0,0 +1,66 @@
+//! State of an interrupted download, so it can be resumed later.
+//!
+//! When a run is cancelled, the query used, the page where the extractor stopped and every post
+//! that was found but not downloaded yet are saved as a [`JobState`]. Loading it back allows
+//! downloading the pending posts first and then continuing the search from the saved cursor.
+use std::path::Path;
+
+use ibdl_common::{
+    post::Post,
+    serde::{Deserialize, Serialize},
+    serde_json,
+    tokio::fs::{create_dir_all, read_to_string, remove_file, write},
+};
+
+use crate::error::QueueError;
+
+/// Everything needed to resume an interrupted download.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "ibdl_common::serde")]
+pub struct JobState {
+    /// Name of the imageboard, as used by `--imageboard`.
+    pub server: String,
+    /// The command and its arguments used to find the posts, like the searched tags or the pool id.
+    pub query: Vec<String>,
+    /// Start page for the extractor when resuming.
+    ///
+    /// `None` if the extractor already found every post and only the pending posts are left.
+    pub cursor: Option<u16>,
+    /// How many posts the extractor can still send, if the original run had a limit.
+    pub limit: Option<u16>,
+    /// Posts found but not downloaded yet.
+    pub pending: Vec<Post>,
+}
+
+impl JobState {
+    /// Reads a job state previously saved with [`JobState::save`].
+    pub async fn load(path: &Path) -> Result<Self, QueueError> {
+        let raw = read_to_string(path).await?;
+
+        serde_json::from_str(&raw).map_err(|error| QueueError::JobStateDecodeFail {
+            file: path.display().to_string(),
+            error: error.to_string(),
+        })
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<(), QueueError> {
+        let raw = serde_json::to_string(self).map_err(|error| QueueError::JobStateEncodeFail {
+            error: error.to_string(),
+        })?;
+
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).await?;
+        }
+
+        write(path, raw).await?;
+        Ok(())
+    }
+
+    /// Removes a job state left by a previous run, if any.
+    pub async fn remove(path: &Path) -> Result<(), QueueError> {
+        if path.is_file() {
+            remove_file(path).await?;
+        }
+        Ok(())
+    }
+}
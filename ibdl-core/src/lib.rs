@@ -8,8 +8,21 @@ use ibdl_common::ImageBoards;
 use std::path::{Path, PathBuf};
 
 pub mod async_queue;
+pub mod caption;
 pub mod error;
+pub mod events;
+pub mod export;
+pub mod job;
+#[cfg(feature = "image")]
+pub mod process;
 pub mod progress;
+pub mod report;
+pub mod session;
+pub mod sink;
+pub mod stats;
+mod test;
+#[cfg(feature = "image")]
+pub mod ugoira;
 
 #[inline]
 pub fn generate_output_path(
@@ -0,0 +1,545 @@
+//! Post-processing of downloaded images, like resizing them into aspect ratio buckets for training.
+//!
+//! An [`ImageProcessor`] is applied by a [`ProcessSink`](crate::sink::ProcessSink) to every file once it's fully
+//! downloaded and its MD5 hash was checked, right before it's saved. Only JPEG, PNG and WebP still images are
+//! processed, along with Ugoira if they are converted, everything else is saved as served. Images can be
+//! re-encoded into any [`OutputFormat`].
+use std::io::Cursor;
+
+use clap::ValueEnum;
+use ibdl_common::{
+    log::warn,
+    post::{Post, error::PostError, extension::Extension},
+};
+#[cfg(feature = "avif")]
+use image::codecs::avif::AvifEncoder;
+use image::{
+    DynamicImage, ImageError, ImageResult, RgbaImage,
+    codecs::{
+        jpeg::JpegEncoder,
+        png::{PngDecoder, PngEncoder},
+        webp::{WebPDecoder, WebPEncoder},
+    },
+    error::{EncodingError, ImageFormatHint},
+    imageops::{self, FilterType},
+};
+use zune_core::{bit_depth::BitDepth, colorspace::ColorSpace, options::EncoderOptions};
+use zune_jpegxl::JxlSimpleEncoder;
+
+use crate::ugoira::UgoiraFormat;
+
+/// Directory where the original files are saved when [`ImageProcessor::keep_original`] is set.
+pub const ORIGINALS_DIR: &str = "originals";
+
+/// Quality of JPEG and AVIF files, unless set otherwise.
+pub const DEFAULT_QUALITY: u8 = 90;
+
+/// Speed of the AVIF encoder, from 1 (slowest) to 10.
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 6;
+
+pub(crate) const FILTER: FilterType = FilterType::Lanczos3;
+
+/// Aspect ratio buckets, as used to train image models on images of different shapes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Buckets {
+    /// Maximum number of pixels of a bucket, like `1024 * 1024`.
+    pub area: u64,
+    /// Width and height of every bucket are multiples of this, like `64`.
+    pub step: u32,
+}
+
+impl Buckets {
+    /// Size of the bucket with the aspect ratio closest to the one of an image of `width` by `height`.
+    ///
+    /// There is a bucket for every width, with the biggest height that fits the area, along with the biggest
+    /// square one. Buckets are never wider or taller than the image, so it's never upscaled: images smaller than
+    /// [`area`](Self::area) get buckets of their own area, and images smaller than a [`step`](Self::step) keep
+    /// their size.
+    #[must_use]
+    pub fn bucket(&self, width: u32, height: u32) -> (u32, u32) {
+        let step = u64::from(self.step.max(1));
+        let area = self.area.min(u64::from(width) * u64::from(height));
+        let ratio = f64::from(width.max(1)) / f64::from(height.max(1));
+
+        // Biggest sides that are multiples of the step and fit in the image
+        let max_width = u64::from(width) / step * step;
+        let max_height = u64::from(height) / step * step;
+
+        let side = ((area as f64).sqrt() as u64 / step * step)
+            .min(max_width)
+            .min(max_height);
+        let widths = (1..=max_width / step)
+            .map(|steps| steps * step)
+            .map(|bucket_width| {
+                (
+                    bucket_width,
+                    (area / bucket_width / step * step).min(max_height),
+                )
+            })
+            .filter(|(_, bucket_height)| *bucket_height > 0);
+
+        let mut best = (side, side);
+        let mut best_distance = if side > 0 {
+            ratio.ln().abs()
+        } else {
+            f64::INFINITY
+        };
+        for (bucket_width, bucket_height) in widths {
+            let distance = (bucket_width as f64 / bucket_height as f64 / ratio)
+                .ln()
+                .abs();
+            if distance < best_distance {
+                best = (bucket_width, bucket_height);
+                best_distance = distance;
+            }
+        }
+
+        if best_distance.is_infinite() {
+            return (width, height);
+        }
+        (
+            u32::try_from(best.0).unwrap_or(u32::MAX),
+            u32::try_from(best.1).unwrap_or(u32::MAX),
+        )
+    }
+}
+
+/// How an image is fitted into its bucket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Fit {
+    /// Scale the image to cover the bucket and crop its center
+    #[default]
+    Crop,
+    /// Scale the image to fit inside the bucket and pad the borders, with transparent pixels or black if the
+    /// format has no transparency
+    Pad,
+    /// Scale the image to the size of the bucket, slightly changing its aspect ratio
+    Stretch,
+}
+
+impl Fit {
+    fn apply(self, image: &DynamicImage, width: u32, height: u32) -> DynamicImage {
+        match self {
+            Self::Crop => image.resize_to_fill(width, height, FILTER),
+            Self::Stretch => image.resize_exact(width, height, FILTER),
+            Self::Pad => {
+                let resized = image.resize(width, height, FILTER).to_rgba8();
+                let mut canvas = RgbaImage::new(width, height);
+                imageops::overlay(
+                    &mut canvas,
+                    &resized,
+                    i64::from((width - resized.width()) / 2),
+                    i64::from((height - resized.height()) / 2),
+                );
+                DynamicImage::ImageRgba8(canvas)
+            }
+        }
+    }
+}
+
+/// Formats images can be re-encoded into.
+///
+/// WebP and AVIF images are saved with 8 bits per channel, while PNG and JPEG XL ones keep 16 bits if the
+/// original has them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Jpeg,
+    Png,
+    /// Lossless WebP
+    Webp,
+    /// Lossless JPEG XL
+    Jxl,
+    /// Needs the `avif` feature
+    #[cfg(feature = "avif")]
+    Avif,
+}
+
+impl OutputFormat {
+    /// Format of a file with the extension of `name`, if it can be processed. Only JPEG, PNG and WebP images
+    /// can be read.
+    fn of_file(name: &str) -> Option<Self> {
+        let (_, extension) = name.rsplit_once('.')?;
+        match Extension::guess_format(extension) {
+            Extension::JPG => Some(Self::Jpeg),
+            Extension::PNG => Some(Self::Png),
+            Extension::WEBP => Some(Self::Webp),
+            _ => None,
+        }
+    }
+
+    pub const fn extension(self) -> Extension {
+        match self {
+            Self::Jpeg => Extension::JPG,
+            Self::Png => Extension::PNG,
+            Self::Webp => Extension::WEBP,
+            Self::Jxl => Extension::JXL,
+            #[cfg(feature = "avif")]
+            Self::Avif => Extension::AVIF,
+        }
+    }
+
+    /// Animations are saved as served, as only their first frame would be kept.
+    fn is_animated(self, data: &[u8]) -> ImageResult<bool> {
+        match self {
+            Self::Png => PngDecoder::new(Cursor::new(data))?.is_apng(),
+            Self::Webp => Ok(WebPDecoder::new(Cursor::new(data))?.has_animation()),
+            _ => Ok(false),
+        }
+    }
+
+    fn encode(self, image: &DynamicImage, quality: u8) -> ImageResult<Vec<u8>> {
+        let mut data = Vec::new();
+        match self {
+            Self::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(
+                JpegEncoder::new_with_quality(&mut data, quality.clamp(1, 100)),
+            )?,
+            Self::Png => image.write_with_encoder(PngEncoder::new(&mut data))?,
+            Self::Webp => {
+                to_8_bits(image).write_with_encoder(WebPEncoder::new_lossless(&mut data))?;
+            }
+            Self::Jxl => data = encode_jxl(image)?,
+            #[cfg(feature = "avif")]
+            Self::Avif => to_8_bits(image).write_with_encoder(
+                AvifEncoder::new_with_speed_quality(&mut data, AVIF_SPEED, quality.clamp(1, 100)),
+            )?,
+        }
+        Ok(data)
+    }
+}
+
+/// Converts `image` to RGB or RGBA with 8 bits per channel, as needed by most encoders.
+fn to_8_bits(image: &DynamicImage) -> DynamicImage {
+    if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    }
+}
+
+fn encode_jxl(image: &DynamicImage) -> ImageResult<Vec<u8>> {
+    let color = image.color();
+    let colorspace = if color.has_alpha() {
+        ColorSpace::RGBA
+    } else {
+        ColorSpace::RGB
+    };
+
+    let (pixels, depth) = if color.bytes_per_pixel() > color.channel_count() {
+        let pixels = if color.has_alpha() {
+            image.to_rgba16().into_raw()
+        } else {
+            image.to_rgb16().into_raw()
+        };
+        // The encoder reads 16 bit channels in native endian.
+        let pixels = pixels
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        (pixels, BitDepth::Sixteen)
+    } else {
+        (to_8_bits(image).into_bytes(), BitDepth::Eight)
+    };
+
+    let options = EncoderOptions::new(
+        image.width() as usize,
+        image.height() as usize,
+        colorspace,
+        depth,
+    );
+    JxlSimpleEncoder::new(&pixels, options)
+        .encode()
+        .map_err(|error| {
+            ImageError::Encoding(EncodingError::new(
+                ImageFormatHint::Name(String::from("JPEG XL")),
+                format!("{error:?}"),
+            ))
+        })
+}
+
+/// How every image is processed before it's saved.
+///
+/// Images are first downscaled to fit [`max_side`](Self::max_side) and [`max_area`](Self::max_area), keeping
+/// their aspect ratio, then fitted into their bucket, if [`buckets`](Self::buckets) are set. Images are never
+/// upscaled, and the ones that don't need any change are saved as served.
+#[derive(Debug, Clone, Default)]
+pub struct ImageProcessor {
+    /// Maximum width and height, in pixels.
+    pub max_side: Option<u32>,
+    /// Maximum number of pixels.
+    pub max_area: Option<u64>,
+    pub buckets: Option<Buckets>,
+    pub fit: Fit,
+    /// Format images are re-encoded into. If not set, images keep their format.
+    pub format: Option<OutputFormat>,
+    /// Formats of the images re-encoded into [`format`](Self::format), like only PNG to convert lossless
+    /// images. If empty, every image is re-encoded.
+    pub convert_from: Vec<OutputFormat>,
+    /// Quality of JPEG and AVIF files, from 1 to 100. [`DEFAULT_QUALITY`] if not set.
+    pub quality: Option<u8>,
+    /// Converts Ugoira into animations of this format, if the post has the delays of their frames.
+    pub ugoira: Option<UgoiraFormat>,
+    /// Saves the original files as well, inside [`ORIGINALS_DIR`].
+    pub keep_original: bool,
+}
+
+impl ImageProcessor {
+    /// Returns `true` if the file `name` of `post` can be processed, going by its extension.
+    #[must_use]
+    pub fn can_process(&self, post: &Post, name: &str) -> bool {
+        OutputFormat::of_file(name).is_some() || self.ugoira_format(post, name).is_some()
+    }
+
+    /// Name of the file `name` of `post` once it's processed, with the extension of the format it's re-encoded
+    /// into.
+    #[must_use]
+    pub fn processed_name(&self, post: &Post, name: &str) -> String {
+        if let (Some(format), Some((stem, _))) =
+            (self.ugoira_format(post, name), name.rsplit_once('.'))
+        {
+            return format!("{stem}.{}", format.extension());
+        }
+
+        match (OutputFormat::of_file(name), name.rsplit_once('.')) {
+            (Some(served_format), Some((stem, _))) => {
+                let format = self.output_format(served_format);
+                if format == served_format {
+                    return name.to_string();
+                }
+                format!("{stem}.{}", format.extension())
+            }
+            _ => name.to_string(),
+        }
+    }
+
+    /// Format an image served as `served_format` is saved in.
+    fn output_format(&self, served_format: OutputFormat) -> OutputFormat {
+        match self.format {
+            Some(format)
+                if self.convert_from.is_empty() || self.convert_from.contains(&served_format) =>
+            {
+                format
+            }
+            _ => served_format,
+        }
+    }
+
+    /// Format the file `name` of `post` is converted into, if it's an Ugoira with the delays of its frames.
+    fn ugoira_format(&self, post: &Post, name: &str) -> Option<UgoiraFormat> {
+        let format = self.ugoira?;
+        let (_, extension) = name.rsplit_once('.')?;
+        if Extension::guess_format(extension) != Extension::Ugoira {
+            return None;
+        }
+        if post.ugoira_frames.is_empty() {
+            return None;
+        }
+        Some(format)
+    }
+
+    /// Processes the file `name` of `post`, already downloaded into `data`.
+    ///
+    /// Returns the processed file along with its new name, or `None` if the file should be saved as served.
+    pub fn process(
+        &self,
+        post: &Post,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Option<(String, Vec<u8>)>, PostError> {
+        if let Some(format) = self.ugoira_format(post, name) {
+            let data = format.convert(&post.ugoira_frames, data)?;
+            return Ok(Some((self.processed_name(post, name), data)));
+        }
+
+        let Some(served_format) = OutputFormat::of_file(name) else {
+            if self.ugoira.is_some() && post.extension == Extension::Ugoira {
+                warn!(
+                    "Post {} has no frame delays, saving its Ugoira as served",
+                    post.id
+                );
+            }
+            return Ok(None);
+        };
+
+        if served_format.is_animated(data).map_err(process_error)? {
+            return Ok(None);
+        }
+
+        let image = image::load_from_memory(data).map_err(process_error)?;
+        let format = self.output_format(served_format);
+
+        let image = match self.resize(&image) {
+            Some(resized) => resized,
+            None if format == served_format => return Ok(None),
+            None => image,
+        };
+
+        let data = format
+            .encode(&image, self.quality.unwrap_or(DEFAULT_QUALITY))
+            .map_err(process_error)?;
+        Ok(Some((self.processed_name(post, name), data)))
+    }
+
+    /// Resizes `image`, or returns `None` if it already has the right size.
+    fn resize(&self, image: &DynamicImage) -> Option<DynamicImage> {
+        let (width, height) = (image.width(), image.height());
+        let (max_width, max_height) = self.max_size(width, height);
+
+        match self.buckets {
+            Some(buckets) => {
+                let (bucket_width, bucket_height) = buckets.bucket(max_width, max_height);
+                if (bucket_width, bucket_height) == (width, height) {
+                    return None;
+                }
+                Some(self.fit.apply(image, bucket_width, bucket_height))
+            }
+            None if (max_width, max_height) == (width, height) => None,
+            None => Some(image.resize_exact(max_width, max_height, FILTER)),
+        }
+    }
+
+    /// Size of an image of `width` by `height` once downscaled to fit `max_side` and `max_area`.
+    fn max_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let mut scale: f64 = 1.0;
+        if let Some(max_side) = self.max_side {
+            scale = scale.min(f64::from(max_side) / f64::from(width.max(height).max(1)));
+        }
+        if let Some(max_area) = self.max_area {
+            let area = f64::from(width) * f64::from(height);
+            scale = scale.min((max_area as f64 / area.max(1.0)).sqrt());
+        }
+
+        if scale >= 1.0 {
+            return (width, height);
+        }
+        let scaled = |size: u32| ((f64::from(size) * scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+}
+
+fn process_error(error: ImageError) -> PostError {
+    PostError::ProcessFail {
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{GenericImageView, RgbImage};
+
+    use super::*;
+    use crate::test::{md5_of, post};
+
+    const BUCKETS: Buckets = Buckets {
+        area: 1024 * 1024,
+        step: 64,
+    };
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        OutputFormat::Png
+            .encode(
+                &DynamicImage::ImageRgb8(RgbImage::new(width, height)),
+                DEFAULT_QUALITY,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn buckets_keep_the_aspect_ratio() {
+        assert_eq!(BUCKETS.bucket(1024, 1024), (1024, 1024));
+        assert_eq!(BUCKETS.bucket(4096, 4096), (1024, 1024));
+        assert_eq!(BUCKETS.bucket(2048, 1024), (1408, 704));
+        assert_eq!(BUCKETS.bucket(1024, 2048), (704, 1472));
+    }
+
+    #[test]
+    fn buckets_never_upscale() {
+        // Smaller than the area
+        assert_eq!(BUCKETS.bucket(500, 300), (448, 256));
+        // A bucket of the same area and aspect ratio would be taller than the image
+        assert_eq!(BUCKETS.bucket(100, 1000), (64, 960));
+        // Smaller than a step
+        assert_eq!(BUCKETS.bucket(30, 30), (30, 30));
+        assert_eq!(BUCKETS.bucket(2000, 40), (2000, 40));
+
+        for (width, height) in [(65, 4000), (3000, 200), (777, 555), (1500, 1499)] {
+            let (bucket_width, bucket_height) = BUCKETS.bucket(width, height);
+            assert!(bucket_width <= width && bucket_height <= height);
+            assert_eq!(bucket_width % 64, 0);
+            assert_eq!(bucket_height % 64, 0);
+            assert!(u64::from(bucket_width) * u64::from(bucket_height) <= BUCKETS.area);
+        }
+    }
+
+    #[test]
+    fn max_size_downscales_keeping_the_aspect_ratio() {
+        let processor = ImageProcessor {
+            max_side: Some(1000),
+            ..Default::default()
+        };
+        assert_eq!(processor.max_size(2000, 1000), (1000, 500));
+        assert_eq!(processor.max_size(500, 4000), (125, 1000));
+        assert_eq!(processor.max_size(800, 600), (800, 600));
+
+        let processor = ImageProcessor {
+            max_area: Some(500 * 500),
+            ..Default::default()
+        };
+        assert_eq!(processor.max_size(1000, 1000), (500, 500));
+        assert_eq!(processor.max_size(100, 100), (100, 100));
+    }
+
+    #[test]
+    fn images_are_resized_into_their_bucket() {
+        let processor = ImageProcessor {
+            buckets: Some(Buckets {
+                area: 64 * 64 * 2,
+                step: 64,
+            }),
+            ..Default::default()
+        };
+        let post = post(1, &md5_of(1));
+
+        let (name, data) = processor
+            .process(&post, "1.png", &png(200, 100))
+            .unwrap()
+            .unwrap();
+        assert_eq!(name, "1.png");
+        assert_eq!(
+            image::load_from_memory(&data).unwrap().dimensions(),
+            (128, 64)
+        );
+
+        // Already the size of its bucket
+        assert!(
+            processor
+                .process(&post, "1.png", &png(128, 64))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn images_are_converted_without_resizing() {
+        let processor = ImageProcessor {
+            format: Some(OutputFormat::Webp),
+            convert_from: vec![OutputFormat::Png],
+            ..Default::default()
+        };
+        let post = post(1, &md5_of(1));
+
+        assert!(processor.can_process(&post, "1.png"));
+        assert!(!processor.can_process(&post, "1.mp4"));
+        assert_eq!(processor.processed_name(&post, "1.jpg"), "1.jpg");
+
+        let (name, data) = processor
+            .process(&post, "1.png", &png(10, 20))
+            .unwrap()
+            .unwrap();
+        assert_eq!(name, "1.webp");
+        assert_eq!(
+            image::load_from_memory(&data).unwrap().dimensions(),
+            (10, 20)
+        );
+    }
+}
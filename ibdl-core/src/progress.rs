@@ -1,5 +1,8 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+
+use crate::events::{Event, EventListener, SharedEventListener};
 
 /// Trait for reporting overall progress, typically for a collection of items (e.g., posts).
 /// All methods should be thread-safe.
@@ -88,3 +91,88 @@ pub type SharedProgressListener = Arc<dyn ProgressListener>;
 pub fn no_op_progress_listener() -> SharedProgressListener {
     Arc::new(NoOpProgressListener)
 }
+
+/// Adapter that drives a [`ProgressListener`] with the [`Event`]s emitted by the queue.
+#[derive(Debug)]
+pub struct ProgressEvents {
+    listener: SharedProgressListener,
+    tasks: Mutex<HashMap<u64, Box<dyn DownloadProgressUpdater>>>,
+}
+
+impl ProgressEvents {
+    pub fn new(listener: SharedProgressListener) -> Self {
+        Self {
+            listener,
+            tasks: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn finish_task(&self, post_id: u64) {
+        let task = self.tasks.lock().unwrap().remove(&post_id);
+        if let Some(task) = task {
+            task.finish();
+        }
+    }
+}
+
+impl EventListener for ProgressEvents {
+    fn on_event(&self, event: Event) {
+        match event {
+            Event::PostsQueued(delta) => self.listener.inc_main_total(delta),
+            Event::PostReceived { .. } => self.listener.main_tick(),
+            Event::DownloadStarted {
+                post_id,
+                file_name,
+                size,
+            } => {
+                let task = self.listener.add_download_task(file_name, size);
+                self.tasks.lock().unwrap().insert(post_id, task);
+            }
+            Event::DownloadProgress {
+                post_id,
+                downloaded,
+            } => {
+                if let Some(task) = self.tasks.lock().unwrap().get(&post_id) {
+                    task.set_progress(downloaded);
+                }
+            }
+            Event::DownloadFinished { post_id, .. } => self.finish_task(post_id),
+            Event::DownloadRetried {
+                post_id,
+                file_name,
+                error,
+            } => {
+                self.finish_task(post_id);
+                self.listener
+                    .log_skip_message(&file_name, &format!("{error}, downloading it again"));
+            }
+            Event::DownloadFailed {
+                post_id,
+                file_name,
+                error,
+            } => {
+                self.finish_task(post_id);
+                self.listener
+                    .log_skip_message(&file_name, &format!("failed: {error}"));
+            }
+            Event::FileSkipped {
+                file_name, reason, ..
+            } => self
+                .listener
+                .log_skip_message(&file_name, &reason.to_string()),
+            Event::FileReplaced {
+                file_name, removed, ..
+            } => self.listener.log_skip_message(
+                &file_name,
+                &format!("removed existing file {removed} (MD5 mismatch), will redownload"),
+            ),
+            Event::Finished => self.listener.main_done(),
+            Event::Extractor(_) => {}
+        }
+    }
+}
+
+/// Wraps a [`ProgressListener`] so it can be used as the event listener of a [`Queue`](crate::async_queue::Queue).
+pub fn progress_events(listener: SharedProgressListener) -> SharedEventListener {
+    Arc::new(ProgressEvents::new(listener))
+}
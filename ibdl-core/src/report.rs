@@ -0,0 +1,128 @@
+//! Results of a download run.
+//!
+//! Every post that fails to download is kept in the [`DownloadReport`] together with the reason,
+//! so it can be shown to the user and saved with [`DownloadReport::write_failed_list`].
+//! The saved list can be read back with [`read_post_list`](crate::export::read_post_list) to retry only those posts.
+use std::{collections::BTreeMap, path::Path, time::Duration};
+
+use ibdl_common::{
+    post::{error::PostError, Post},
+    serde::{Deserialize, Serialize},
+    serde_json,
+    tokio::{
+        fs::{create_dir_all, remove_file, File},
+        io::{AsyncWriteExt, BufWriter},
+    },
+};
+
+use crate::{error::QueueError, stats::TagStats};
+
+/// A post that couldn't be downloaded and the reason why.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "ibdl_common::serde")]
+pub struct FailedPost {
+    pub post: Post,
+    /// Short name of the error, as returned by [`PostError::kind`].
+    pub kind: String,
+    /// HTTP status returned by the file server, if any.
+    pub status: Option<u16>,
+    pub message: String,
+}
+
+impl FailedPost {
+    pub fn new(post: Post, error: &PostError) -> Self {
+        Self {
+            post,
+            kind: error.kind().to_string(),
+            status: error.status(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Returns `true` if the download was interrupted by a cancellation instead of failing.
+    pub fn is_cancelled(&self) -> bool {
+        self.kind == PostError::Cancelled.kind()
+    }
+}
+
+/// Summary of a finished download run.
+#[derive(Debug, Default)]
+pub struct DownloadReport {
+    /// Number of posts successfully downloaded.
+    pub downloaded: u64,
+    /// Every post that failed to download.
+    pub failed: Vec<FailedPost>,
+    /// `true` if the run was cancelled before all posts were processed.
+    pub cancelled: bool,
+    /// Posts that were not downloaded because the run was cancelled.
+    pub pending: Vec<Post>,
+    /// Tags, ratings and extensions of every saved post, if [`Queue::tag_stats`](crate::async_queue::Queue::tag_stats) is set.
+    pub tag_stats: Option<TagStats>,
+}
+
+/// Summary of a whole [`Session`](crate::session::Session), from the search to the last download.
+#[derive(Debug, Default)]
+pub struct SessionReport {
+    /// Downloaded, failed and pending posts, as reported by the queue.
+    pub download: DownloadReport,
+    /// Posts not downloaded because the same file was already saved.
+    pub skipped: u64,
+    /// Posts removed by the blacklist or the rating and extension filters.
+    pub filtered: u64,
+    /// Total size of the downloaded files.
+    pub bytes: u64,
+    /// Time from the start of the search until the last download finished.
+    pub duration: Duration,
+    /// Posts sent to the queue by the post source. Pending posts of a resumed job are not included.
+    pub queued: u64,
+    /// Page that continues the search right after the last fetched one, if the search was cancelled.
+    pub cursor: Option<u16>,
+}
+
+impl DownloadReport {
+    /// Groups all failures by reason. Failures with an HTTP status are grouped by status too.
+    pub fn failures_by_reason(&self) -> BTreeMap<String, Vec<&FailedPost>> {
+        let mut groups: BTreeMap<String, Vec<&FailedPost>> = BTreeMap::new();
+
+        for failed in &self.failed {
+            let reason = failed.status.map_or_else(
+                || failed.kind.clone(),
+                |status| format!("{} ({})", failed.kind, status),
+            );
+            groups.entry(reason).or_default().push(failed);
+        }
+
+        groups
+    }
+
+    /// Writes all failed posts to `path`, one JSON object per line.
+    ///
+    /// If there are no failures, a list left by a previous run in the same path is removed instead.
+    pub async fn write_failed_list(&self, path: &Path) -> Result<(), QueueError> {
+        if self.failed.is_empty() {
+            if path.is_file() {
+                remove_file(path).await?;
+            }
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).await?;
+        }
+
+        let mut list_file = BufWriter::new(File::create(path).await?);
+
+        for failed in &self.failed {
+            let mut line =
+                serde_json::to_string(failed).map_err(|error| QueueError::PostListEncodeFail {
+                    error: error.to_string(),
+                })?;
+            line.push('\n');
+
+            list_file.write_all(line.as_bytes()).await?;
+        }
+
+        list_file.flush().await?;
+        Ok(())
+    }
+}
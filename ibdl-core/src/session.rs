@@ -0,0 +1,495 @@
+//! High-level API that runs a whole job, from finding the posts to the last download.
+//!
+//! A [`Session`] connects a [`PostSource`] to a [`Queue`] with the channels, counters and event
+//! listeners needed in between, and returns a [`SessionReport`] once everything is done.
+//!
+//! ```no_run
+//! use ibdl_common::post::NameType;
+//! use ibdl_core::async_queue::QueueOpts;
+//! use ibdl_core::session::{OutputOptions, PostSource, Session};
+//! use ibdl_extractors::extractor_config::DEFAULT_SERVERS;
+//! use ibdl_extractors::prelude::*;
+//! # async fn run() -> Result<(), ibdl_core::error::SessionError> {
+//! let config = DEFAULT_SERVERS.get("danbooru").unwrap().clone();
+//!
+//! let search = ExtractorBuilder::new(config.clone())
+//!     .tags(&["umbreon"])
+//!     .limit(Some(20))
+//!     .build()
+//!     .await?;
+//!
+//! let options = OutputOptions {
+//!     queue: QueueOpts {
+//!         save_as_cbz: false,
+//!         pool_download: false,
+//!         name_type: NameType::ID,
+//!         annotate: false,
+//!     },
+//!     simultaneous_downloads: 3,
+//!     channel_size: Some(64),
+//! };
+//!
+//! let report = Session::new(config, PostSource::Search(search), options)
+//!     .run("umbreon".into())
+//!     .await?;
+//!
+//! println!("{} files downloaded in {:?}", report.download.downloaded, report.duration);
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering},
+};
+use std::time::Instant;
+
+use ibdl_common::{
+    client,
+    post::{
+        Post,
+        channel::{PostReceiver, post_channel},
+    },
+    reqwest::Client,
+    tokio::{join, spawn, sync::mpsc::channel, task::JoinHandle},
+    tokio_util::sync::CancellationToken,
+};
+use ibdl_extractors::{
+    error::ExtractorError,
+    extractor_config::ServerConfig,
+    prelude::{
+        AnyExtractor, ConfiguredExtractor, EmitEvents, ExtractorThreadHandle, PoolInfo,
+        PostFetchAsync, PostFetchMethod,
+    },
+};
+
+#[cfg(feature = "image")]
+use crate::process::ImageProcessor;
+#[cfg(feature = "cbz")]
+use crate::sink::{ExistingArchive, VolumeLimits, ZipCompression};
+use crate::{
+    async_queue::{Queue, QueueOpts},
+    caption::CaptionConfig,
+    error::{QueueError, SessionError},
+    events::{
+        Event, EventListener, ExtractorEvent, SharedEventListener, extractor_listener,
+        no_op_event_listener,
+    },
+    export::ExportFormat,
+    report::{DownloadReport, SessionReport},
+    sink::StorageSink,
+};
+
+/// Where the posts of a session come from.
+pub enum PostSource {
+    /// Posts found by an extractor set up with [`ExtractorBuilder`](ibdl_extractors::prelude::ExtractorBuilder).
+    Search(ConfiguredExtractor),
+    /// Specific posts, fetched by id.
+    Posts {
+        extractor: AnyExtractor,
+        ids: Vec<u32>,
+    },
+    /// Posts that were already fetched, like a list read with [`read_post_list`](crate::export::read_post_list).
+    ///
+    /// `filtered` is the number of posts removed from the list beforehand, reported as [`SessionReport::filtered`].
+    List { posts: Vec<Post>, filtered: u64 },
+}
+
+/// How the posts of a session are saved.
+#[derive(Debug, Clone, Copy)]
+pub struct OutputOptions {
+    pub queue: QueueOpts,
+    /// Number of files downloaded at the same time.
+    pub simultaneous_downloads: u8,
+    /// Number of posts that can wait in the queue before the search is paused. `None` never pauses it.
+    pub channel_size: Option<usize>,
+}
+
+/// A whole download job: a post source, the queue that saves the posts and everything in between.
+///
+/// The event listener of the extractor is replaced by the one of the session, which forwards all events
+/// to the listener set with [`events`](Self::events).
+pub struct Session {
+    server: ServerConfig,
+    source: PostSource,
+    options: OutputOptions,
+    events: SharedEventListener,
+    cancel: CancellationToken,
+    pending: Vec<Post>,
+    resumed: bool,
+    query: Vec<String>,
+    pool: Option<PoolInfo>,
+    caption: CaptionConfig,
+    tag_stats: bool,
+    #[cfg(feature = "cbz")]
+    volumes: VolumeLimits,
+    #[cfg(feature = "cbz")]
+    compression: ZipCompression,
+    #[cfg(feature = "cbz")]
+    existing_archive: ExistingArchive,
+    #[cfg(feature = "image")]
+    processor: Option<ImageProcessor>,
+}
+
+impl Session {
+    #[must_use]
+    pub fn new(server: ServerConfig, source: PostSource, options: OutputOptions) -> Self {
+        Self {
+            server,
+            source,
+            options,
+            events: no_op_event_listener(),
+            cancel: CancellationToken::new(),
+            pending: Vec::new(),
+            resumed: false,
+            query: Vec::new(),
+            pool: None,
+            caption: CaptionConfig::default(),
+            tag_stats: false,
+            #[cfg(feature = "cbz")]
+            volumes: VolumeLimits::default(),
+            #[cfg(feature = "cbz")]
+            compression: ZipCompression::default(),
+            #[cfg(feature = "cbz")]
+            existing_archive: ExistingArchive::default(),
+            #[cfg(feature = "image")]
+            processor: None,
+        }
+    }
+
+    /// Listener that receives the events of both the extractor and the queue.
+    #[must_use]
+    pub fn events(mut self, listener: SharedEventListener) -> Self {
+        self.events = listener;
+        self
+    }
+
+    /// Describes what is being downloaded, like the searched tags. See [`Queue::query`].
+    #[must_use]
+    pub fn query(mut self, query: Vec<String>) -> Self {
+        self.query = query;
+        self
+    }
+
+    /// Info of the pool being downloaded. See [`Queue::pool_info`].
+    #[must_use]
+    pub fn pool_info(mut self, pool: Option<PoolInfo>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Which tags are written into the caption of every post. See [`Queue::caption`].
+    #[must_use]
+    pub fn caption(mut self, config: CaptionConfig) -> Self {
+        self.caption = config;
+        self
+    }
+
+    /// Counts the tags of every saved post. See [`Queue::tag_stats`].
+    #[must_use]
+    pub const fn tag_stats(mut self, enabled: bool) -> Self {
+        self.tag_stats = enabled;
+        self
+    }
+
+    /// Limits of each CBZ archive when they are split into volumes. See [`Queue::volumes`].
+    #[cfg(feature = "cbz")]
+    #[must_use]
+    pub const fn volumes(mut self, limits: VolumeLimits) -> Self {
+        self.volumes = limits;
+        self
+    }
+
+    /// How files are compressed inside CBZ archives. See [`Queue::compression`].
+    #[cfg(feature = "cbz")]
+    #[must_use]
+    pub const fn compression(mut self, compression: ZipCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Whether an existing CBZ archive is appended to or replaced. See [`Queue::existing_archive`].
+    #[cfg(feature = "cbz")]
+    #[must_use]
+    pub const fn existing_archive(mut self, existing: ExistingArchive) -> Self {
+        self.existing_archive = existing;
+        self
+    }
+
+    /// Resizes and re-encodes every image before it's saved. See [`Queue::process`].
+    #[cfg(feature = "image")]
+    #[must_use]
+    pub fn process(mut self, processor: Option<ImageProcessor>) -> Self {
+        self.processor = processor;
+        self
+    }
+
+    /// Continues an interrupted job, downloading the posts it left pending along with the new ones.
+    ///
+    /// A search that doesn't find any post is not an error for a resumed job, as the saved cursor
+    /// can point past the last page.
+    #[must_use]
+    pub fn resume(mut self, pending: Vec<Post>) -> Self {
+        self.pending = pending;
+        self.resumed = true;
+        self
+    }
+
+    /// Token that stops the session. Posts not downloaded yet are returned in [`DownloadReport::pending`](crate::report::DownloadReport::pending).
+    #[must_use]
+    pub fn cancel_token(&self) -> CancellationToken {
+        self.cancel.clone()
+    }
+
+    /// Downloads all posts into `output_dir`, or into the CBZ file at `output_dir` if
+    /// [`QueueOpts::save_as_cbz`] is set.
+    pub async fn run(self, output_dir: PathBuf) -> Result<SessionReport, SessionError> {
+        self.download(|queue, posts, cancel| {
+            queue.setup_async_downloader(output_dir, posts, cancel)
+        })
+        .await
+    }
+
+    /// Downloads all posts into `sink` instead of the local filesystem.
+    pub async fn run_with_sink<S: StorageSink>(
+        self,
+        sink: S,
+    ) -> Result<SessionReport, SessionError> {
+        self.download(|queue, posts, cancel| queue.setup_sink_downloader(sink, posts, cancel))
+            .await
+    }
+
+    async fn download(
+        self,
+        setup: impl FnOnce(
+            Queue,
+            PostReceiver,
+            CancellationToken,
+        ) -> JoinHandle<Result<DownloadReport, QueueError>>,
+    ) -> Result<SessionReport, SessionError> {
+        let started_at = Instant::now();
+        let tracker = Arc::new(SessionTracker::new(self.events.clone()));
+        let cancel = self.cancel.clone();
+        let resumed = self.resumed;
+
+        let job = self.start(tracker.clone());
+        let downloader = setup(job.queue, job.posts, cancel);
+
+        let (filtered, download, queued) = join!(job.extractor, downloader, job.queued);
+
+        let download = download??;
+        let filtered = match filtered? {
+            Err(ExtractorError::ZeroPosts) if resumed => 0,
+            filtered => filtered?,
+        };
+
+        Ok(SessionReport {
+            download,
+            skipped: tracker.skipped.load(Ordering::SeqCst),
+            filtered,
+            bytes: tracker.bytes.load(Ordering::SeqCst),
+            duration: started_at.elapsed(),
+            queued: queued?,
+            cursor: tracker.cursor(job.start_page),
+        })
+    }
+
+    /// Writes all posts to a list in `format` instead of downloading them. See [`Queue::setup_async_exporter`].
+    ///
+    /// Returns the number of exported posts.
+    pub async fn export(
+        self,
+        format: ExportFormat,
+        list_path: PathBuf,
+        output_dir: PathBuf,
+    ) -> Result<u64, SessionError> {
+        let events = self.events.clone();
+
+        let job = self.start(events);
+        let exporter = job
+            .queue
+            .setup_async_exporter(format, list_path, output_dir, job.posts);
+
+        let (filtered, exported, _) = join!(job.extractor, exporter, job.queued);
+
+        filtered??;
+        Ok(exported??)
+    }
+
+    /// Spawns the post source and sets up the queue that receives its posts.
+    fn start(self, events: SharedEventListener) -> StartedJob {
+        let (posts_sender, posts) = post_channel(self.options.channel_size);
+        let (length_sender, mut length_receiver) = channel::<u64>(1);
+
+        let queued = spawn({
+            let events = events.clone();
+            async move {
+                let mut sent = 0;
+                while let Some(total_posts) = length_receiver.recv().await {
+                    sent += total_posts;
+                    events.on_event(Event::PostsQueued(total_posts));
+                }
+                sent
+            }
+        });
+
+        if !self.pending.is_empty() {
+            // Pending posts are sent alongside the new ones, as the channel may be too small to hold them all
+            events.on_event(Event::PostsQueued(self.pending.len() as u64));
+            let pending = self.pending;
+            let pending_sender = posts_sender.clone();
+            spawn(async move {
+                for post in pending {
+                    if pending_sender.send(post).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let (extractor, client, start_page) = match self.source {
+            PostSource::Search(mut search) => {
+                search
+                    .extractor
+                    .set_event_listener(extractor_listener(events.clone()));
+                let client = search.extractor.client();
+                let start_page = search.start_page;
+
+                let handle =
+                    search.setup_fetch_thread(posts_sender, Some(length_sender), self.cancel);
+                (handle, client, start_page)
+            }
+            PostSource::Posts { mut extractor, ids } => {
+                extractor.set_event_listener(extractor_listener(events.clone()));
+                let client = extractor.client();
+
+                let handle = extractor.setup_async_post_fetch(
+                    posts_sender,
+                    PostFetchMethod::Multiple(ids),
+                    length_sender,
+                    self.cancel,
+                );
+                (handle, client, None)
+            }
+            PostSource::List { posts, filtered } => {
+                let handle: ExtractorThreadHandle = spawn(async move {
+                    length_sender.send(posts.len() as u64).await?;
+
+                    for post in posts {
+                        posts_sender.send(post).await?;
+                    }
+
+                    Ok(filtered)
+                });
+                (handle, client!(self.server), None)
+            }
+        };
+
+        let queue = Queue::new(
+            self.server,
+            self.options.simultaneous_downloads,
+            Some(client),
+            self.options.queue,
+            Some(events),
+        )
+        .query(self.query)
+        .pool_info(self.pool)
+        .caption(self.caption)
+        .tag_stats(self.tag_stats);
+        #[cfg(feature = "cbz")]
+        let queue = queue
+            .volumes(self.volumes)
+            .compression(self.compression)
+            .existing_archive(self.existing_archive);
+        #[cfg(feature = "image")]
+        let queue = queue.process(self.processor);
+
+        StartedJob {
+            queue,
+            posts,
+            extractor,
+            queued,
+            start_page,
+        }
+    }
+}
+
+struct StartedJob {
+    queue: Queue,
+    posts: PostReceiver,
+    extractor: ExtractorThreadHandle,
+    /// Returns the number of posts announced by the post source.
+    queued: JoinHandle<u64>,
+    start_page: Option<u16>,
+}
+
+/// Counts what happened during a session. All events are forwarded to the listener of the session.
+#[derive(Debug)]
+struct SessionTracker {
+    skipped: AtomicU64,
+    bytes: AtomicU64,
+    /// Bytes downloaded so far by each running download.
+    progress: Mutex<HashMap<u64, u64>>,
+    pages: AtomicU16,
+    cancelled: AtomicBool,
+    forward: SharedEventListener,
+}
+
+impl SessionTracker {
+    fn new(forward: SharedEventListener) -> Self {
+        Self {
+            skipped: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            progress: Mutex::new(HashMap::new()),
+            pages: AtomicU16::new(0),
+            cancelled: AtomicBool::new(false),
+            forward,
+        }
+    }
+
+    /// Returns the start page that continues the search right after the last fetched page.
+    ///
+    /// Returns `None` if the extractor wasn't cancelled, since it already went through all pages.
+    fn cursor(&self, start_page: Option<u16>) -> Option<u16> {
+        self.cancelled.load(Ordering::SeqCst).then(|| {
+            start_page
+                .unwrap_or(0)
+                .saturating_add(self.pages.load(Ordering::SeqCst))
+        })
+    }
+}
+
+impl EventListener for SessionTracker {
+    fn on_event(&self, event: Event) {
+        match &event {
+            Event::Extractor(ExtractorEvent::PageFetched { .. }) => {
+                self.pages.fetch_add(1, Ordering::SeqCst);
+            }
+            Event::Extractor(ExtractorEvent::Cancelled) => {
+                self.cancelled.store(true, Ordering::SeqCst);
+            }
+            Event::DownloadProgress {
+                post_id,
+                downloaded,
+            } => {
+                self.progress.lock().unwrap().insert(*post_id, *downloaded);
+            }
+            Event::DownloadFinished { post_id, .. } => {
+                let size = self.progress.lock().unwrap().remove(post_id);
+                if let Some(size) = size {
+                    self.bytes.fetch_add(size, Ordering::SeqCst);
+                }
+            }
+            Event::DownloadRetried { post_id, .. } | Event::DownloadFailed { post_id, .. } => {
+                self.progress.lock().unwrap().remove(post_id);
+            }
+            Event::FileSkipped { .. } => {
+                self.skipped.fetch_add(1, Ordering::SeqCst);
+            }
+            _ => {}
+        }
+
+        self.forward.on_event(event);
+    }
+}
@@ -0,0 +1,109 @@
+//! Helpers shared by the sinks that save files into a single archive.
+use std::{
+    io::{Cursor, Read, SeekFrom},
+    path::PathBuf,
+};
+
+use ibdl_common::{
+    log::debug,
+    post::{Post, error::PostError, rating::Rating},
+    tokio::{
+        fs,
+        io::{AsyncSeekExt, AsyncWriteExt, BufWriter},
+    },
+};
+use tempfile::tempfile_in;
+
+/// Files bigger than this are spooled to a temporary file until they are written into the archive.
+const SPOOL_THRESHOLD: usize = 1024 * 1024;
+
+/// One directory is created for each rating when files are saved by rating.
+pub(super) const RATING_DIRS: [Rating; 4] = [
+    Rating::Safe,
+    Rating::Questionable,
+    Rating::Explicit,
+    Rating::Unknown,
+];
+
+/// Path of the file `name` of `post` inside the archive. With `by_rating`, it's saved in the directory of its
+/// rating, otherwise at the root, as pages of a pool.
+pub(super) fn entry_name(post: &Post, name: &str, by_rating: bool) -> String {
+    if by_rating {
+        format!("{}/{}", post.rating, name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Keeps the data of a file being downloaded until it's written into an archive.
+///
+/// Small files are kept in memory and bigger ones are spooled to a temporary file, so memory usage doesn't
+/// grow with the size of the files.
+#[derive(Debug)]
+pub(super) struct Spool {
+    data: SpoolData,
+    /// Where the temporary file is created.
+    dir: PathBuf,
+    /// Number of bytes written so far.
+    len: u64,
+}
+
+#[derive(Debug)]
+enum SpoolData {
+    Memory(Vec<u8>),
+    /// An unnamed temporary file, removed as soon as it's closed.
+    File(BufWriter<fs::File>),
+}
+
+impl Spool {
+    pub const fn new(dir: PathBuf) -> Self {
+        Self {
+            data: SpoolData::Memory(Vec::new()),
+            dir,
+            len: 0,
+        }
+    }
+
+    /// A spool holding a small file that is already in memory.
+    pub const fn from_vec(data: Vec<u8>, dir: PathBuf) -> Self {
+        Self {
+            len: data.len() as u64,
+            data: SpoolData::Memory(data),
+            dir,
+        }
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), PostError> {
+        self.len += chunk.len() as u64;
+
+        match &mut self.data {
+            SpoolData::Memory(data) if data.len() + chunk.len() > SPOOL_THRESHOLD => {
+                debug!(
+                    "Spooling file to a temporary file in {}",
+                    self.dir.display()
+                );
+                let mut file = BufWriter::new(fs::File::from_std(tempfile_in(&self.dir)?));
+                file.write_all(data).await?;
+                file.write_all(chunk).await?;
+                self.data = SpoolData::File(file);
+            }
+            SpoolData::Memory(data) => data.extend_from_slice(chunk),
+            SpoolData::File(file) => file.write_all(chunk).await?,
+        }
+        Ok(())
+    }
+
+    /// Returns a reader over everything written so far, along with its length.
+    pub async fn into_reader(self) -> Result<(Box<dyn Read + Send>, u64), PostError> {
+        let reader: Box<dyn Read + Send> = match self.data {
+            SpoolData::Memory(data) => Box::new(Cursor::new(data)),
+            SpoolData::File(mut file) => {
+                file.flush().await?;
+                let mut file = file.into_inner();
+                file.seek(SeekFrom::Start(0)).await?;
+                Box::new(file.into_std().await)
+            }
+        };
+        Ok((reader, self.len))
+    }
+}
@@ -0,0 +1,596 @@
+use std::{
+    collections::HashSet,
+    io::Read,
+    mem,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use ibdl_common::{
+    post::{Post, error::PostError, rating::Rating, tags::Tag},
+    serde::{Deserialize, Serialize},
+    tokio::{
+        fs::{create_dir_all, remove_file},
+        task::spawn_blocking,
+    },
+};
+use ibdl_extractors::prelude::PoolInfo;
+use zip::{CompressionMethod, write::SimpleFileOptions};
+
+pub use self::volume::VolumeLimits;
+use self::volume::{Volume, VolumeSettings, read_summary_file, split_pool};
+use super::{
+    Existing, SinkItem, StorageSink,
+    archive::{Spool, entry_name},
+};
+use crate::{error::QueueError, events::SkipReason};
+
+mod volume;
+
+/// Name of the [`CbzSummary`] saved at the root of every archive.
+pub const SUMMARY_FILE: &str = "00_summary.json";
+
+/// Describes the posts saved inside a CBZ archive.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(crate = "ibdl_common::serde")]
+pub struct CbzSummary {
+    /// Name of the imageboard, as used by `--imageboard`.
+    pub server: String,
+    /// The command and its arguments used to find the posts, as in [`JobState::query`](crate::job::JobState::query).
+    pub query: Vec<String>,
+    /// When the archive was last written, in RFC 3339 format.
+    pub download_date: String,
+    /// Every post saved inside the archive, including the ones added by previous runs.
+    pub posts: Vec<SummaryPost>,
+    /// Number of the archive when the output is split into volumes, starting at 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<usize>,
+}
+
+/// How downloaded files are compressed inside the archive.
+///
+/// Small files that aren't posts, like captions, are always deflated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ZipCompression {
+    /// Files are saved as they are, as expected from CBZ archives. Most images are already compressed.
+    #[default]
+    Stored,
+    /// Files are deflated with a level from 0 to 9, or the default level if `None`.
+    Deflated { level: Option<i64> },
+}
+
+impl ZipCompression {
+    fn options(self) -> SimpleFileOptions {
+        match self {
+            Self::Stored => {
+                SimpleFileOptions::default().compression_method(CompressionMethod::Stored)
+            }
+            Self::Deflated { level } => SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(level),
+        }
+    }
+}
+
+/// What to do with an archive saved by a previous run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExistingArchive {
+    /// Keep its files and skip the posts listed in its summary.
+    #[default]
+    Append,
+    /// Replace it with a new archive. Existing volumes are removed.
+    Replace,
+}
+
+/// A post saved inside a CBZ archive.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "ibdl_common::serde")]
+pub struct SummaryPost {
+    pub id: u64,
+    /// Path of the file inside the archive.
+    pub file: String,
+    pub md5: String,
+    pub rating: Rating,
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug)]
+struct Archive {
+    volume: Volume,
+    settings: VolumeSettings,
+    /// MD5 hashes of the posts in the summaries of every volume.
+    saved: HashSet<String>,
+    /// Number of the next volume to be opened.
+    next_volume: usize,
+}
+
+impl Archive {
+    /// Writes a file into the current volume. If a post doesn't fit into it, the volume is finished and the
+    /// post is written into a new one.
+    fn add(
+        &mut self,
+        entry: String,
+        options: SimpleFileOptions,
+        data: &mut dyn Read,
+        size: u64,
+        post: Option<SummaryPost>,
+    ) -> Result<(), QueueError> {
+        // Pools are only split once every page is downloaded.
+        if post.is_some() && self.settings.by_rating && self.settings.is_full(&self.volume, size) {
+            let number = self.next_volume;
+            self.next_volume += 1;
+
+            let next = Volume::open(
+                self.settings.volume_path(number),
+                Some(number),
+                &self.settings,
+            )?;
+            mem::replace(&mut self.volume, next).finish(self.settings.pool.as_ref())?;
+        }
+
+        if let Some(post) = &post {
+            self.saved.insert(post.md5.clone());
+        }
+        self.volume.add(entry, options, data, size, post)
+    }
+}
+
+/// Saves every file into a CBZ archive.
+///
+/// Files are only written into the archive once they are fully downloaded, so an interrupted download leaves
+/// nothing behind and the archive is still finalized when the run is cancelled. Until then, small files are
+/// kept in memory and bigger ones are spooled to temporary files next to the archive, so memory usage doesn't
+/// grow with the size of the files.
+///
+/// The archive is written next to its final path with a `.part` extension and only moved into place by
+/// [`finish`](StorageSink::finish), along with its [`CbzSummary`].
+///
+/// With [`VolumeLimits`], the output is split into numbered volumes, like `name_001.cbz`, each one with its
+/// own summary.
+#[derive(Debug)]
+pub struct CbzSink {
+    archive: Arc<Mutex<Archive>>,
+    /// Where big files are spooled while they are downloaded.
+    spool_dir: PathBuf,
+    by_rating: bool,
+    compression: ZipCompression,
+}
+
+impl CbzSink {
+    /// Creates the archive at `path`. `server` and `query` are saved in its summary.
+    ///
+    /// If the archive already exists and `existing` is [`ExistingArchive::Append`], its files are kept and new
+    /// posts are appended to it. Posts listed in its summary are skipped. Archives without a summary, like the
+    /// ones saved by older versions, get one rebuilt from their files.
+    ///
+    /// With `by_rating`, files are saved in one directory for each rating. Otherwise, they are all saved at
+    /// the root of the archive, as pages of a pool.
+    ///
+    /// If `limits` are set, posts are saved into volumes instead. New posts are appended to the last existing
+    /// volume, while the pages of a pool, including the ones in existing volumes, are split again in page order
+    /// once all of them are downloaded. Posts saved in any existing volume are skipped.
+    pub async fn create(
+        path: PathBuf,
+        by_rating: bool,
+        server: String,
+        query: Vec<String>,
+        limits: VolumeLimits,
+        existing: ExistingArchive,
+    ) -> Result<Self, QueueError> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .await
+                .map_err(|error| QueueError::DirCreationError {
+                    message: error.to_string(),
+                })?;
+        }
+
+        let spool_dir = path
+            .parent()
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+
+        let settings = VolumeSettings {
+            path,
+            by_rating,
+            server,
+            query,
+            limits,
+            pool: None,
+            replace: existing == ExistingArchive::Replace,
+        };
+
+        let mut existing = if limits.is_set() {
+            settings.existing_volumes()
+        } else {
+            Vec::new()
+        };
+        if settings.replace {
+            for volume in mem::take(&mut existing) {
+                remove_file(&volume).await?;
+            }
+        }
+        let mut next_volume = 1;
+        let mut saved = HashSet::new();
+        let volume = if by_rating {
+            // The last volume is reopened below.
+            existing.pop();
+            for volume in &existing {
+                let summary = read_summary_file(volume)?;
+                saved.extend(summary.posts.into_iter().map(|post| post.md5));
+            }
+
+            if limits.is_set() {
+                let number = existing.len() + 1;
+                next_volume = number + 1;
+                Volume::open(settings.volume_path(number), Some(number), &settings)?
+            } else {
+                Volume::open(settings.path.clone(), None, &settings)?
+            }
+        } else {
+            // Pools are staged in a single archive until they are split, along with the pages of existing
+            // volumes, so new pages can go anywhere in the pool.
+            let mut staged = Volume::open(settings.path.clone(), None, &settings)?;
+            for volume in &existing {
+                staged.copy_archive(volume)?;
+            }
+            staged
+        };
+        saved.extend(volume.summary.posts.iter().map(|post| post.md5.clone()));
+
+        Ok(Self {
+            archive: Arc::new(Mutex::new(Archive {
+                volume,
+                settings,
+                saved,
+                next_volume,
+            })),
+            spool_dir,
+            by_rating,
+            compression: ZipCompression::default(),
+        })
+    }
+
+    /// Adds a `ComicInfo.xml` with the info of the pool and its pages, so comic readers can show its name,
+    /// description and credits.
+    #[must_use]
+    pub fn pool(self, pool: PoolInfo) -> Self {
+        self.archive.lock().unwrap().settings.pool = Some(pool);
+        self
+    }
+
+    /// Compresses the downloaded files, like for plain zip archives. CBZ archives store them as they are.
+    #[must_use]
+    pub const fn compression(mut self, compression: ZipCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    fn item(&self, entry: String, options: SimpleFileOptions) -> CbzItem {
+        CbzItem {
+            archive: self.archive.clone(),
+            entry,
+            options,
+            spool: Spool::new(self.spool_dir.clone()),
+            post: None,
+        }
+    }
+}
+
+impl StorageSink for CbzSink {
+    type Item = CbzItem;
+
+    async fn exists(&self, post: &Post, _name: &str) -> Result<Existing, PostError> {
+        let saved = self.archive.lock().unwrap().saved.contains(&post.md5);
+
+        if saved {
+            return Ok(Existing::Skip(SkipReason::AlreadyExists));
+        }
+        Ok(Existing::Missing)
+    }
+
+    async fn open(&self, post: &Post, name: &str) -> Result<CbzItem, PostError> {
+        let entry = entry_name(post, name, self.by_rating);
+
+        let mut item = self.item(entry.clone(), self.compression.options());
+        item.post = Some(SummaryPost {
+            id: post.id,
+            file: entry,
+            md5: post.md5.clone(),
+            rating: post.rating,
+            tags: post.tags.clone(),
+        });
+        Ok(item)
+    }
+
+    /// Unlike downloaded files, which are usually already compressed, small files are always deflated.
+    async fn put(&self, post: &Post, name: &str, data: Vec<u8>) -> Result<(), PostError> {
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(5));
+
+        let mut item = self.item(entry_name(post, name, self.by_rating), options);
+        item.spool = Spool::from_vec(data, self.spool_dir.clone());
+        item.commit().await
+    }
+
+    async fn finish(self) -> Result<(), QueueError> {
+        // Finishing a volume consumes its `ZipWriter`, so every item must have released its clone of the `Arc`.
+        let Archive {
+            volume, settings, ..
+        } = Arc::try_unwrap(self.archive)
+            .map_err(|_arc_still_has_clones| QueueError::MutexLockReleaseError)?
+            .into_inner()
+            .map_err(|_poison_error| QueueError::MutexLockReleaseError)?;
+
+        if settings.limits.is_set() && !settings.by_rating {
+            split_pool(volume, &settings)
+        } else {
+            volume.finish(settings.pool.as_ref())
+        }
+    }
+}
+
+/// A file being written into a [`CbzSink`].
+#[derive(Debug)]
+pub struct CbzItem {
+    archive: Arc<Mutex<Archive>>,
+    entry: String,
+    options: SimpleFileOptions,
+    spool: Spool,
+    /// Added to the summary once the file is written. `None` for files that aren't posts, like captions.
+    post: Option<SummaryPost>,
+}
+
+impl SinkItem for CbzItem {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), PostError> {
+        self.spool.write(chunk).await
+    }
+
+    async fn commit(self) -> Result<(), PostError> {
+        let (mut data, len) = self.spool.into_reader().await?;
+
+        spawn_blocking(move || -> Result<(), PostError> {
+            self.archive
+                .lock()
+                .unwrap()
+                .add(self.entry, self.options, &mut data, len, self.post)
+                .map_err(|error| PostError::ZipFileWriteError {
+                    message: error.to_string(),
+                })
+        })
+        .await
+        .map_err(|thread_error| PostError::ZipThreadStartError {
+            msg: thread_error.to_string(),
+        })?
+    }
+
+    async fn abort(self) -> Result<(), PostError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write, path::Path};
+
+    use ibdl_common::post::NameType;
+    use zip::{ZipArchive, ZipWriter};
+
+    use super::*;
+    use crate::test::{md5_of, post};
+
+    /// Writes an archive like the ones saved by older versions, without a summary.
+    fn write_legacy_cbz(path: &Path, files: &[(&str, &[u8])]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+        for (name, data) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(data).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn file_names(path: &Path) -> Vec<String> {
+        let archive = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        archive.file_names().map(ToString::to_string).collect()
+    }
+
+    async fn create(path: &Path, by_rating: bool, limits: VolumeLimits) -> CbzSink {
+        CbzSink::create(
+            path.to_path_buf(),
+            by_rating,
+            String::from("danbooru"),
+            Vec::new(),
+            limits,
+            ExistingArchive::Append,
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn save(sink: &CbzSink, post: &Post, name: &str) {
+        let mut item = sink.open(post, name).await.unwrap();
+        item.write(post.md5.as_bytes()).await.unwrap();
+        item.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn appends_to_archive_without_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("legacy.cbz");
+        let named_by_md5 = md5_of(1);
+        write_legacy_cbz(
+            &path,
+            &[
+                (&format!("Safe/{named_by_md5}.png"), b"one"),
+                (&format!("Safe/{named_by_md5}.txt"), b"caption"),
+                ("Explicit/2.jpg", b"two"),
+            ],
+        );
+
+        let sink = create(&path, true, VolumeLimits::default()).await;
+
+        let by_name = post(1, &named_by_md5);
+        let by_content = post(2, &format!("{:x}", md5::compute(b"two")));
+        for saved in [&by_name, &by_content] {
+            assert_eq!(
+                sink.exists(saved, "").await.unwrap(),
+                Existing::Skip(SkipReason::AlreadyExists)
+            );
+        }
+
+        let new = post(3, &md5_of(3));
+        assert_eq!(sink.exists(&new, "").await.unwrap(), Existing::Missing);
+        save(&sink, &new, &new.file_name(NameType::MD5)).await;
+        sink.finish().await.unwrap();
+
+        let summary = read_summary_file(&path).unwrap();
+        let posts: Vec<(u64, &str, Rating)> = summary
+            .posts
+            .iter()
+            .map(|post| (post.id, post.md5.as_str(), post.rating))
+            .collect();
+        assert_eq!(
+            posts,
+            [
+                (0, named_by_md5.as_str(), Rating::Safe),
+                (2, by_content.md5.as_str(), Rating::Explicit),
+                (3, new.md5.as_str(), Rating::Safe),
+            ]
+        );
+        assert!(file_names(&path).contains(&format!("Safe/{named_by_md5}.txt")));
+    }
+
+    #[tokio::test]
+    async fn replaces_existing_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("replaced.cbz");
+        write_legacy_cbz(&path, &[("Safe/old.png", b"old")]);
+
+        let sink = CbzSink::create(
+            path.clone(),
+            true,
+            String::from("danbooru"),
+            Vec::new(),
+            VolumeLimits::default(),
+            ExistingArchive::Replace,
+        )
+        .await
+        .unwrap();
+        let new = post(1, &md5_of(1));
+        save(&sink, &new, &new.file_name(NameType::MD5)).await;
+        sink.finish().await.unwrap();
+
+        let names = file_names(&path);
+        assert!(!names.contains(&String::from("Safe/old.png")));
+        assert_eq!(read_summary_file(&path).unwrap().posts.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn splits_posts_into_volumes_and_appends_to_the_last_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("split.cbz");
+        let limits = VolumeLimits {
+            max_posts: Some(2),
+            max_bytes: None,
+        };
+
+        let sink = create(&path, true, limits).await;
+        for id in 1..=3 {
+            let post = post(id, &md5_of(id));
+            save(&sink, &post, &post.file_name(NameType::MD5)).await;
+        }
+        sink.finish().await.unwrap();
+
+        let first = dir.path().join("split_001.cbz");
+        let second = dir.path().join("split_002.cbz");
+        assert!(!path.exists());
+        assert_eq!(read_summary_file(&first).unwrap().posts.len(), 2);
+        assert_eq!(read_summary_file(&first).unwrap().volume, Some(1));
+        assert_eq!(read_summary_file(&second).unwrap().posts.len(), 1);
+
+        let sink = create(&path, true, limits).await;
+        let saved = post(1, &md5_of(1));
+        assert_eq!(
+            sink.exists(&saved, "").await.unwrap(),
+            Existing::Skip(SkipReason::AlreadyExists)
+        );
+        let new = post(4, &md5_of(4));
+        save(&sink, &new, &new.file_name(NameType::MD5)).await;
+        sink.finish().await.unwrap();
+
+        assert_eq!(read_summary_file(&second).unwrap().posts.len(), 2);
+        assert!(!dir.path().join("split_003.cbz").exists());
+    }
+
+    #[tokio::test]
+    async fn splits_pool_pages_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pool.cbz");
+        let limits = VolumeLimits {
+            max_posts: Some(2),
+            max_bytes: None,
+        };
+
+        let sink = create(&path, false, limits).await;
+        for id in [3, 1, 2] {
+            let page = post(id, &md5_of(id));
+            save(&sink, &page, &page.seq_file_name(6)).await;
+        }
+        sink.finish().await.unwrap();
+
+        let pages = |number: usize| -> Vec<String> {
+            let volume = dir.path().join(format!("pool_{number:03}.cbz"));
+            let summary = read_summary_file(&volume).unwrap();
+            summary.posts.into_iter().map(|page| page.file).collect()
+        };
+        assert_eq!(pages(1), ["000001.png", "000002.png"]);
+        assert_eq!(pages(2), ["000003.png"]);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn splits_existing_pool_pages_again_with_new_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pool.cbz");
+        let limits = VolumeLimits {
+            max_posts: Some(2),
+            max_bytes: None,
+        };
+        let pages = |number: usize| -> Vec<String> {
+            let volume = dir.path().join(format!("pool_{number:03}.cbz"));
+            let summary = read_summary_file(&volume).unwrap();
+            summary.posts.into_iter().map(|page| page.file).collect()
+        };
+
+        let sink = create(&path, false, limits).await;
+        for id in [1, 3] {
+            let page = post(id, &md5_of(id));
+            save(&sink, &page, &page.seq_file_name(6)).await;
+        }
+        sink.finish().await.unwrap();
+        assert_eq!(pages(1), ["000001.png", "000003.png"]);
+
+        // A page in the middle of the pool is added by another run
+        let sink = create(&path, false, limits).await;
+        let saved = post(3, &md5_of(3));
+        assert_eq!(
+            sink.exists(&saved, "").await.unwrap(),
+            Existing::Skip(SkipReason::AlreadyExists)
+        );
+        let page = post(2, &md5_of(2));
+        save(&sink, &page, &page.seq_file_name(6)).await;
+        sink.finish().await.unwrap();
+        assert_eq!(pages(1), ["000001.png", "000002.png"]);
+        assert_eq!(pages(2), ["000003.png"]);
+
+        // Bigger volumes leave no empty ones behind
+        let limits = VolumeLimits {
+            max_posts: Some(3),
+            max_bytes: None,
+        };
+        create(&path, false, limits).await.finish().await.unwrap();
+        assert_eq!(pages(1), ["000001.png", "000002.png", "000003.png"]);
+        assert!(!dir.path().join("pool_002.cbz").exists());
+        assert!(!path.exists());
+    }
+}
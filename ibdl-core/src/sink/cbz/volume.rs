@@ -0,0 +1,539 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs::{File, remove_file, rename},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use ibdl_common::{log::debug, post::rating::Rating, serde_json};
+use ibdl_extractors::prelude::PoolInfo;
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+use zip::{
+    CompressionMethod, ZipArchive, ZipWriter, read::ZipFile, result::ZipError,
+    write::SimpleFileOptions,
+};
+
+use super::{CbzSummary, SUMMARY_FILE, SummaryPost};
+use crate::{
+    error::QueueError,
+    sink::{
+        archive::RATING_DIRS,
+        comic_info::{COMIC_INFO_FILE, comic_info},
+    },
+};
+
+/// Limits of each archive when the output of a [`CbzSink`](super::CbzSink) is split into volumes.
+///
+/// A volume always holds at least one post, even if its file alone is bigger than `max_bytes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VolumeLimits {
+    /// Maximum number of posts in each volume.
+    pub max_posts: Option<usize>,
+    /// Maximum size of the files in each volume, in bytes.
+    pub max_bytes: Option<u64>,
+}
+
+impl VolumeLimits {
+    /// Returns `true` if the output is split into volumes.
+    #[must_use]
+    pub const fn is_set(&self) -> bool {
+        self.max_posts.is_some() || self.max_bytes.is_some()
+    }
+
+    /// Returns `true` if a file of `size` bytes doesn't fit into a volume with `posts` posts and `bytes` bytes.
+    const fn is_full(&self, posts: usize, bytes: u64, size: u64) -> bool {
+        if posts == 0 {
+            return false;
+        }
+
+        let too_many = match self.max_posts {
+            Some(max) => posts >= max,
+            None => false,
+        };
+        let too_big = match self.max_bytes {
+            Some(max) => bytes + size > max,
+            None => false,
+        };
+        too_many || too_big
+    }
+}
+
+/// Everything needed to open a new volume.
+#[derive(Debug)]
+pub(super) struct VolumeSettings {
+    /// Path of the output, without a volume number.
+    pub path: PathBuf,
+    pub by_rating: bool,
+    pub server: String,
+    pub query: Vec<String>,
+    pub limits: VolumeLimits,
+    pub pool: Option<PoolInfo>,
+    /// Replace existing archives instead of appending to them.
+    pub replace: bool,
+}
+
+impl VolumeSettings {
+    /// Path of the volume `number`, like `name_001.cbz` for the first volume of `name.cbz`.
+    pub fn volume_path(&self, number: usize) -> PathBuf {
+        let stem = self.path.file_stem().unwrap_or_default().to_string_lossy();
+        self.path.with_file_name(format!("{stem}_{number:03}.cbz"))
+    }
+
+    /// Volumes saved by previous runs, in order.
+    pub fn existing_volumes(&self) -> Vec<PathBuf> {
+        (1..)
+            .map(|number| self.volume_path(number))
+            .take_while(|path| path.exists())
+            .collect()
+    }
+
+    /// Returns `true` if a file of `size` bytes doesn't fit into `volume`.
+    pub const fn is_full(&self, volume: &Volume, size: u64) -> bool {
+        self.limits
+            .is_full(volume.summary.posts.len(), volume.bytes, size)
+    }
+}
+
+/// A single archive being written, along with its summary.
+#[derive(Debug)]
+pub(super) struct Volume {
+    zip: ZipWriter<File>,
+    pub summary: CbzSummary,
+    path: PathBuf,
+    part_path: PathBuf,
+    /// Size of the files saved in the archive.
+    bytes: u64,
+    /// `ComicInfo.xml` of the existing archive, kept if no pool info is set.
+    previous_comic_info: Option<Vec<u8>>,
+}
+
+impl Volume {
+    /// Opens the archive at `path`, which is written to `<path>.part` until it's finished.
+    ///
+    /// If the archive already exists, its files are copied into the new one, unless it's being replaced.
+    /// Appending in place would skip the copy, but the summary can't be replaced inside an archive, and a run
+    /// that fails halfway would leave the archive without its central directory. The copy is raw, so files
+    /// aren't compressed again, and the existing archive is only replaced once the new one is finished.
+    pub fn open(
+        path: PathBuf,
+        number: Option<usize>,
+        settings: &VolumeSettings,
+    ) -> Result<Self, QueueError> {
+        let append = path.exists() && !settings.replace;
+        Self::open_as(path, number, settings, append)
+    }
+
+    /// Opens the archive at `path`, keeping the files of the existing one only if `append` is set.
+    fn open_as(
+        path: PathBuf,
+        number: Option<usize>,
+        settings: &VolumeSettings,
+        append: bool,
+    ) -> Result<Self, QueueError> {
+        let mut part_path = OsString::from(path.as_os_str());
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+
+        debug!("Target file: {}", path.display());
+        let mut volume = Self {
+            zip: ZipWriter::new(File::create(&part_path)?),
+            summary: CbzSummary {
+                server: settings.server.clone(),
+                query: settings.query.clone(),
+                volume: number,
+                ..CbzSummary::default()
+            },
+            path,
+            part_path,
+            bytes: 0,
+            previous_comic_info: None,
+        };
+
+        let directories = if append {
+            debug!("Appending to existing archive {}", volume.path.display());
+            volume.copy_archive(&volume.path.clone())?
+        } else {
+            HashSet::new()
+        };
+
+        if settings.by_rating {
+            let opts = SimpleFileOptions::default();
+            for rating in RATING_DIRS {
+                if !directories.contains(&rating.to_string()) {
+                    volume.zip.add_directory(rating.to_string(), opts)?;
+                }
+            }
+        }
+
+        Ok(volume)
+    }
+
+    /// Copies every file of the archive at `path` into this one, except its summary and `ComicInfo.xml`, and
+    /// adds its posts to the summary.
+    ///
+    /// Returns the directories of the archive.
+    pub fn copy_archive(&mut self, path: &Path) -> Result<HashSet<String>, QueueError> {
+        let mut existing = ZipArchive::new(File::open(path)?)?;
+        self.summary
+            .posts
+            .extend(read_summary(&mut existing, path)?.posts);
+
+        let mut directories = HashSet::new();
+        for index in 0..existing.len() {
+            let file = existing.by_index_raw(index)?;
+            if file.name() == SUMMARY_FILE {
+                continue;
+            }
+            if file.name() == COMIC_INFO_FILE {
+                drop(file);
+                let mut raw = Vec::new();
+                existing.by_index(index)?.read_to_end(&mut raw)?;
+                self.previous_comic_info = Some(raw);
+                continue;
+            }
+            if file.is_dir() {
+                directories.insert(file.name().trim_end_matches('/').to_string());
+            }
+            self.copy(file, None)?;
+        }
+        Ok(directories)
+    }
+
+    /// Writes the file `entry` and adds `post` to the summary.
+    pub fn add(
+        &mut self,
+        entry: String,
+        options: SimpleFileOptions,
+        data: &mut dyn Read,
+        size: u64,
+        post: Option<SummaryPost>,
+    ) -> Result<(), QueueError> {
+        debug!("Writing {} to cbz file", entry);
+        self.zip.start_file(entry, options)?;
+        io::copy(data, &mut self.zip)?;
+
+        self.bytes += size;
+        if let Some(post) = post {
+            self.summary.posts.push(post);
+        }
+        Ok(())
+    }
+
+    /// Copies a file from another archive without decompressing it.
+    fn copy(
+        &mut self,
+        file: ZipFile<'_, File>,
+        post: Option<SummaryPost>,
+    ) -> Result<(), QueueError> {
+        self.bytes += file.compressed_size();
+        self.zip.raw_copy_file(file)?;
+
+        if let Some(post) = post {
+            self.summary.posts.push(post);
+        }
+        Ok(())
+    }
+
+    /// Writes the summary and, for pools, `ComicInfo.xml`, then moves the archive into place.
+    pub fn finish(mut self, pool: Option<&PoolInfo>) -> Result<(), QueueError> {
+        self.summary.download_date =
+            OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .map_err(|error| QueueError::SummarySerializeFail {
+                    error: error.to_string(),
+                })?;
+
+        let raw = serde_json::to_string_pretty(&self.summary).map_err(|error| {
+            QueueError::SummarySerializeFail {
+                error: error.to_string(),
+            }
+        })?;
+
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(5));
+        self.zip.start_file(SUMMARY_FILE, options)?;
+        self.zip.write_all(raw.as_bytes())?;
+
+        let comic_info = match pool {
+            Some(pool) => {
+                Some(comic_info(pool, &self.summary.posts, self.summary.volume).into_bytes())
+            }
+            None => self.previous_comic_info,
+        };
+        if let Some(comic_info) = comic_info {
+            self.zip.start_file(COMIC_INFO_FILE, options)?;
+            self.zip.write_all(&comic_info)?;
+        }
+
+        self.zip.finish()?;
+
+        rename(&self.part_path, &self.path)?;
+        debug!(
+            "Saved {} posts into {}",
+            self.summary.posts.len(),
+            self.path.display()
+        );
+        Ok(())
+    }
+
+    /// Finishes the archive without a summary, leaving it at its `.part` path.
+    fn close(self) -> Result<PathBuf, QueueError> {
+        self.zip.finish()?;
+        Ok(self.part_path)
+    }
+}
+
+/// Splits the pages of a pool, staged in a single archive, into volumes in page order.
+///
+/// Pages are only split once the whole pool is downloaded, since they don't arrive in order. Files that
+/// aren't pages, like captions, go into the volume of the page they belong to.
+///
+/// `staged` must also hold the pages of existing volumes, which are replaced starting from the first one.
+pub(super) fn split_pool(staged: Volume, settings: &VolumeSettings) -> Result<(), QueueError> {
+    let mut pages = staged.summary.posts.clone();
+    pages.sort_by(|a, b| a.file.cmp(&b.file));
+
+    let staged_path = staged.close()?;
+    let mut staged = ZipArchive::new(File::open(&staged_path)?)?;
+    let names: Vec<String> = staged.file_names().map(ToString::to_string).collect();
+
+    let mut volume: Option<Volume> = None;
+    let mut number = 1;
+    for page in pages {
+        let size = staged.by_name(&page.file)?.compressed_size();
+
+        let current = match volume.take() {
+            Some(current) if !settings.is_full(&current, size) => current,
+            previous => {
+                if let Some(previous) = previous {
+                    previous.finish(settings.pool.as_ref())?;
+                }
+                number += 1;
+                // Existing volumes are replaced, since their pages are staged along with the new ones.
+                Volume::open_as(
+                    settings.volume_path(number - 1),
+                    Some(number - 1),
+                    settings,
+                    false,
+                )?
+            }
+        };
+        let current = volume.insert(current);
+
+        let attachments: Vec<&String> = names
+            .iter()
+            .filter(|name| **name != page.file && belongs_to(name, &page))
+            .collect();
+
+        current.copy(by_name_raw(&mut staged, &page.file)?, Some(page))?;
+        for name in attachments {
+            current.copy(by_name_raw(&mut staged, name)?, None)?;
+        }
+    }
+
+    if let Some(last) = volume {
+        last.finish(settings.pool.as_ref())?;
+    }
+
+    // Volumes left by a previous run with smaller limits.
+    for path in (number..).map(|number| settings.volume_path(number)) {
+        if !path.exists() {
+            break;
+        }
+        remove_file(path)?;
+    }
+
+    drop(staged);
+    remove_file(&staged_path)?;
+    Ok(())
+}
+
+fn by_name_raw<'a>(
+    archive: &'a mut ZipArchive<File>,
+    name: &str,
+) -> Result<ZipFile<'a, File>, ZipError> {
+    let index = archive.index_for_name(name).ok_or(ZipError::FileNotFound)?;
+    archive.by_index_raw(index)
+}
+
+/// Returns `true` if the file `name` is named after the MD5 hash or the ID of `page`.
+fn belongs_to(name: &str, page: &SummaryPost) -> bool {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    stem == page.md5 || stem == page.id.to_string()
+}
+
+/// Reads the summary of an archive, or rebuilds it from its files if the archive doesn't have one, like the
+/// ones saved by older versions.
+pub(super) fn read_summary(
+    archive: &mut ZipArchive<File>,
+    path: &Path,
+) -> Result<CbzSummary, QueueError> {
+    let read_error = || QueueError::ZipSummaryReadError {
+        file: path.display().to_string(),
+    };
+
+    let Some(index) = archive.index_for_name(SUMMARY_FILE) else {
+        return rebuild_summary(archive, path);
+    };
+
+    let mut raw = String::new();
+    archive
+        .by_index(index)
+        .map_err(|_| read_error())?
+        .read_to_string(&mut raw)
+        .map_err(|_| read_error())?;
+
+    serde_json::from_str(&raw).map_err(|error| QueueError::SummaryDeserializeFail {
+        error: error.to_string(),
+    })
+}
+
+/// Builds a summary from the files of an archive.
+///
+/// Every file except captions and `ComicInfo.xml` is taken as a post. Its MD5 hash is taken from its name, or
+/// computed from its contents if it's named after the post ID, and its rating from the directory it's in.
+/// Tags, and the ID of posts named after their MD5 hash, can't be recovered.
+fn rebuild_summary(archive: &mut ZipArchive<File>, path: &Path) -> Result<CbzSummary, QueueError> {
+    debug!(
+        "{} has no summary, rebuilding it from its files",
+        path.display()
+    );
+
+    let mut posts = Vec::new();
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index)?;
+        let name = file.name().to_string();
+        if file.is_dir() || name == COMIC_INFO_FILE || name.ends_with(".txt") {
+            continue;
+        }
+
+        let (dir, file_name) = name
+            .rsplit_once('/')
+            .map_or((None, name.as_str()), |(dir, file_name)| {
+                (Some(dir), file_name)
+            });
+        let stem = file_name
+            .rsplit_once('.')
+            .map_or(file_name, |(stem, _)| stem);
+
+        let (id, md5) = if stem.len() == 32 && stem.chars().all(|c| c.is_ascii_hexdigit()) {
+            (0, stem.to_ascii_lowercase())
+        } else {
+            let mut hasher = md5::Context::new();
+            io::copy(&mut file, &mut hasher)?;
+            (
+                stem.parse().unwrap_or_default(),
+                format!("{:x}", hasher.compute()),
+            )
+        };
+
+        let rating = RATING_DIRS
+            .into_iter()
+            .find(|rating| dir == Some(rating.to_string().as_str()))
+            .unwrap_or(Rating::Unknown);
+
+        posts.push(SummaryPost {
+            id,
+            file: name.clone(),
+            md5,
+            rating,
+            tags: Vec::new(),
+        });
+    }
+
+    Ok(CbzSummary {
+        posts,
+        ..CbzSummary::default()
+    })
+}
+
+/// Reads the summary of the archive at `path`.
+pub(super) fn read_summary_file(path: &Path) -> Result<CbzSummary, QueueError> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    read_summary(&mut archive, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(path: PathBuf) -> VolumeSettings {
+        VolumeSettings {
+            path,
+            by_rating: false,
+            server: "danbooru".to_string(),
+            query: Vec::new(),
+            limits: VolumeLimits::default(),
+            pool: None,
+            replace: false,
+        }
+    }
+
+    fn add(volume: &mut Volume, id: u64) {
+        let post = SummaryPost {
+            id,
+            file: format!("{id}.png"),
+            md5: format!("{id:032}"),
+            rating: Rating::Safe,
+            tags: Vec::new(),
+        };
+        volume
+            .add(
+                post.file.clone(),
+                SimpleFileOptions::default(),
+                &mut &b"page"[..],
+                4,
+                Some(post),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn failed_append_leaves_the_archive_intact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pool.cbz");
+        let settings = settings(path.clone());
+
+        let mut volume = Volume::open(path.clone(), None, &settings).unwrap();
+        add(&mut volume, 1);
+        volume.finish(None).unwrap();
+        let saved = std::fs::read(&path).unwrap();
+
+        // The run fails before the archive is finished
+        let mut volume = Volume::open(path.clone(), None, &settings).unwrap();
+        add(&mut volume, 2);
+        drop(volume);
+
+        assert_eq!(std::fs::read(&path).unwrap(), saved);
+        let summary = read_summary_file(&path).unwrap();
+        assert_eq!(summary.posts.len(), 1);
+
+        let mut volume = Volume::open(path.clone(), None, &settings).unwrap();
+        add(&mut volume, 2);
+        volume.finish(None).unwrap();
+        let ids: Vec<u64> = read_summary_file(&path)
+            .unwrap()
+            .posts
+            .iter()
+            .map(|post| post.id)
+            .collect();
+        assert_eq!(ids, [1, 2]);
+    }
+
+    #[test]
+    fn volume_limits() {
+        let limits = VolumeLimits {
+            max_posts: Some(2),
+            max_bytes: Some(100),
+        };
+
+        // A volume always takes its first post, however big.
+        assert!(!limits.is_full(0, 0, 1000));
+        assert!(!limits.is_full(1, 50, 50));
+        assert!(limits.is_full(1, 50, 51));
+        assert!(limits.is_full(2, 10, 10));
+        assert!(!VolumeLimits::default().is_full(1000, u64::MAX / 2, 1000));
+    }
+}
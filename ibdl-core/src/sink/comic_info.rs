@@ -0,0 +1,87 @@
+use std::fmt::Write;
+
+use ibdl_common::post::tags::TagType;
+use ibdl_extractors::prelude::PoolInfo;
+
+use super::cbz::SummaryPost;
+
+/// Name of the metadata file read by comic readers like Komga, Kavita or YACReader.
+pub const COMIC_INFO_FILE: &str = "ComicInfo.xml";
+
+/// Builds the `ComicInfo.xml` of a pool from its info and the pages saved in the archive.
+///
+/// Pages are ordered by file name, which is their position in the pool. Artists, copyrights and characters
+/// are collected from the tags of every page. `volume` is the number of the archive when the pool is split
+/// into volumes.
+pub fn comic_info(pool: &PoolInfo, pages: &[SummaryPost], volume: Option<usize>) -> String {
+    let mut pages: Vec<&SummaryPost> = pages.iter().collect();
+    pages.sort_by(|a, b| a.file.cmp(&b.file));
+
+    let artists = tag_list(&pages, TagType::Author);
+    let copyrights = tag_list(&pages, TagType::Copyright);
+    let characters = tag_list(&pages, TagType::Character);
+
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+        "<ComicInfo xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" ",
+        "xmlns:xsd=\"http://www.w3.org/2001/XMLSchema\">\n"
+    ));
+
+    element(&mut xml, "Title", &pool.name);
+    element(&mut xml, "Series", &pool.name);
+    if let Some(volume) = volume {
+        element(&mut xml, "Volume", &volume.to_string());
+    }
+    element(&mut xml, "Summary", &pool.description);
+    element(&mut xml, "Writer", &artists);
+    element(&mut xml, "SeriesGroup", &copyrights);
+    element(&mut xml, "Characters", &characters);
+    element(&mut xml, "Web", &pool.url);
+    element(&mut xml, "PageCount", &pages.len().to_string());
+
+    xml.push_str("  <Pages>\n");
+    for index in 0..pages.len() {
+        if index == 0 {
+            let _ = writeln!(xml, "    <Page Image=\"{index}\" Type=\"FrontCover\" />");
+        } else {
+            let _ = writeln!(xml, "    <Page Image=\"{index}\" />");
+        }
+    }
+    xml.push_str("  </Pages>\n</ComicInfo>\n");
+
+    xml
+}
+
+/// Tags of `tag_type` found in any page, in order of appearance and with spaces instead of underscores.
+fn tag_list(pages: &[&SummaryPost], tag_type: TagType) -> String {
+    let mut tags: Vec<String> = Vec::new();
+
+    for tag in pages.iter().flat_map(|page| &page.tags) {
+        if tag.tag_type() != tag_type {
+            continue;
+        }
+
+        let tag = tag.tag().replace('_', " ");
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+
+    tags.join(", ")
+}
+
+/// Writes `<name>value</name>`, or nothing if `value` is empty.
+fn element(xml: &mut String, name: &str, value: &str) {
+    if !value.is_empty() {
+        let _ = writeln!(xml, "  <{name}>{}</{name}>", escape(value));
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
@@ -0,0 +1,214 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use ibdl_common::{
+    log::debug,
+    post::{NameType, Post, error::PostError, rating::Rating, tags::TagType},
+    serde::{Deserialize, Serialize},
+    serde_json,
+    tokio::fs::{read_to_string, rename, write},
+};
+
+use super::{Existing, FolderItem, FolderSink, SinkItem, StorageSink};
+use crate::{caption::CaptionConfig, error::QueueError, events::SkipReason};
+
+/// Name of the metadata file of an image folder dataset.
+pub const METADATA_FILE: &str = "metadata.jsonl";
+
+/// Metadata of a post saved into a training dataset.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "ibdl_common::serde")]
+pub struct DatasetRecord {
+    /// Path of the file, relative to the dataset.
+    pub file_name: String,
+    pub id: u64,
+    /// MD5 hash of the file as served, even if it was processed before being saved.
+    pub md5: String,
+    /// Caption of the post, built by a [`CaptionConfig`].
+    pub caption: String,
+    /// Every tag of the post, grouped by type.
+    pub tags: BTreeMap<TagType, Vec<String>>,
+    pub rating: Rating,
+    pub score: Option<i64>,
+}
+
+impl DatasetRecord {
+    pub fn new(post: &Post, file_name: String, caption: &CaptionConfig) -> Self {
+        let mut tags: BTreeMap<TagType, Vec<String>> = BTreeMap::new();
+        for tag in &post.tags {
+            tags.entry(tag.tag_type()).or_default().push(tag.tag());
+        }
+
+        Self {
+            file_name,
+            id: post.id,
+            md5: post.md5.clone(),
+            caption: caption.caption(post),
+            tags,
+            rating: post.rating,
+            score: post.score,
+        }
+    }
+}
+
+/// Saves every file into a directory along with a `metadata.jsonl`, as read by the `imagefolder` loader of
+/// Hugging Face datasets.
+///
+/// Files are saved like in a [`FolderSink`]. Each line of the metadata file is a [`DatasetRecord`], written
+/// by [`finish`](StorageSink::finish). Records of files saved by previous runs are kept.
+#[derive(Debug)]
+pub struct ImageFolderSink {
+    folder: FolderSink,
+    dir: PathBuf,
+    records: Arc<Mutex<Vec<DatasetRecord>>>,
+    caption: CaptionConfig,
+    /// Files renamed to the other [`NameType`], as `(from, to)`.
+    renamed: Mutex<HashMap<String, String>>,
+}
+
+impl ImageFolderSink {
+    /// Creates `dir` if needed. `name_type` is the naming scheme of the files written into it.
+    pub async fn create(dir: PathBuf, name_type: NameType) -> Result<Self, QueueError> {
+        Ok(Self {
+            folder: FolderSink::create(dir.clone(), name_type).await?,
+            dir,
+            records: Arc::new(Mutex::new(Vec::new())),
+            caption: CaptionConfig::default(),
+            renamed: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// How the caption of every record is built.
+    #[must_use]
+    pub fn caption(mut self, config: CaptionConfig) -> Self {
+        self.caption = config;
+        self
+    }
+
+    async fn read_records(&self) -> Result<Vec<DatasetRecord>, QueueError> {
+        let path = self.dir.join(METADATA_FILE);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let raw = read_to_string(&path).await?;
+        raw.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|error| QueueError::MetadataDecodeFail {
+                    file: path.display().to_string(),
+                    line: index + 1,
+                    error: error.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+impl StorageSink for ImageFolderSink {
+    type Item = ImageFolderItem;
+
+    async fn exists(&self, post: &Post, name: &str) -> Result<Existing, PostError> {
+        let existing = self.folder.exists(post, name).await?;
+
+        if let Existing::Skip(SkipReason::Renamed { from }) = &existing {
+            self.renamed
+                .lock()
+                .unwrap()
+                .insert(from.clone(), name.to_string());
+        }
+        Ok(existing)
+    }
+
+    async fn open(&self, post: &Post, name: &str) -> Result<ImageFolderItem, PostError> {
+        Ok(ImageFolderItem {
+            file: self.folder.open(post, name).await?,
+            record: DatasetRecord::new(post, name.to_string(), &self.caption),
+            records: self.records.clone(),
+        })
+    }
+
+    /// Small files, like captions, don't get a record.
+    async fn put(&self, post: &Post, name: &str, data: Vec<u8>) -> Result<(), PostError> {
+        self.folder.put(post, name, data).await
+    }
+
+    async fn mark_processed(&self, post: &Post, name: &str) -> Result<(), PostError> {
+        self.folder.mark_processed(post, name).await
+    }
+
+    async fn finish(self) -> Result<(), QueueError> {
+        let previous_records = self.read_records().await?;
+        self.folder.finish().await?;
+        let new_records = Arc::try_unwrap(self.records)
+            .map_err(|_arc_still_has_clones| QueueError::MutexLockReleaseError)?
+            .into_inner()
+            .map_err(|_poison_error| QueueError::MutexLockReleaseError)?;
+        let renamed = self
+            .renamed
+            .into_inner()
+            .map_err(|_poison_error| QueueError::MutexLockReleaseError)?;
+
+        let replaced: HashSet<&str> = new_records
+            .iter()
+            .map(|record| record.file_name.as_str())
+            .collect();
+
+        let mut records = Vec::new();
+        for mut record in previous_records {
+            if let Some(to) = renamed.get(&record.file_name) {
+                record.file_name.clone_from(to);
+            }
+            if !replaced.contains(record.file_name.as_str()) {
+                records.push(record);
+            }
+        }
+        records.extend(new_records);
+
+        let mut raw = String::new();
+        for record in &records {
+            let line =
+                serde_json::to_string(record).map_err(|error| QueueError::PostListEncodeFail {
+                    error: error.to_string(),
+                })?;
+            raw.push_str(&line);
+            raw.push('\n');
+        }
+
+        let path = self.dir.join(METADATA_FILE);
+        let part_path = self.dir.join(format!("{METADATA_FILE}.part"));
+        write(&part_path, raw).await?;
+        rename(&part_path, &path).await?;
+
+        debug!("Saved {} records into {}", records.len(), path.display());
+        Ok(())
+    }
+}
+
+/// A file being written into an [`ImageFolderSink`].
+#[derive(Debug)]
+pub struct ImageFolderItem {
+    file: FolderItem,
+    record: DatasetRecord,
+    records: Arc<Mutex<Vec<DatasetRecord>>>,
+}
+
+impl SinkItem for ImageFolderItem {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), PostError> {
+        self.file.write(chunk).await
+    }
+
+    async fn commit(self) -> Result<(), PostError> {
+        self.file.commit().await?;
+        self.records.lock().unwrap().push(self.record);
+        Ok(())
+    }
+
+    async fn abort(self) -> Result<(), PostError> {
+        self.file.abort().await
+    }
+}
@@ -0,0 +1,396 @@
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use ibdl_common::{
+    log::debug,
+    post::{NameType, Post, error::PostError},
+    serde::{Deserialize, Serialize},
+    serde_json,
+    tokio::{
+        fs::{File, OpenOptions, create_dir_all, read, read_to_string, remove_file, rename, write},
+        io::{AsyncWriteExt, BufWriter},
+    },
+};
+use md5::compute;
+
+use super::{Existing, SinkItem, StorageSink};
+use crate::{error::QueueError, events::SkipReason};
+
+/// Name of the file where a [`FolderSink`] keeps the MD5 hash of the posts of its processed files.
+///
+/// Every line is a JSON object with the name of a file and the MD5 hash of its post, or `null` once the file
+/// is replaced by the one served. Lines are appended as files are processed, so an interrupted run keeps them.
+pub const PROCESSED_FILE: &str = ".ibdl_processed.json";
+
+/// A line of [`PROCESSED_FILE`].
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "ibdl_common::serde")]
+struct ProcessedEntry {
+    file: String,
+    md5: Option<String>,
+}
+
+/// Saves every file into a directory.
+///
+/// Files saved by a previous run with the other [`NameType`] are renamed instead of downloaded again.
+///
+/// Files marked with [`mark_processed`](StorageSink::mark_processed) are saved into [`PROCESSED_FILE`] along
+/// with the MD5 hash of their post, which is checked instead of the one of the file. The list is rewritten
+/// without the replaced files once the run finishes.
+#[derive(Debug, Clone)]
+pub struct FolderSink {
+    dir: PathBuf,
+    name_type: NameType,
+    /// MD5 hash of the post of every processed file, by file name.
+    processed: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl FolderSink {
+    /// Creates `dir` if needed. `name_type` is the naming scheme of the files written into it.
+    pub async fn create(dir: PathBuf, name_type: NameType) -> Result<Self, QueueError> {
+        debug!("Target dir: {}", dir.display());
+        create_dir_all(&dir)
+            .await
+            .map_err(|error| QueueError::DirCreationError {
+                message: error.to_string(),
+            })?;
+
+        let processed = match read_to_string(dir.join(PROCESSED_FILE)).await {
+            Ok(raw) => read_processed(&raw)?,
+            Err(error) if error.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Self {
+            dir,
+            name_type,
+            processed: Arc::new(Mutex::new(processed)),
+        })
+    }
+
+    /// Updates the MD5 hash of the post of the file `name`, or removes it if `md5` is `None`.
+    ///
+    /// Returns `true` if the list changed.
+    fn update_processed(&self, name: &str, md5: Option<&String>) -> bool {
+        let mut processed = self.processed.lock().unwrap();
+        match md5 {
+            Some(md5) => processed.insert(name.to_string(), md5.clone()).as_ref() != Some(md5),
+            None => processed.remove(name).is_some(),
+        }
+    }
+
+    /// Updates the MD5 hash of the post of the file `name`, or removes it if `md5` is `None`, and appends the
+    /// change to [`PROCESSED_FILE`].
+    async fn set_processed(&self, name: &str, md5: Option<String>) -> Result<(), PostError> {
+        if !self.update_processed(name, md5.as_ref()) {
+            return Ok(());
+        }
+
+        let entry = ProcessedEntry {
+            file: name.to_string(),
+            md5,
+        };
+        let mut line = serde_json::to_string(&entry).map_err(|error| PostError::StorageFail {
+            message: error.to_string(),
+        })?;
+        line.push('\n');
+
+        // Lines are written with a single call, so concurrent downloads don't mix them up.
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(PROCESSED_FILE))
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn md5_matches(path: &Path, post: &Post) -> Result<bool, PostError> {
+        let file_content = read(path).await?;
+        Ok(format!("{:x}", compute(file_content)) == post.md5)
+    }
+
+    /// Returns `true` if the file `name`, saved at `path`, is the one of `post`.
+    async fn is_saved(&self, path: &Path, name: &str, post: &Post) -> Result<bool, PostError> {
+        let processed = self.processed.lock().unwrap().get(name) == Some(&post.md5);
+        if processed {
+            return Ok(true);
+        }
+        Self::md5_matches(path, post).await
+    }
+}
+
+impl StorageSink for FolderSink {
+    type Item = FolderItem;
+
+    /// If no file is saved as `name`, the file with the other naming scheme is checked as well.
+    /// It is renamed if the hash matches.
+    ///
+    /// Files with a different hash are removed.
+    async fn exists(&self, post: &Post, name: &str) -> Result<Existing, PostError> {
+        let target = self.dir.join(name);
+
+        if target.exists() {
+            if self.is_saved(&target, name, post).await? {
+                return Ok(Existing::Skip(SkipReason::AlreadyExists));
+            }
+            self.set_processed(name, None).await?;
+            remove_file(&target).await?;
+            return Ok(Existing::Replaced {
+                removed: name.to_string(),
+            });
+        }
+
+        // Pool pages are only saved with their position in the pool
+        if name != post.file_name(self.name_type) {
+            return Ok(Existing::Missing);
+        }
+
+        let alternative_name = post.file_name(!self.name_type);
+        let alternative = self.dir.join(&alternative_name);
+
+        if alternative.exists() {
+            if self.is_saved(&alternative, &alternative_name, post).await? {
+                rename(&alternative, &target).await?;
+
+                let md5 = self
+                    .processed
+                    .lock()
+                    .unwrap()
+                    .get(&alternative_name)
+                    .cloned();
+                if md5.is_some() {
+                    self.set_processed(&alternative_name, None).await?;
+                    self.set_processed(name, md5).await?;
+                }
+
+                return Ok(Existing::Skip(SkipReason::Renamed {
+                    from: alternative_name,
+                }));
+            }
+            self.set_processed(&alternative_name, None).await?;
+            remove_file(&alternative).await?;
+            return Ok(Existing::Replaced {
+                removed: alternative_name,
+            });
+        }
+
+        Ok(Existing::Missing)
+    }
+
+    /// `name` can be inside a subdirectory, which is created if needed.
+    async fn open(&self, _post: &Post, name: &str) -> Result<FolderItem, PostError> {
+        let path = self.dir.join(name);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).await?;
+        }
+        // The file is replaced with the one served, until it's marked as processed again.
+        self.set_processed(name, None).await?;
+
+        // Written next to the final file, so renaming it once committed can't cross filesystems.
+        let part_path = path.with_file_name(format!(
+            ".{}.part",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        debug!("Creating/writing to file {:?}", &part_path);
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&part_path)
+            .await?;
+
+        Ok(FolderItem {
+            writer: BufWriter::new(file),
+            part_path,
+            path,
+        })
+    }
+
+    async fn mark_processed(&self, post: &Post, name: &str) -> Result<(), PostError> {
+        self.set_processed(name, Some(post.md5.clone())).await
+    }
+
+    /// Rewrites [`PROCESSED_FILE`] with only the files still processed, or removes it if there are none.
+    async fn finish(self) -> Result<(), QueueError> {
+        let path = self.dir.join(PROCESSED_FILE);
+        let processed = std::mem::take(&mut *self.processed.lock().unwrap());
+        if processed.is_empty() {
+            if path.exists() {
+                remove_file(&path).await?;
+            }
+            return Ok(());
+        }
+
+        let mut raw = String::new();
+        for (file, md5) in &processed {
+            let entry = ProcessedEntry {
+                file: file.clone(),
+                md5: Some(md5.clone()),
+            };
+            let line = serde_json::to_string(&entry).map_err(|error| {
+                QueueError::ProcessedListEncodeFail {
+                    error: error.to_string(),
+                }
+            })?;
+            raw.push_str(&line);
+            raw.push('\n');
+        }
+        write(&path, raw).await?;
+
+        debug!(
+            "Saved {} processed files into {}",
+            processed.len(),
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+/// Reads the files listed in [`PROCESSED_FILE`], replaying its lines in order.
+fn read_processed(raw: &str) -> Result<BTreeMap<String, String>, QueueError> {
+    let mut processed = BTreeMap::new();
+    for (index, line) in raw.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: ProcessedEntry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            // The last line may be cut short if the previous run was killed while writing it.
+            Err(_) if index + 1 == raw.lines().count() => break,
+            Err(error) => {
+                return Err(QueueError::ProcessedListDecodeFail {
+                    line: index + 1,
+                    error: error.to_string(),
+                });
+            }
+        };
+        match entry.md5 {
+            Some(md5) => processed.insert(entry.file, md5),
+            None => processed.remove(&entry.file),
+        };
+    }
+    Ok(processed)
+}
+
+/// A file being written into a [`FolderSink`].
+///
+/// The file is written into a hidden `.part` file next to it, which replaces it once committed.
+#[derive(Debug)]
+pub struct FolderItem {
+    writer: BufWriter<File>,
+    part_path: PathBuf,
+    path: PathBuf,
+}
+
+impl SinkItem for FolderItem {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), PostError> {
+        self.writer.write_all(chunk).await?;
+        Ok(())
+    }
+
+    async fn commit(mut self) -> Result<(), PostError> {
+        self.writer.flush().await?;
+        drop(self.writer);
+        rename(&self.part_path, &self.path).await?;
+        Ok(())
+    }
+
+    async fn abort(self) -> Result<(), PostError> {
+        drop(self.writer);
+        debug!("Removing partial file {}", self.part_path.display());
+        remove_file(&self.part_path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{md5_of, post};
+
+    async fn save(sink: &FolderSink, post: &Post, name: &str) {
+        let mut item = sink.open(post, name).await.unwrap();
+        item.write(b"processed").await.unwrap();
+        item.commit().await.unwrap();
+        sink.mark_processed(post, name).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn processed_files_are_saved_as_they_are_marked() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FolderSink::create(dir.path().to_path_buf(), NameType::ID)
+            .await
+            .unwrap();
+        let (first, second) = (post(1, &md5_of(1)), post(2, &md5_of(2)));
+
+        save(&sink, &first, "1.webp").await;
+        save(&sink, &second, "2.webp").await;
+        // Replaced by the file served, then processed again
+        save(&sink, &first, "1.webp").await;
+
+        // Another run reads the list before this one finishes
+        let resumed = FolderSink::create(dir.path().to_path_buf(), NameType::ID)
+            .await
+            .unwrap();
+        assert_eq!(
+            resumed.exists(&first, "1.webp").await.unwrap(),
+            Existing::Skip(SkipReason::AlreadyExists)
+        );
+        assert_eq!(
+            resumed.exists(&second, "2.webp").await.unwrap(),
+            Existing::Skip(SkipReason::AlreadyExists)
+        );
+
+        sink.finish().await.unwrap();
+        let raw = read_to_string(dir.path().join(PROCESSED_FILE))
+            .await
+            .unwrap();
+        assert_eq!(raw.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn files_are_only_replaced_once_committed() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = FolderSink::create(dir.path().to_path_buf(), NameType::ID)
+            .await
+            .unwrap();
+        let first = post(1, &md5_of(1));
+        let path = dir.path().join("1.webp");
+        write(&path, b"previous").await.unwrap();
+
+        let mut item = sink.open(&first, "1.webp").await.unwrap();
+        item.write(b"partial").await.unwrap();
+        item.abort().await.unwrap();
+        assert_eq!(read(&path).await.unwrap(), b"previous");
+        assert!(!dir.path().join(".1.webp.part").exists());
+
+        let mut item = sink.open(&first, "1.webp").await.unwrap();
+        item.write(b"served").await.unwrap();
+        assert_eq!(read(&path).await.unwrap(), b"previous");
+        item.commit().await.unwrap();
+        assert_eq!(read(&path).await.unwrap(), b"served");
+        assert!(!dir.path().join(".1.webp.part").exists());
+    }
+
+    #[test]
+    fn reads_processed_list() {
+        let raw = "{\"file\":\"1.webp\",\"md5\":\"a\"}\n\
+                   {\"file\":\"2.webp\",\"md5\":\"b\"}\n\
+                   {\"file\":\"1.webp\",\"md5\":null}\n\
+                   {\"file\":\"3.we";
+        assert_eq!(
+            read_processed(raw).unwrap(),
+            BTreeMap::from([("2.webp".to_string(), "b".to_string())])
+        );
+
+        assert!(matches!(
+            read_processed("not json\n{\"file\":\"1.webp\",\"md5\":\"a\"}"),
+            Err(QueueError::ProcessedListDecodeFail { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! Storage backends for the download [`Queue`](crate::async_queue::Queue).
+//!
+//! The queue only downloads files, everything related to where they are saved goes through a [`StorageSink`]:
+//! checking if a post is already saved, opening a new file, writing its chunks as they arrive and committing
+//! or aborting it. This crate includes:
+//!
+//! * [`FolderSink`]: Saves every file into a directory.
+//! * [`CbzSink`]: Saves every file into a CBZ archive, along with a [`CbzSummary`]. Needs the `cbz` feature.
+//! * [`TarSink`]: Saves every file into a tar archive, optionally compressed with zstd. Needs the `tar` feature.
+//! * [`ImageFolderSink`]: Saves every file into a directory, along with a `metadata.jsonl` for training datasets.
+//! * [`WebDatasetSink`]: Saves every post as a sample of WebDataset tar shards. Needs the `tar` feature.
+//! * [`S3Sink`]: Uploads every file into a bucket of an S3-compatible object storage. Needs the `s3` feature.
+//! * [`ProcessSink`]: Resizes and re-encodes every image before saving it into another sink. Needs the `image`
+//!   feature.
+//!
+//! Other sinks can be used with [`Queue::setup_sink_downloader`](crate::async_queue::Queue::setup_sink_downloader).
+use std::future::Future;
+
+use ibdl_common::post::{Post, error::PostError};
+
+use crate::{error::QueueError, events::SkipReason};
+
+#[cfg(any(feature = "cbz", feature = "tar"))]
+mod archive;
+#[cfg(feature = "cbz")]
+mod cbz;
+#[cfg(feature = "cbz")]
+mod comic_info;
+mod dataset;
+mod folder;
+#[cfg(feature = "image")]
+mod process;
+#[cfg(feature = "s3")]
+mod s3;
+#[cfg(feature = "tar")]
+mod tar;
+#[cfg(feature = "tar")]
+mod webdataset;
+
+#[cfg(feature = "cbz")]
+pub use cbz::{
+    CbzItem, CbzSink, CbzSummary, ExistingArchive, SUMMARY_FILE, SummaryPost, VolumeLimits,
+    ZipCompression,
+};
+#[cfg(feature = "cbz")]
+pub use comic_info::COMIC_INFO_FILE;
+pub use dataset::{DatasetRecord, ImageFolderItem, ImageFolderSink, METADATA_FILE};
+pub use folder::{FolderItem, FolderSink, PROCESSED_FILE};
+#[cfg(feature = "image")]
+pub use process::{BufferedItem, ProcessItem, ProcessSink};
+#[cfg(feature = "s3")]
+pub use s3::{S3Config, S3Item, S3Sink};
+#[cfg(feature = "tar")]
+pub use tar::{TarCompression, TarItem, TarSink};
+#[cfg(feature = "tar")]
+pub(crate) use webdataset::read_shard_records;
+#[cfg(feature = "tar")]
+pub use webdataset::{DEFAULT_SHARD_SIZE, WebDatasetItem, WebDatasetSink};
+
+/// What a sink found when checking for a post that may already be saved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Existing {
+    /// There is no file for this post, so it must be downloaded.
+    Missing,
+    /// The file is already saved with the same MD5 hash, so the download is skipped.
+    Skip(SkipReason),
+    /// A file with a different MD5 hash was removed, so the post must be downloaded again.
+    Replaced { removed: String },
+}
+
+/// Where the files downloaded by the queue are saved.
+///
+/// A single sink is shared by all downloads of a run, so all methods can be called concurrently.
+pub trait StorageSink: Send + Sync + 'static {
+    /// A file being written, returned by [`open`](Self::open).
+    type Item: SinkItem;
+
+    /// Checks if the file `name` of `post` is already saved with the MD5 hash of the post.
+    fn exists(
+        &self,
+        post: &Post,
+        name: &str,
+    ) -> impl Future<Output = Result<Existing, PostError>> + Send;
+
+    /// Starts writing the file `name` of `post`. Nothing should be visible until the item is committed.
+    fn open(
+        &self,
+        post: &Post,
+        name: &str,
+    ) -> impl Future<Output = Result<Self::Item, PostError>> + Send;
+
+    /// Saves a small file that is already in memory, like a caption.
+    fn put(
+        &self,
+        post: &Post,
+        name: &str,
+        data: Vec<u8>,
+    ) -> impl Future<Output = Result<(), PostError>> + Send {
+        async move {
+            let mut item = self.open(post, name).await?;
+            item.write(&data).await?;
+            item.commit().await
+        }
+    }
+
+    /// Called once the file `name` of `post` was saved with other contents than the ones served, like a resized
+    /// or converted image. Sinks that check saved files by their own MD5 hash must remember the one of the post,
+    /// so [`exists`](Self::exists) still recognises them.
+    fn mark_processed(
+        &self,
+        _post: &Post,
+        _name: &str,
+    ) -> impl Future<Output = Result<(), PostError>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Called once after every post was processed, including when the run is cancelled.
+    fn finish(self) -> impl Future<Output = Result<(), QueueError>> + Send;
+}
+
+/// A single file being written into a [`StorageSink`].
+pub trait SinkItem: Send {
+    /// Writes the next chunk of the file.
+    fn write(&mut self, chunk: &[u8]) -> impl Future<Output = Result<(), PostError>> + Send;
+
+    /// Saves the file after all chunks were written.
+    fn commit(self) -> impl Future<Output = Result<(), PostError>> + Send;
+
+    /// Discards the file, removing everything written so far.
+    fn abort(self) -> impl Future<Output = Result<(), PostError>> + Send;
+}
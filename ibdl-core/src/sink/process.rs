@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use ibdl_common::{
+    log::debug,
+    post::{Post, error::PostError},
+    tokio::task::spawn_blocking,
+};
+
+use super::{Existing, SinkItem, StorageSink};
+use crate::{
+    error::QueueError,
+    process::{ImageProcessor, ORIGINALS_DIR},
+};
+
+/// Processes every image with an [`ImageProcessor`] before saving it into another sink.
+///
+/// Images are kept in memory until they are fully downloaded, then processed and saved under their new name.
+/// Files that can't be processed, like videos, are written straight into the inner sink.
+/// With [`ImageProcessor::keep_original`], the original file is saved as well, inside [`ORIGINALS_DIR`].
+///
+/// Processed files don't have the MD5 hash of their post, so they are marked with
+/// [`StorageSink::mark_processed`] to be recognised by later runs.
+pub struct ProcessSink<S> {
+    inner: Arc<S>,
+    processor: Arc<ImageProcessor>,
+}
+
+impl<S: StorageSink> ProcessSink<S> {
+    pub fn new(inner: S, processor: ImageProcessor) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            processor: Arc::new(processor),
+        }
+    }
+}
+
+/// Name of the original of the file `name`, when it's kept.
+fn original_name(name: &str) -> String {
+    format!("{ORIGINALS_DIR}/{name}")
+}
+
+/// Saves `data` into `sink` as the file `name` of `post`.
+async fn save<S: StorageSink>(
+    sink: &S,
+    post: &Post,
+    name: &str,
+    data: Vec<u8>,
+) -> Result<(), PostError> {
+    let mut item = sink.open(post, name).await?;
+    if let Err(error) = item.write(&data).await {
+        item.abort().await?;
+        return Err(error);
+    }
+    item.commit().await
+}
+
+impl<S: StorageSink> StorageSink for ProcessSink<S> {
+    type Item = ProcessItem<S>;
+
+    /// If originals are kept, the original file is checked first, then the processed one.
+    async fn exists(&self, post: &Post, name: &str) -> Result<Existing, PostError> {
+        if self.processor.keep_original && self.processor.can_process(post, name) {
+            let original = self.inner.exists(post, &original_name(name)).await?;
+            if original != Existing::Missing {
+                return Ok(original);
+            }
+        }
+
+        self.inner
+            .exists(post, &self.processor.processed_name(post, name))
+            .await
+    }
+
+    async fn open(&self, post: &Post, name: &str) -> Result<ProcessItem<S>, PostError> {
+        if !self.processor.can_process(post, name) {
+            return Ok(ProcessItem::Direct(self.inner.open(post, name).await?));
+        }
+
+        Ok(ProcessItem::Buffered(BufferedItem {
+            sink: self.inner.clone(),
+            processor: self.processor.clone(),
+            post: post.clone(),
+            name: name.to_string(),
+            data: Vec::new(),
+        }))
+    }
+
+    /// Small files, like captions, are saved as they are.
+    async fn put(&self, post: &Post, name: &str, data: Vec<u8>) -> Result<(), PostError> {
+        self.inner.put(post, name, data).await
+    }
+
+    async fn mark_processed(&self, post: &Post, name: &str) -> Result<(), PostError> {
+        self.inner.mark_processed(post, name).await
+    }
+
+    async fn finish(self) -> Result<(), QueueError> {
+        let inner = Arc::try_unwrap(self.inner)
+            .map_err(|_arc_still_has_clones| QueueError::SinkStillShared)?;
+        inner.finish().await
+    }
+}
+
+/// A file being downloaded into a [`ProcessSink`].
+pub enum ProcessItem<S: StorageSink> {
+    /// A file that can't be processed, written into the inner sink as it's downloaded.
+    Direct(S::Item),
+    /// An image kept in memory until it's fully downloaded.
+    Buffered(BufferedItem<S>),
+}
+
+/// An image being downloaded into a [`ProcessSink`], processed once committed.
+pub struct BufferedItem<S> {
+    sink: Arc<S>,
+    processor: Arc<ImageProcessor>,
+    post: Post,
+    name: String,
+    data: Vec<u8>,
+}
+
+impl<S: StorageSink> SinkItem for ProcessItem<S> {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), PostError> {
+        match self {
+            Self::Direct(item) => item.write(chunk).await,
+            Self::Buffered(item) => {
+                item.data.extend_from_slice(chunk);
+                Ok(())
+            }
+        }
+    }
+
+    async fn commit(self) -> Result<(), PostError> {
+        match self {
+            Self::Direct(item) => item.commit().await,
+            Self::Buffered(item) => item.commit().await,
+        }
+    }
+
+    /// Buffered images are only saved once committed, so there is nothing to remove.
+    async fn abort(self) -> Result<(), PostError> {
+        match self {
+            Self::Direct(item) => item.abort().await,
+            Self::Buffered(_) => Ok(()),
+        }
+    }
+}
+
+impl<S: StorageSink> BufferedItem<S> {
+    async fn commit(self) -> Result<(), PostError> {
+        let keep_original = self.processor.keep_original;
+        let processor = self.processor;
+        let (post, name, data) = (self.post.clone(), self.name.clone(), self.data);
+
+        let (data, processed) = spawn_blocking(move || {
+            let processed = processor.process(&post, &name, &data);
+            (data, processed)
+        })
+        .await
+        .map_err(|thread_error| PostError::StorageFail {
+            message: thread_error.to_string(),
+        })?;
+
+        let Some((processed_name, processed)) = processed? else {
+            return save(self.sink.as_ref(), &self.post, &self.name, data).await;
+        };
+        debug!("Processed {} into {}", self.name, processed_name);
+
+        if keep_original {
+            self.sink
+                .put(&self.post, &original_name(&self.name), data)
+                .await?;
+        }
+        save(self.sink.as_ref(), &self.post, &processed_name, processed).await?;
+        self.sink.mark_processed(&self.post, &processed_name).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ibdl_common::{post::NameType, tokio::fs::read};
+
+    use super::*;
+    use crate::{
+        process::{Buckets, OutputFormat},
+        sink::FolderSink,
+        test::{md5_of, post},
+    };
+
+    async fn sink(dir: &std::path::Path) -> ProcessSink<FolderSink> {
+        let processor = ImageProcessor {
+            buckets: Some(Buckets {
+                area: 64 * 64,
+                step: 64,
+            }),
+            format: Some(OutputFormat::Webp),
+            ..Default::default()
+        };
+        let folder = FolderSink::create(dir.to_path_buf(), NameType::MD5)
+            .await
+            .unwrap();
+        ProcessSink::new(folder, processor)
+    }
+
+    #[tokio::test]
+    async fn files_that_cant_be_processed_are_not_buffered() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = sink(dir.path()).await;
+        let post = post(1, &md5_of(1));
+
+        let mut item = sink.open(&post, "1.mp4").await.unwrap();
+        assert!(matches!(item, ProcessItem::Direct(_)));
+        item.write(b"video").await.unwrap();
+        item.commit().await.unwrap();
+
+        assert_eq!(read(dir.path().join("1.mp4")).await.unwrap(), b"video");
+    }
+
+    #[tokio::test]
+    async fn images_are_processed_once_committed() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = sink(dir.path()).await;
+        let post = post(1, &md5_of(1));
+
+        let image = image::DynamicImage::ImageRgb8(image::RgbImage::new(100, 100));
+        let mut png = Vec::new();
+        image
+            .write_with_encoder(image::codecs::png::PngEncoder::new(&mut png))
+            .unwrap();
+
+        let mut item = sink.open(&post, "1.png").await.unwrap();
+        assert!(matches!(item, ProcessItem::Buffered(_)));
+        item.write(&png).await.unwrap();
+        item.commit().await.unwrap();
+
+        assert!(!dir.path().join("1.png").exists());
+        let processed = image::open(dir.path().join("1.webp")).unwrap();
+        assert_eq!((processed.width(), processed.height()), (64, 64));
+    }
+}
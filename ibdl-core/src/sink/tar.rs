@@ -0,0 +1,218 @@
+use std::{
+    ffi::OsString,
+    fs::{File, rename},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use ibdl_common::{
+    log::debug,
+    post::{Post, error::PostError},
+    tokio::{fs::create_dir_all, task::spawn_blocking},
+};
+use tar::{Builder, EntryType, Header};
+use zstd::Encoder;
+
+use super::{
+    Existing, SinkItem, StorageSink,
+    archive::{RATING_DIRS, Spool, entry_name},
+};
+use crate::error::QueueError;
+
+/// How a tar archive is compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TarCompression {
+    /// A plain `.tar` archive.
+    #[default]
+    None,
+    /// A `.tar.zst` archive, compressed with zstd at a level from 1 to 22.
+    Zstd { level: i32 },
+}
+
+/// Where the tar archive is written.
+enum TarWriter {
+    Plain(File),
+    Zstd(Encoder<'static, File>),
+}
+
+impl TarWriter {
+    /// Flushes the last compressed frame, if any.
+    fn finish(self) -> io::Result<File> {
+        match self {
+            Self::Plain(file) => Ok(file),
+            Self::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl Write for TarWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(file) => file.write(buf),
+            Self::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(file) => file.flush(),
+            Self::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Saves every file into a tar archive, optionally compressed with zstd.
+///
+/// Files are laid out like in a [`CbzSink`](super::CbzSink): one directory for each rating, or every page at
+/// the root for pools. As with CBZ archives, files are spooled until they are fully downloaded and the archive
+/// is written to a `.part` file until [`finish`](StorageSink::finish) moves it into place.
+///
+/// Tar archives can't be appended to, so an existing archive is replaced and every post is downloaded.
+pub struct TarSink {
+    archive: Arc<Mutex<Builder<TarWriter>>>,
+    path: PathBuf,
+    part_path: PathBuf,
+    /// Where big files are spooled while they are downloaded.
+    spool_dir: PathBuf,
+    by_rating: bool,
+}
+
+impl TarSink {
+    /// Creates the archive at `path`.
+    ///
+    /// With `by_rating`, files are saved in one directory for each rating. Otherwise, they are all saved at
+    /// the root of the archive, as pages of a pool.
+    pub async fn create(
+        path: PathBuf,
+        by_rating: bool,
+        compression: TarCompression,
+    ) -> Result<Self, QueueError> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)
+                .await
+                .map_err(|error| QueueError::DirCreationError {
+                    message: error.to_string(),
+                })?;
+        }
+
+        let spool_dir = path
+            .parent()
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+
+        let mut part_path = OsString::from(path.as_os_str());
+        part_path.push(".part");
+        let part_path = PathBuf::from(part_path);
+
+        debug!("Target file: {}", path.display());
+        let file = File::create(&part_path)?;
+        let writer = match compression {
+            TarCompression::None => TarWriter::Plain(file),
+            TarCompression::Zstd { level } => TarWriter::Zstd(Encoder::new(file, level)?),
+        };
+
+        let mut archive = Builder::new(writer);
+        if by_rating {
+            for rating in RATING_DIRS {
+                let mut header = Self::header(EntryType::Directory, 0);
+                archive.append_data(&mut header, format!("{rating}/"), io::empty())?;
+            }
+        }
+
+        Ok(Self {
+            archive: Arc::new(Mutex::new(archive)),
+            path,
+            part_path,
+            spool_dir,
+            by_rating,
+        })
+    }
+
+    pub(super) fn header(entry_type: EntryType, size: u64) -> Header {
+        let mtime = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        let mut header = Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mtime(mtime);
+        header.set_mode(if entry_type.is_dir() { 0o755 } else { 0o644 });
+        header
+    }
+}
+
+impl StorageSink for TarSink {
+    type Item = TarItem;
+
+    async fn exists(&self, _post: &Post, _name: &str) -> Result<Existing, PostError> {
+        Ok(Existing::Missing)
+    }
+
+    async fn open(&self, post: &Post, name: &str) -> Result<TarItem, PostError> {
+        Ok(TarItem {
+            archive: self.archive.clone(),
+            entry: entry_name(post, name, self.by_rating),
+            spool: Spool::new(self.spool_dir.clone()),
+        })
+    }
+
+    async fn put(&self, post: &Post, name: &str, data: Vec<u8>) -> Result<(), PostError> {
+        let item = TarItem {
+            archive: self.archive.clone(),
+            entry: entry_name(post, name, self.by_rating),
+            spool: Spool::from_vec(data, self.spool_dir.clone()),
+        };
+        item.commit().await
+    }
+
+    async fn finish(self) -> Result<(), QueueError> {
+        // `into_inner()` consumes the `Builder`, so every item must have released its clone of the `Arc`.
+        let archive = Arc::try_unwrap(self.archive)
+            .map_err(|_arc_still_has_clones| QueueError::MutexLockReleaseError)?
+            .into_inner()
+            .map_err(|_poison_error| QueueError::MutexLockReleaseError)?;
+
+        archive.into_inner()?.finish()?;
+
+        rename(&self.part_path, &self.path)?;
+        debug!("Saved tar archive into {}", self.path.display());
+        Ok(())
+    }
+}
+
+/// A file being written into a [`TarSink`].
+pub struct TarItem {
+    archive: Arc<Mutex<Builder<TarWriter>>>,
+    entry: String,
+    spool: Spool,
+}
+
+impl SinkItem for TarItem {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), PostError> {
+        self.spool.write(chunk).await
+    }
+
+    async fn commit(self) -> Result<(), PostError> {
+        let (mut data, len) = self.spool.into_reader().await?;
+
+        spawn_blocking(move || -> Result<(), PostError> {
+            debug!("Writing {} to tar file", self.entry);
+            let mut header = TarSink::header(EntryType::Regular, len);
+            self.archive
+                .lock()
+                .unwrap()
+                .append_data(&mut header, &self.entry, &mut data)?;
+            Ok(())
+        })
+        .await
+        .map_err(|thread_error| PostError::StorageFail {
+            message: thread_error.to_string(),
+        })?
+    }
+
+    async fn abort(self) -> Result<(), PostError> {
+        Ok(())
+    }
+}
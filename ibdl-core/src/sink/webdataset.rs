@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    fs::{File, rename},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use ibdl_common::{
+    log::debug,
+    post::{Post, error::PostError},
+    serde_json,
+    tokio::{fs::create_dir_all, task::spawn_blocking},
+};
+use tar::{Archive, Builder, EntryType};
+
+use super::{DatasetRecord, Existing, SinkItem, StorageSink, TarSink, archive::Spool};
+use crate::{caption::CaptionConfig, error::QueueError, events::SkipReason};
+
+/// Number of samples in each shard, unless set otherwise.
+pub const DEFAULT_SHARD_SIZE: usize = 1000;
+
+/// Saves every post as a sample of a WebDataset, split into tar shards named `000000.tar`, `000001.tar`...
+///
+/// Each sample is made of the file of the post, `<key>.txt` with its caption and `<key>.json` with its
+/// [`DatasetRecord`], where the key is the name of the file without its extension. The files of a sample are
+/// written together once the post is fully downloaded, so they are always contiguous in the shard.
+///
+/// Shards saved by previous runs are never modified: new samples go into new shards, and posts already saved
+/// in one with the same MD5 hash are skipped.
+pub struct WebDatasetSink {
+    shards: Arc<Mutex<Shards>>,
+    /// MD5 hashes of the samples saved by previous runs, by key.
+    saved: HashMap<String, String>,
+    dir: PathBuf,
+    caption: CaptionConfig,
+}
+
+/// The shard being written.
+struct Shards {
+    dir: PathBuf,
+    shard_size: usize,
+    current: Option<Builder<File>>,
+    /// Number of the next shard to open.
+    next: usize,
+    samples: usize,
+}
+
+impl Shards {
+    fn part_path(&self, number: usize) -> PathBuf {
+        self.dir.join(format!("{number:06}.tar.part"))
+    }
+
+    fn append(&mut self, entry: &str, data: &mut dyn Read, size: u64) -> io::Result<()> {
+        let shard = match &mut self.current {
+            Some(shard) => shard,
+            None => {
+                let path = self.part_path(self.next);
+                debug!("Target file: {}", path.display());
+                self.next += 1;
+                self.samples = 0;
+                self.current.insert(Builder::new(File::create(path)?))
+            }
+        };
+
+        let mut header = TarSink::header(EntryType::Regular, size);
+        shard.append_data(&mut header, entry, data)
+    }
+
+    /// Writes the file of a sample, followed by its caption and its record.
+    fn add_sample(
+        &mut self,
+        record: &DatasetRecord,
+        data: &mut dyn Read,
+        size: u64,
+        json: &[u8],
+    ) -> io::Result<()> {
+        let key = sample_key(&record.file_name);
+        let caption = record.caption.as_bytes();
+
+        self.append(&record.file_name, data, size)?;
+        self.append(
+            &format!("{key}.txt"),
+            &mut &caption[..],
+            caption.len() as u64,
+        )?;
+        self.append(&format!("{key}.json"), &mut &json[..], json.len() as u64)?;
+
+        self.samples += 1;
+        if self.samples >= self.shard_size {
+            self.close()?;
+        }
+        Ok(())
+    }
+
+    /// Finishes the current shard and moves it into place.
+    fn close(&mut self) -> io::Result<()> {
+        if let Some(shard) = self.current.take() {
+            shard.into_inner()?;
+
+            let part_path = self.part_path(self.next - 1);
+            let path = part_path.with_extension("");
+            rename(&part_path, &path)?;
+            debug!("Saved {} samples into {}", self.samples, path.display());
+        }
+        Ok(())
+    }
+}
+
+impl WebDatasetSink {
+    /// Creates `dir` if needed. Each shard written into it holds up to `shard_size` samples.
+    pub async fn create(dir: PathBuf, shard_size: usize) -> Result<Self, QueueError> {
+        create_dir_all(&dir)
+            .await
+            .map_err(|error| QueueError::DirCreationError {
+                message: error.to_string(),
+            })?;
+
+        let shard_dir = dir.clone();
+        let (records, next) = spawn_blocking(move || read_shard_records(&shard_dir))
+            .await
+            .map_err(io::Error::from)??;
+        let saved = records
+            .into_iter()
+            .map(|record| (sample_key(&record.file_name).to_string(), record.md5))
+            .collect();
+
+        Ok(Self {
+            shards: Arc::new(Mutex::new(Shards {
+                dir: dir.clone(),
+                shard_size: shard_size.max(1),
+                current: None,
+                next,
+                samples: 0,
+            })),
+            saved,
+            dir,
+            caption: CaptionConfig::default(),
+        })
+    }
+
+    /// How the caption of every sample is built.
+    #[must_use]
+    pub fn caption(mut self, config: CaptionConfig) -> Self {
+        self.caption = config;
+        self
+    }
+}
+
+/// Reads the records of the samples in the shards of `dir` and returns them along with the number of shards.
+pub fn read_shard_records(dir: &Path) -> Result<(Vec<DatasetRecord>, usize), QueueError> {
+    let mut records = Vec::new();
+    let mut next = 0;
+
+    loop {
+        let path = dir.join(format!("{next:06}.tar"));
+        if !path.exists() {
+            break;
+        }
+
+        let mut shard = Archive::new(File::open(&path)?);
+        for entry in shard.entries_with_seek()? {
+            let mut entry = entry?;
+            if entry
+                .path()?
+                .extension()
+                .is_none_or(|extension| extension != "json")
+            {
+                continue;
+            }
+
+            let mut raw = String::new();
+            entry.read_to_string(&mut raw)?;
+            if let Ok(record) = serde_json::from_str::<DatasetRecord>(&raw) {
+                records.push(record);
+            }
+        }
+        next += 1;
+    }
+
+    Ok((records, next))
+}
+
+/// Name of `name` without its extension, used as the key of the sample.
+fn sample_key(name: &str) -> &str {
+    name.rsplit_once('.').map_or(name, |(key, _)| key)
+}
+
+impl StorageSink for WebDatasetSink {
+    type Item = WebDatasetItem;
+
+    async fn exists(&self, post: &Post, name: &str) -> Result<Existing, PostError> {
+        match self.saved.get(sample_key(name)) {
+            Some(md5) if *md5 == post.md5 => Ok(Existing::Skip(SkipReason::AlreadyExists)),
+            _ => Ok(Existing::Missing),
+        }
+    }
+
+    async fn open(&self, post: &Post, name: &str) -> Result<WebDatasetItem, PostError> {
+        Ok(WebDatasetItem {
+            shards: self.shards.clone(),
+            record: DatasetRecord::new(post, name.to_string(), &self.caption),
+            spool: Spool::new(self.dir.clone()),
+        })
+    }
+
+    /// Captions are already part of every sample, so other small files are ignored.
+    async fn put(&self, _post: &Post, name: &str, _data: Vec<u8>) -> Result<(), PostError> {
+        debug!("Ignoring {name}, which isn't part of a sample");
+        Ok(())
+    }
+
+    async fn finish(self) -> Result<(), QueueError> {
+        let mut shards = Arc::try_unwrap(self.shards)
+            .map_err(|_arc_still_has_clones| QueueError::MutexLockReleaseError)?
+            .into_inner()
+            .map_err(|_poison_error| QueueError::MutexLockReleaseError)?;
+
+        shards.close()?;
+        Ok(())
+    }
+}
+
+/// A post being written into a [`WebDatasetSink`].
+pub struct WebDatasetItem {
+    shards: Arc<Mutex<Shards>>,
+    record: DatasetRecord,
+    spool: Spool,
+}
+
+impl SinkItem for WebDatasetItem {
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), PostError> {
+        self.spool.write(chunk).await
+    }
+
+    async fn commit(self) -> Result<(), PostError> {
+        let (mut data, len) = self.spool.into_reader().await?;
+
+        let json = serde_json::to_vec(&self.record).map_err(|error| PostError::StorageFail {
+            message: error.to_string(),
+        })?;
+
+        spawn_blocking(move || -> Result<(), PostError> {
+            debug!("Writing sample {} to shard", self.record.file_name);
+            self.shards
+                .lock()
+                .unwrap()
+                .add_sample(&self.record, &mut data, len, &json)?;
+            Ok(())
+        })
+        .await
+        .map_err(|thread_error| PostError::StorageFail {
+            message: thread_error.to_string(),
+        })?
+    }
+
+    async fn abort(self) -> Result<(), PostError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,496 @@
+//! Tag, rating and extension distribution of a set of posts, used to review a dataset before training.
+//!
+//! [`TagStats`] is filled with every post saved by a run, see [`Queue::tag_stats`](crate::async_queue::Queue::tag_stats),
+//! or read back from an existing output with [`read_tag_stats`]. Its [`TagReport`] can be written as JSON or CSV.
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use ibdl_common::{
+    post::{Post, rating::Rating, tags::TagType},
+    serde::{Deserialize, Serialize},
+    serde_json,
+    tokio::fs::{File, read_dir, read_to_string, write},
+    tokio::io::AsyncWriteExt,
+};
+
+use crate::{
+    error::QueueError,
+    export::read_post_list,
+    sink::{DatasetRecord, METADATA_FILE},
+};
+
+/// Counts of the tags, ratings and extensions of a set of posts.
+#[derive(Debug, Clone, Default)]
+pub struct TagStats {
+    posts: u64,
+    /// Every tag seen, along with its type and the number of posts with it.
+    tags: Vec<(TagType, String, u64)>,
+    /// Position of every tag in `tags`.
+    index: HashMap<String, usize>,
+    ratings: BTreeMap<Rating, u64>,
+    extensions: BTreeMap<String, u64>,
+    /// Tags of every post, to count how often the most frequent ones appear together.
+    post_tags: Vec<Vec<usize>>,
+}
+
+impl TagStats {
+    /// Number of posts counted so far.
+    #[must_use]
+    pub const fn posts(&self) -> u64 {
+        self.posts
+    }
+
+    pub fn add_post(&mut self, post: &Post) {
+        self.add(
+            post.tags.iter().map(|tag| (tag.tag_type(), tag.tag())),
+            Some(post.rating),
+            Some(&post.extension.to_string()),
+        );
+    }
+
+    pub fn add_record(&mut self, record: &DatasetRecord) {
+        let tags = record
+            .tags
+            .iter()
+            .flat_map(|(tag_type, tags)| tags.iter().map(|tag| (*tag_type, tag.clone())));
+        let extension = record.file_name.rsplit_once('.').map(|(_, ext)| ext);
+
+        self.add(tags, Some(record.rating), extension);
+    }
+
+    /// Counts the tags of a caption written by `annotate`. Captions don't have tag types, so all tags are
+    /// counted as [`TagType::Any`].
+    pub fn add_caption(&mut self, caption: &str, extension: Option<&str>) {
+        let tags = caption
+            .split(',')
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(|tag| (TagType::Any, tag.replace(' ', "_")));
+
+        self.add(tags, None, extension);
+    }
+
+    fn add(
+        &mut self,
+        tags: impl Iterator<Item = (TagType, String)>,
+        rating: Option<Rating>,
+        extension: Option<&str>,
+    ) {
+        let mut post_tags = Vec::new();
+        for (tag_type, tag) in tags {
+            if tag.is_empty() {
+                continue;
+            }
+
+            let position = match self.index.get(&tag) {
+                Some(position) => *position,
+                None => {
+                    self.index.insert(tag.clone(), self.tags.len());
+                    self.tags.push((tag_type, tag, 0));
+                    self.tags.len() - 1
+                }
+            };
+
+            if !post_tags.contains(&position) {
+                self.tags[position].2 += 1;
+                post_tags.push(position);
+            }
+        }
+
+        self.posts += 1;
+        self.post_tags.push(post_tags);
+        if let Some(rating) = rating {
+            *self.ratings.entry(rating).or_default() += 1;
+        }
+        if let Some(extension) = extension {
+            *self
+                .extensions
+                .entry(extension.to_ascii_lowercase())
+                .or_default() += 1;
+        }
+    }
+
+    /// Builds the report of everything counted so far.
+    ///
+    /// Co-occurrences are counted between the `top` most frequent tags.
+    #[must_use]
+    pub fn report(&self, top: usize) -> TagReport {
+        let mut tags: BTreeMap<TagType, Vec<TagCount>> = BTreeMap::new();
+        for (tag_type, tag, count) in &self.tags {
+            tags.entry(*tag_type).or_default().push(TagCount {
+                tag: tag.clone(),
+                count: *count,
+            });
+        }
+        for counts in tags.values_mut() {
+            counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.tag.cmp(&b.tag)));
+        }
+
+        let mut by_count: Vec<usize> = (0..self.tags.len()).collect();
+        by_count.sort_by(|a, b| {
+            self.tags[*b]
+                .2
+                .cmp(&self.tags[*a].2)
+                .then_with(|| self.tags[*a].1.cmp(&self.tags[*b].1))
+        });
+        by_count.truncate(top);
+
+        let mut pairs: HashMap<(usize, usize), u64> = HashMap::new();
+        for post_tags in &self.post_tags {
+            let mut found: Vec<usize> = by_count
+                .iter()
+                .copied()
+                .filter(|tag| post_tags.contains(tag))
+                .collect();
+            found.sort_unstable();
+
+            for (i, a) in found.iter().enumerate() {
+                for b in &found[i + 1..] {
+                    *pairs.entry((*a, *b)).or_default() += 1;
+                }
+            }
+        }
+
+        let mut co_occurrence: Vec<TagPair> = pairs
+            .into_iter()
+            .map(|((a, b), count)| {
+                let (a, b) = (&self.tags[a].1, &self.tags[b].1);
+                TagPair {
+                    a: a.min(b).clone(),
+                    b: a.max(b).clone(),
+                    count,
+                }
+            })
+            .collect();
+        co_occurrence.sort_by(|x, y| {
+            y.count
+                .cmp(&x.count)
+                .then_with(|| x.a.cmp(&y.a))
+                .then_with(|| x.b.cmp(&y.b))
+        });
+
+        TagReport {
+            posts: self.posts,
+            tags,
+            ratings: self.ratings.clone(),
+            extensions: self.extensions.clone(),
+            co_occurrence,
+        }
+    }
+}
+
+/// Number of posts with a tag.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "ibdl_common::serde")]
+pub struct TagCount {
+    pub tag: String,
+    pub count: u64,
+}
+
+/// Number of posts with both tags.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "ibdl_common::serde")]
+pub struct TagPair {
+    pub a: String,
+    pub b: String,
+    pub count: u64,
+}
+
+/// Distribution of the tags, ratings and extensions of a set of posts, built by [`TagStats::report`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "ibdl_common::serde")]
+pub struct TagReport {
+    pub posts: u64,
+    /// Every tag grouped by type, from the most to the least frequent.
+    pub tags: BTreeMap<TagType, Vec<TagCount>>,
+    pub ratings: BTreeMap<Rating, u64>,
+    pub extensions: BTreeMap<String, u64>,
+    /// How often the most frequent tags appear together, from the most to the least frequent pair.
+    pub co_occurrence: Vec<TagPair>,
+}
+
+impl TagReport {
+    pub async fn write_json(&self, path: &Path) -> Result<(), QueueError> {
+        let raw =
+            serde_json::to_string_pretty(self).map_err(|error| QueueError::StatsSerializeFail {
+                error: error.to_string(),
+            })?;
+        write(path, raw).await?;
+        Ok(())
+    }
+
+    /// Writes the report as a single table with the columns `kind,tag_type,name,other,count`, where `kind` is
+    /// `tag`, `rating`, `extension` or `pair`. For pairs, `name` and `other` are both tags.
+    pub async fn write_csv(&self, path: &Path) -> Result<(), QueueError> {
+        let mut raw = String::from("kind,tag_type,name,other,count\n");
+        let mut row = |kind: &str, tag_type: &str, name: &str, other: &str, count: u64| {
+            raw.push_str(&format!(
+                "{kind},{},{},{},{count}\n",
+                csv_field(tag_type),
+                csv_field(name),
+                csv_field(other)
+            ));
+        };
+
+        for (tag_type, tags) in &self.tags {
+            let tag_type = format!("{tag_type:?}");
+            for tag in tags {
+                row("tag", &tag_type, &tag.tag, "", tag.count);
+            }
+        }
+        for (rating, count) in &self.ratings {
+            row("rating", "", &rating.to_string(), "", *count);
+        }
+        for (extension, count) in &self.extensions {
+            row("extension", "", extension, "", *count);
+        }
+        for pair in &self.co_occurrence {
+            row("pair", "", &pair.a, &pair.b, pair.count);
+        }
+
+        let mut file = File::create(path).await?;
+        file.write_all(raw.as_bytes()).await?;
+        Ok(())
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, a quote or a line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Counts the posts of an existing output.
+///
+/// `path` can be a post list written with `--export posts`, or a directory with a `metadata.jsonl`, WebDataset
+/// shards (with the `tar` feature) or captions written by `annotate`.
+pub async fn read_tag_stats(path: &Path) -> Result<TagStats, QueueError> {
+    let mut stats = TagStats::default();
+
+    if path.is_file() {
+        for post in read_post_list(path).await? {
+            stats.add_post(&post);
+        }
+        return Ok(stats);
+    }
+
+    let metadata = path.join(METADATA_FILE);
+    if metadata.is_file() {
+        let raw = read_to_string(&metadata).await?;
+        for (index, line) in raw.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: DatasetRecord =
+                serde_json::from_str(line).map_err(|error| QueueError::MetadataDecodeFail {
+                    file: metadata.display().to_string(),
+                    line: index + 1,
+                    error: error.to_string(),
+                })?;
+            stats.add_record(&record);
+        }
+        return Ok(stats);
+    }
+
+    #[cfg(feature = "tar")]
+    {
+        let dir = path.to_path_buf();
+        let (records, shards) =
+            ibdl_common::tokio::task::spawn_blocking(move || crate::sink::read_shard_records(&dir))
+                .await
+                .map_err(std::io::Error::from)??;
+
+        if shards > 0 {
+            for record in &records {
+                stats.add_record(record);
+            }
+            return Ok(stats);
+        }
+    }
+
+    read_captions(path, &mut stats).await?;
+    if stats.posts() == 0 {
+        return Err(QueueError::NoTagStats {
+            path: path.display().to_string(),
+        });
+    }
+    Ok(stats)
+}
+
+/// Counts every caption in `dir`, along with the extension of the file it belongs to.
+async fn read_captions(dir: &Path, stats: &mut TagStats) -> Result<(), QueueError> {
+    let mut extensions: HashMap<String, String> = HashMap::new();
+    let mut captions = Vec::new();
+
+    let mut entries = read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+            continue;
+        };
+        let stem = stem.to_string_lossy().to_string();
+
+        if extension == "txt" {
+            captions.push((stem, path));
+        } else {
+            extensions.insert(stem, extension.to_string_lossy().to_string());
+        }
+    }
+
+    for (stem, path) in captions {
+        let caption = read_to_string(&path).await?;
+        stats.add_caption(&caption, extensions.get(&stem).map(String::as_str));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ibdl_common::post::{extension::Extension, tags::Tag};
+
+    use super::*;
+    use crate::test::{md5_of, post};
+
+    fn tagged_post(id: u64, rating: Rating, tags: &[(&str, TagType)]) -> Post {
+        let mut post = post(id, &md5_of(id));
+        post.rating = rating;
+        post.tags = tags
+            .iter()
+            .map(|(tag, tag_type)| Tag::new(tag, *tag_type))
+            .collect();
+        post
+    }
+
+    fn counts(report: &TagReport, tag_type: TagType) -> Vec<(&str, u64)> {
+        report.tags[&tag_type]
+            .iter()
+            .map(|count| (count.tag.as_str(), count.count))
+            .collect()
+    }
+
+    fn sample() -> TagStats {
+        let mut stats = TagStats::default();
+        stats.add_post(&tagged_post(
+            1,
+            Rating::Safe,
+            &[
+                ("umbreon", TagType::Character),
+                ("night", TagType::General),
+                ("night", TagType::General),
+                ("", TagType::General),
+            ],
+        ));
+        stats.add_post(&tagged_post(
+            2,
+            Rating::Explicit,
+            &[
+                ("umbreon", TagType::Character),
+                ("espeon", TagType::Character),
+                ("night", TagType::General),
+            ],
+        ));
+        let mut jpeg = tagged_post(3, Rating::Safe, &[("espeon", TagType::Character)]);
+        jpeg.extension = Extension::JPG;
+        stats.add_post(&jpeg);
+        stats
+    }
+
+    #[test]
+    fn counts_each_tag_once_per_post() {
+        let report = sample().report(10);
+
+        assert_eq!(report.posts, 3);
+        assert_eq!(
+            counts(&report, TagType::Character),
+            [("espeon", 2), ("umbreon", 2)]
+        );
+        assert_eq!(counts(&report, TagType::General), [("night", 2)]);
+        assert_eq!(
+            report.ratings,
+            BTreeMap::from([(Rating::Safe, 2), (Rating::Explicit, 1)])
+        );
+        assert_eq!(
+            report.extensions,
+            BTreeMap::from([("jpg".to_string(), 1), ("png".to_string(), 2)])
+        );
+    }
+
+    #[test]
+    fn co_occurrence_is_counted_between_top_tags() {
+        let pairs = |report: &TagReport| {
+            report
+                .co_occurrence
+                .iter()
+                .map(|pair| (pair.a.clone(), pair.b.clone(), pair.count))
+                .collect::<Vec<_>>()
+        };
+
+        let report = sample().report(10);
+        assert_eq!(
+            pairs(&report),
+            [
+                ("night".to_string(), "umbreon".to_string(), 2),
+                ("espeon".to_string(), "night".to_string(), 1),
+                ("espeon".to_string(), "umbreon".to_string(), 1),
+            ]
+        );
+
+        // Ties are broken alphabetically, so the top 2 tags are espeon and night
+        let report = sample().report(2);
+        assert_eq!(
+            pairs(&report),
+            [("espeon".to_string(), "night".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn captions_are_counted_without_types() {
+        let mut stats = TagStats::default();
+        stats.add_caption("long hair, bow, , long hair", Some("PNG"));
+
+        let report = stats.report(10);
+        assert_eq!(
+            counts(&report, TagType::Any),
+            [("bow", 1), ("long_hair", 1)]
+        );
+        assert!(report.ratings.is_empty());
+        assert_eq!(report.extensions, BTreeMap::from([("png".to_string(), 1)]));
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("umbreon"), "umbreon");
+        assert_eq!(csv_field("1,2"), "\"1,2\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[tokio::test]
+    async fn reads_captions_of_a_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path().join("1.txt"), "umbreon, night")
+            .await
+            .unwrap();
+        write(dir.path().join("1.jpg"), "image").await.unwrap();
+        write(dir.path().join("2.txt"), "umbreon").await.unwrap();
+
+        let report = read_tag_stats(dir.path()).await.unwrap().report(10);
+        assert_eq!(report.posts, 2);
+        assert_eq!(
+            counts(&report, TagType::Any),
+            [("umbreon", 2), ("night", 1)]
+        );
+        assert_eq!(report.extensions, BTreeMap::from([("jpg".to_string(), 1)]));
+    }
+
+    #[tokio::test]
+    async fn empty_directory_has_no_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = read_tag_stats(dir.path()).await.unwrap_err();
+        assert!(matches!(error, QueueError::NoTagStats { .. }));
+    }
+}
@@ -0,0 +1,80 @@
+#![cfg(test)]
+//! Helpers shared by the unit tests of this crate.
+use ibdl_common::{
+    ImageBoards,
+    post::{Post, extension::Extension, rating::Rating},
+    tokio::{
+        self,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    },
+};
+
+/// A Danbooru post with no tags, saved as a PNG file.
+pub fn post(id: u64, md5: &str) -> Post {
+    Post {
+        id,
+        website: ImageBoards::Danbooru,
+        url: format!("https://cdn.donmai.us/original/{md5}.png"),
+        md5: md5.to_string(),
+        extension: Extension::PNG,
+        rating: Rating::Safe,
+        tags: Vec::new(),
+        score: None,
+        ugoira_frames: Vec::new(),
+    }
+}
+
+/// A fake MD5 hash made from `id`, so every post of a test has a different one.
+pub fn md5_of(id: u64) -> String {
+    format!("{id:032x}")
+}
+
+/// Starts an HTTP server answering each request with the next of `bodies`, then with 404 once they run out.
+///
+/// Returns the URL of the server.
+pub async fn serve(bodies: Vec<&'static [u8]>) -> String {
+    serve_with(bodies, false).await
+}
+
+/// Like [`serve`], but sends the bodies with chunked encoding, so their size isn't known in advance.
+pub async fn serve_chunked(bodies: Vec<&'static [u8]>) -> String {
+    serve_with(bodies, true).await
+}
+
+async fn serve_with(bodies: Vec<&'static [u8]>, chunked: bool) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/file.png", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        let mut bodies = bodies.into_iter();
+        while let Ok((mut stream, _)) = listener.accept().await {
+            // Requests are only GETs, so they end with the headers.
+            let mut request = Vec::new();
+            let mut chunk = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut chunk).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(read) => request.extend_from_slice(&chunk[..read]),
+                }
+            }
+
+            let (status, body) = bodies
+                .next()
+                .map_or(("404 Not Found", &b""[..]), |body| ("200 OK", body));
+            let (length, body) = if chunked && !body.is_empty() {
+                let mut encoded = format!("{:x}\r\n", body.len()).into_bytes();
+                encoded.extend_from_slice(body);
+                encoded.extend_from_slice(b"\r\n0\r\n\r\n");
+                (String::from("transfer-encoding: chunked"), encoded)
+            } else {
+                (format!("content-length: {}", body.len()), body.to_vec())
+            };
+            let head = format!("HTTP/1.1 {status}\r\n{length}\r\nconnection: close\r\n\r\n");
+            let _ = stream.write_all(head.as_bytes()).await;
+            let _ = stream.write_all(&body).await;
+        }
+    });
+
+    url
+}
@@ -0,0 +1,347 @@
+//! Conversion of Pixiv Ugoira into playable animations.
+//!
+//! Ugoira are served as zip archives with every frame as a separate image, while the delay of each frame comes
+//! with the post, in [`Post::ugoira_frames`](ibdl_common::post::Post::ugoira_frames). Frames are converted as
+//! they are, without resizing them.
+use std::{
+    fmt::Display,
+    io::{Cursor, Read},
+};
+
+use clap::ValueEnum;
+use ibdl_common::post::{UgoiraFrame, error::PostError, extension::Extension};
+use image::{
+    DynamicImage, ExtendedColorType, ImageDecoder, ImageEncoder, ImageReader,
+    codecs::webp::WebPEncoder,
+};
+use png::{BitDepth, ColorType, Encoder};
+use zip::ZipArchive;
+
+use crate::process::FILTER;
+
+/// Flags of the `VP8X` chunk of an animated WebP.
+const WEBP_ANIMATION: u8 = 0b0000_0010;
+const WEBP_ALPHA: u8 = 0b0001_0000;
+/// Flag of an `ANMF` chunk to replace the previous frame instead of drawing over it.
+const WEBP_NO_BLEND: u8 = 0b0000_0010;
+/// Size of the `RIFF` header of a WebP file, before its first chunk.
+const WEBP_HEADER_SIZE: usize = 12;
+
+/// Formats Ugoira can be converted into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UgoiraFormat {
+    /// Lossless animated WebP
+    Webp,
+    /// Animated PNG
+    Apng,
+}
+
+impl UgoiraFormat {
+    pub const fn extension(self) -> Extension {
+        match self {
+            Self::Webp => Extension::WEBP,
+            Self::Apng => Extension::PNG,
+        }
+    }
+
+    /// Converts the zip archive `data` of an Ugoira into an animation showing `frames` in order, looping forever.
+    pub fn convert(self, frames: &[UgoiraFrame], data: &[u8]) -> Result<Vec<u8>, PostError> {
+        let images = Frames::open(frames, data)?;
+        match self {
+            Self::Webp => images.encode_webp(),
+            Self::Apng => images.encode_apng(),
+        }
+    }
+}
+
+/// The frames of an Ugoira, decoded one at a time with the size of the first one and 8 bits per channel, so
+/// only one of them is kept in memory while the animation is encoded.
+struct Frames<'a> {
+    archive: ZipArchive<Cursor<&'a [u8]>>,
+    frames: &'a [UgoiraFrame],
+    width: u32,
+    height: u32,
+    /// Frames have an alpha channel if any of them has one.
+    alpha: bool,
+}
+
+impl<'a> Frames<'a> {
+    /// Reads the size of the first frame, and the color type of every frame, without decoding them.
+    fn open(frames: &'a [UgoiraFrame], data: &'a [u8]) -> Result<Self, PostError> {
+        let archive = ZipArchive::new(Cursor::new(data)).map_err(ugoira_error)?;
+        let Some(first) = frames.first() else {
+            return Err(ugoira_error("the post has no frames"));
+        };
+
+        let mut images = Self {
+            archive,
+            frames,
+            width: 0,
+            height: 0,
+            alpha: false,
+        };
+        (images.width, images.height) = images.decoder(first)?.dimensions();
+        for frame in frames {
+            if images.decoder(frame)?.color_type().has_alpha() {
+                images.alpha = true;
+                break;
+            }
+        }
+        Ok(images)
+    }
+
+    /// Reads the file of `frame` and returns a decoder that only read its header.
+    fn decoder(&mut self, frame: &UgoiraFrame) -> Result<impl ImageDecoder, PostError> {
+        let mut file = self.archive.by_name(&frame.file).map_err(ugoira_error)?;
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer).map_err(ugoira_error)?;
+
+        ImageReader::new(Cursor::new(buffer))
+            .with_guessed_format()
+            .map_err(ugoira_error)?
+            .into_decoder()
+            .map_err(ugoira_error)
+    }
+
+    /// Decodes `frame` into its raw pixels.
+    fn pixels(&mut self, frame: &UgoiraFrame) -> Result<Vec<u8>, PostError> {
+        let image = DynamicImage::from_decoder(self.decoder(frame)?).map_err(ugoira_error)?;
+        let image = if (image.width(), image.height()) == (self.width, self.height) {
+            image
+        } else {
+            image.resize_exact(self.width, self.height, FILTER)
+        };
+
+        Ok(if self.alpha {
+            image.into_rgba8().into_raw()
+        } else {
+            image.into_rgb8().into_raw()
+        })
+    }
+
+    /// Builds an animated WebP out of a lossless WebP image for every frame.
+    fn encode_webp(mut self) -> Result<Vec<u8>, PostError> {
+        let color = if self.alpha {
+            ExtendedColorType::Rgba8
+        } else {
+            ExtendedColorType::Rgb8
+        };
+        let flags = if self.alpha {
+            WEBP_ANIMATION | WEBP_ALPHA
+        } else {
+            WEBP_ANIMATION
+        };
+
+        let mut chunks = Vec::new();
+        // The flags are followed by 3 reserved bytes and the size of the canvas.
+        let mut header = vec![flags, 0, 0, 0];
+        header.extend(u24(self.width - 1));
+        header.extend(u24(self.height - 1));
+        webp_chunk(&mut chunks, b"VP8X", &header)?;
+        // Transparent background, looping forever.
+        webp_chunk(&mut chunks, b"ANIM", &[0; 6])?;
+
+        for frame in self.frames {
+            let pixels = self.pixels(frame)?;
+            let mut still = Vec::new();
+            WebPEncoder::new_lossless(&mut still)
+                .write_image(&pixels, self.width, self.height, color)
+                .map_err(ugoira_error)?;
+
+            let mut payload = [
+                u24(0),
+                u24(0),
+                u24(self.width - 1),
+                u24(self.height - 1),
+                u24(frame.delay),
+            ]
+            .concat();
+            payload.push(WEBP_NO_BLEND);
+            // A still image is a single VP8L chunk after the header, which becomes the data of the frame.
+            payload.extend_from_slice(&still[WEBP_HEADER_SIZE..]);
+            webp_chunk(&mut chunks, b"ANMF", &payload)?;
+        }
+
+        let mut data = Vec::with_capacity(WEBP_HEADER_SIZE + chunks.len());
+        data.extend_from_slice(b"RIFF");
+        data.extend_from_slice(&chunk_size(chunks.len() + 4)?);
+        data.extend_from_slice(b"WEBP");
+        data.append(&mut chunks);
+        Ok(data)
+    }
+
+    fn encode_apng(mut self) -> Result<Vec<u8>, PostError> {
+        let mut data = Vec::new();
+        let mut encoder = Encoder::new(&mut data, self.width, self.height);
+        encoder.set_color(if self.alpha {
+            ColorType::Rgba
+        } else {
+            ColorType::Rgb
+        });
+        encoder.set_depth(BitDepth::Eight);
+        let num_frames = u32::try_from(self.frames.len()).map_err(ugoira_error)?;
+        encoder.set_animated(num_frames, 0).map_err(ugoira_error)?;
+
+        let mut writer = encoder.write_header().map_err(ugoira_error)?;
+        for frame in self.frames {
+            let pixels = self.pixels(frame)?;
+            let (numerator, denominator) = apng_delay(frame.delay);
+            writer
+                .set_frame_delay(numerator, denominator)
+                .map_err(ugoira_error)?;
+            writer.write_image_data(&pixels).map_err(ugoira_error)?;
+        }
+        writer.finish().map_err(ugoira_error)?;
+        Ok(data)
+    }
+}
+
+/// `delay` in milliseconds as the fraction of a second used by APNG, whose numerator and denominator only take
+/// 16 bits. Long delays lose precision instead of being cut short, up to the longest one that fits.
+fn apng_delay(delay: u32) -> (u16, u16) {
+    for denominator in [1000, 100, 10] {
+        if let Ok(numerator) = u16::try_from(delay / (1000 / u32::from(denominator))) {
+            return (numerator, denominator);
+        }
+    }
+    (u16::try_from(delay / 1000).unwrap_or(u16::MAX), 1)
+}
+
+/// Appends a chunk of a WebP file, padded to an even size.
+fn webp_chunk(data: &mut Vec<u8>, name: &[u8; 4], payload: &[u8]) -> Result<(), PostError> {
+    data.extend_from_slice(name);
+    data.extend_from_slice(&chunk_size(payload.len())?);
+    data.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        data.push(0);
+    }
+    Ok(())
+}
+
+fn chunk_size(size: usize) -> Result<[u8; 4], PostError> {
+    u32::try_from(size)
+        .map(u32::to_le_bytes)
+        .map_err(|_| ugoira_error("the animation is too big for a WebP file"))
+}
+
+/// `value` as 3 bytes in little endian, as used by WebP for sizes and durations, capped to the biggest one that
+/// fits.
+const fn u24(value: u32) -> [u8; 3] {
+    let [first, second, third, _] = value.to_le_bytes();
+    if value > 0xFF_FFFF {
+        return [0xFF; 3];
+    }
+    [first, second, third]
+}
+
+fn ugoira_error(error: impl Display) -> PostError {
+    PostError::ProcessFail {
+        message: format!("Ugoira conversion failed: {error}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use image::{
+        AnimationDecoder, DynamicImage, Rgba, RgbaImage, codecs::png::PngEncoder,
+        codecs::webp::WebPDecoder,
+    };
+    use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+    use super::*;
+
+    const COLORS: [[u8; 4]; 4] = [
+        [255, 0, 0, 255],
+        [0, 255, 0, 128],
+        [0, 0, 255, 255],
+        [255, 255, 255, 255],
+    ];
+    /// The last delay doesn't fit into the 16 bits of an APNG delay as milliseconds.
+    const DELAYS: [u32; 4] = [100, 40, 250, 70_000];
+
+    /// An Ugoira of 4 frames of 5 by 3 pixels, each of a single color, the second one translucent.
+    fn ugoira() -> (Vec<UgoiraFrame>, Vec<u8>) {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let mut frames = Vec::new();
+
+        for (index, (color, delay)) in COLORS.iter().zip(DELAYS).enumerate() {
+            let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(5, 3, Rgba(*color)));
+            let mut png = Vec::new();
+            image.write_with_encoder(PngEncoder::new(&mut png)).unwrap();
+
+            let file = format!("{index:06}.png");
+            zip.start_file(
+                file.as_str(),
+                SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+            )
+            .unwrap();
+            zip.write_all(&png).unwrap();
+            frames.push(UgoiraFrame { file, delay });
+        }
+
+        (frames, zip.finish().unwrap().into_inner())
+    }
+
+    #[test]
+    fn webp_has_every_frame_and_delay() {
+        let (frames, data) = ugoira();
+        let webp = UgoiraFormat::Webp.convert(&frames, &data).unwrap();
+
+        let decoder = WebPDecoder::new(Cursor::new(&webp)).unwrap();
+        assert!(decoder.has_animation());
+        let decoded = decoder.into_frames().collect_frames().unwrap();
+
+        assert_eq!(decoded.len(), 4);
+        for ((frame, color), delay) in decoded.iter().zip(COLORS).zip(DELAYS) {
+            assert_eq!(frame.delay().numer_denom_ms(), (delay, 1));
+            assert_eq!(frame.buffer().dimensions(), (5, 3));
+            assert!(frame.buffer().pixels().all(|pixel| pixel.0 == color));
+        }
+    }
+
+    #[test]
+    fn apng_has_every_frame_and_delay() {
+        let (frames, data) = ugoira();
+        let apng = UgoiraFormat::Apng.convert(&frames, &data).unwrap();
+
+        let mut reader = png::Decoder::new(Cursor::new(&apng)).read_info().unwrap();
+        let animation = reader.info().animation_control().unwrap();
+        assert_eq!((animation.num_frames, animation.num_plays), (4, 0));
+
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        for (color, delay) in COLORS.iter().zip(DELAYS) {
+            let output = reader.next_frame(&mut buffer).unwrap();
+            assert_eq!((output.width, output.height), (5, 3));
+            assert_eq!(output.color_type, ColorType::Rgba);
+
+            let control = reader.info().frame_control().unwrap();
+            assert_eq!(
+                u32::from(control.delay_num) * 1000 / u32::from(control.delay_den),
+                delay
+            );
+            assert!(
+                buffer[..output.buffer_size()]
+                    .chunks(4)
+                    .all(|pixel| pixel == color)
+            );
+        }
+    }
+
+    #[test]
+    fn apng_delay_is_capped() {
+        assert_eq!(apng_delay(65_535), (65_535, 1000));
+        assert_eq!(apng_delay(65_536), (6553, 100));
+        assert_eq!(apng_delay(u32::MAX), (u16::MAX, 1));
+    }
+
+    #[test]
+    fn missing_frame_fails() {
+        let (mut frames, data) = ugoira();
+        frames[1].file = "missing.png".to_string();
+
+        assert!(UgoiraFormat::Webp.convert(&frames, &data).is_err());
+        assert!(UgoiraFormat::Apng.convert(&[], &data).is_err());
+    }
+}
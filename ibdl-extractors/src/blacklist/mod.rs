@@ -44,6 +44,7 @@ use std::collections::HashMap;
 use std::path::Path;
 use toml::from_str;
 
+use crate::events::{EventSink, ExtractorEvent, FilterRule};
 use crate::extractor_config::ServerConfig;
 
 use super::error::ExtractorError;
@@ -103,7 +104,7 @@ pub struct BlacklistFilter {
     selected_ratings: Vec<Rating>,
     disabled: bool,
     ignore_animated: bool,
-    extension: Option<Extension>,
+    extensions: Vec<Extension>,
 }
 
 impl BlacklistFilter {
@@ -113,7 +114,7 @@ impl BlacklistFilter {
         selected_ratings: &[Rating],
         disabled: bool,
         ignore_animated: bool,
-        extension: Option<Extension>,
+        extensions: &[Extension],
     ) -> Result<Self, ExtractorError> {
         let mut gbl_tags: AHashSet<String> = AHashSet::new();
         if !disabled {
@@ -148,27 +149,52 @@ impl BlacklistFilter {
             selected_ratings: sorted_list,
             disabled,
             ignore_animated,
-            extension,
+            extensions: extensions.to_vec(),
         })
     }
 
     #[inline]
     #[must_use]
     pub fn filter(&self, list: Vec<Post>) -> (u64, Vec<Post>) {
+        self.filter_with_events(list, &EventSink::default())
+    }
+
+    /// Same as [`filter`](Self::filter), but also emits a [`PostFiltered`](ExtractorEvent::PostFiltered)
+    /// event with the matching rule for every removed post.
+    #[must_use]
+    pub fn filter_with_events(&self, list: Vec<Post>, events: &EventSink) -> (u64, Vec<Post>) {
         let mut original_list = list;
 
         let original_size = original_list.len();
         let mut removed = 0;
 
         let start = Instant::now();
-        if let Some(ext) = self.extension {
-            debug!("Selecting only posts with extension {:?}", ext.to_string());
-            original_list.retain(|post| ext == post.extension);
+        if !self.extensions.is_empty() {
+            debug!("Selecting only posts with extensions {:?}", self.extensions);
+            original_list.retain(|post| {
+                let keep = self.extensions.contains(&post.extension);
+                if !keep {
+                    events.emit(ExtractorEvent::PostFiltered {
+                        post_id: post.id,
+                        rule: FilterRule::Extension(post.extension),
+                    });
+                }
+                keep
+            });
         }
 
         if !self.selected_ratings.is_empty() {
             debug!("Selected ratings: {:?}", self.selected_ratings);
-            original_list.retain(|c| self.selected_ratings.binary_search(&c.rating).is_ok());
+            original_list.retain(|c| {
+                let keep = self.selected_ratings.binary_search(&c.rating).is_ok();
+                if !keep {
+                    events.emit(ExtractorEvent::PostFiltered {
+                        post_id: c.id,
+                        rule: FilterRule::Rating(c.rating),
+                    });
+                }
+                keep
+            });
 
             let safe_counter = original_size - original_list.len();
             debug!("Removed {} posts with non-selected ratings", safe_counter);
@@ -183,14 +209,32 @@ impl BlacklistFilter {
                 0
             } else {
                 debug!("Removing posts with tags {:?}", self.gbl_tags);
-                original_list.retain(|c| !c.tags.iter().any(|s| self.gbl_tags.contains(&s.tag())));
+                original_list.retain(|c| {
+                    c.tags
+                        .iter()
+                        .find(|s| self.gbl_tags.contains(&s.tag()))
+                        .map_or(true, |tag| {
+                            events.emit(ExtractorEvent::PostFiltered {
+                                post_id: c.id,
+                                rule: FilterRule::BlacklistedTag(tag.tag()),
+                            });
+                            false
+                        })
+                });
                 fsize - original_list.len()
             };
 
             if self.ignore_animated {
                 original_list.retain(|post| {
-                    !(post.tags.contains(&Tag::new("animated", TagType::Meta))
-                        || post.extension.is_video())
+                    let keep = !(post.tags.contains(&Tag::new("animated", TagType::Meta))
+                        || post.extension.is_video());
+                    if !keep {
+                        events.emit(ExtractorEvent::PostFiltered {
+                            post_id: post.id,
+                            rule: FilterRule::Animated,
+                        });
+                    }
+                    keep
                 });
             }
 
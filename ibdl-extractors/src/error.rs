@@ -8,11 +8,12 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum ExtractorError {
+    /// The post is boxed, as it would make every result of an extractor much bigger.
     #[error("Failed to send post through channel")]
-    SyncChannelSendFail(#[from] std::sync::mpsc::SendError<Post>),
+    SyncChannelSendFail(#[source] Box<std::sync::mpsc::SendError<Post>>),
 
     #[error("Failed to send post through channel")]
-    ChannelSendFail(#[from] SendError<Post>),
+    ChannelSendFail(#[source] Box<SendError<Post>>),
 
     #[error("Too many tags, got: {current} while this imageboard only supports a max of {max}")]
     TooManyTags { current: usize, max: u64 },
@@ -59,9 +60,27 @@ pub enum ExtractorError {
     #[error("Unsupported operation for this server")]
     UnsupportedOperation,
 
+    #[error("Invalid extractor option `{option}`: {reason}")]
+    InvalidOption {
+        option: &'static str,
+        reason: String,
+    },
+
     #[error("Error sending length data to progress counter: {source}")]
     SendLengthFail {
         #[from]
         source: SendError<u64>,
     },
 }
+
+impl From<std::sync::mpsc::SendError<Post>> for ExtractorError {
+    fn from(error: std::sync::mpsc::SendError<Post>) -> Self {
+        Self::SyncChannelSendFail(Box::new(error))
+    }
+}
+
+impl From<SendError<Post>> for ExtractorError {
+    fn from(error: SendError<Post>) -> Self {
+        Self::ChannelSendFail(Box::new(error))
+    }
+}
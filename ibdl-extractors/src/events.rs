@@ -0,0 +1,86 @@
+//! Typed events emitted by the extractors while searching for posts.
+//!
+//! Extractors that implement [`EmitEvents`](crate::extractor::caps::EmitEvents) report every page fetched,
+//! every post found and every post removed by the [`BlacklistFilter`](crate::blacklist::BlacklistFilter)
+//! to an [`ExtractorListener`].
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Arc;
+
+use ibdl_common::post::{extension::Extension, rating::Rating};
+
+/// The rule that caused a post to be removed from the download queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterRule {
+    /// The post has an extension the user didn't allow.
+    Extension(Extension),
+    /// The post has a rating the user didn't select.
+    Rating(Rating),
+    /// The post has a blacklisted tag.
+    BlacklistedTag(String),
+    /// The post is animated or a video and those were disabled.
+    Animated,
+    /// The post doesn't have one of the searched tags that the imageboard couldn't filter by itself.
+    MissingTag,
+}
+
+impl Display for FilterRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Extension(ext) => write!(f, "extension {ext} not allowed"),
+            Self::Rating(rating) => write!(f, "rating {rating} not selected"),
+            Self::BlacklistedTag(tag) => write!(f, "blacklisted tag {tag}"),
+            Self::Animated => write!(f, "animated post"),
+            Self::MissingTag => write!(f, "missing searched tag"),
+        }
+    }
+}
+
+/// Events emitted by the extractors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExtractorEvent {
+    /// A page of posts was fetched from the imageboard API.
+    PageFetched { page: u16, posts: usize },
+    /// A post was found in a fetched page, before any filtering.
+    PostDiscovered { post_id: u64 },
+    /// A post was removed from the download queue.
+    PostFiltered { post_id: u64, rule: FilterRule },
+    /// The search was cancelled before fetching the next page.
+    Cancelled,
+}
+
+/// Receives the events emitted by an extractor. All methods should be thread-safe.
+pub trait ExtractorListener: Send + Sync + Debug {
+    fn on_extractor_event(&self, event: ExtractorEvent);
+}
+
+/// Convenience type alias for a shared, thread-safe extractor listener.
+pub type SharedExtractorListener = Arc<dyn ExtractorListener>;
+
+/// Optional listener held by the extractors. Emitting with no listener set does nothing.
+#[derive(Debug, Clone, Default)]
+pub struct EventSink {
+    listener: Option<SharedExtractorListener>,
+}
+
+impl EventSink {
+    #[must_use]
+    pub fn new(listener: SharedExtractorListener) -> Self {
+        Self {
+            listener: Some(listener),
+        }
+    }
+
+    #[inline]
+    pub fn emit(&self, event: ExtractorEvent) {
+        if let Some(listener) = &self.listener {
+            listener.on_extractor_event(event);
+        }
+    }
+
+    /// Returns `true` if a listener is set, to avoid building events no one will receive.
+    #[inline]
+    #[must_use]
+    pub const fn is_active(&self) -> bool {
+        self.listener.is_some()
+    }
+}
@@ -1,10 +1,13 @@
 use crate::auth::ImageboardConfig;
 use crate::error::ExtractorError;
+use crate::events::SharedExtractorListener;
 use ahash::HashMap;
 use bitflags::bitflags;
-use ibdl_common::post::Post;
-use ibdl_common::tokio::sync::mpsc::{Sender, UnboundedSender};
+use futures::Stream;
+use ibdl_common::post::{channel::PostSender, Post};
+use ibdl_common::tokio::sync::mpsc::Sender;
 use ibdl_common::tokio::task::JoinHandle;
+use ibdl_common::tokio_util::sync::CancellationToken;
 use std::future::Future;
 
 pub type ExtractorThreadHandle = JoinHandle<Result<u64, ExtractorError>>;
@@ -28,27 +31,55 @@ pub trait Auth {
     ) -> impl Future<Output = Result<(), ExtractorError>> + Send;
 }
 
-/// Capability for the extractor asynchronously send posts through a [`unbounded_channel`](ibdl_common::tokio::sync::mpsc::unbounded_channel) to another thread.
+/// Capability for the extractor asynchronously send posts through a [`post_channel`](ibdl_common::post::channel::post_channel) to another thread.
+///
+/// With a bounded channel, the extractor waits for free space before sending more posts, so it never gets too far ahead of the downloads.
 pub trait AsyncFetch {
     /// Similar to [`full_search`](Extractor::full_search) in functionality, but instead of returning a [`PostQueue`](PostQueue), sends posts asynchronously through a channel.
+    ///
+    /// Sends the same posts as [`post_stream`](StreamFetch::post_stream).
     fn async_fetch(
         &mut self,
-        sender_channel: UnboundedSender<Post>,
+        sender_channel: PostSender,
         start_page: Option<u16>,
         limit: Option<u16>,
         post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
     ) -> impl Future<Output = Result<u64, ExtractorError>> + Send;
 
     /// High-level convenience thread builder for [`async_fetch`](crate::websites::AsyncFetch::async_fetch)
+    ///
+    /// Once `cancel` is triggered, the extractor stops before fetching the next page.
     fn setup_fetch_thread(
         self,
-        sender_channel: UnboundedSender<Post>,
+        sender_channel: PostSender,
         start_page: Option<u16>,
         limit: Option<u16>,
         post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
     ) -> JoinHandle<Result<u64, ExtractorError>>;
 }
 
+/// Capability for the extractor to expose a search as a [`Stream`] of posts.
+pub trait StreamFetch {
+    /// Searches page by page from `start_page`, yielding every post that passes the blacklist and the selected filters,
+    /// until `limit` posts were yielded or there are no more pages.
+    ///
+    /// Pages are only fetched as the stream is polled, respecting the imageboard's rate limits, and dropping the stream stops the search.
+    /// If the search has no posts at all, the stream yields a single [`ExtractorError::ZeroPosts`].
+    fn post_stream(
+        &mut self,
+        start_page: Option<u16>,
+        limit: Option<u16>,
+    ) -> impl Stream<Item = Result<Post, ExtractorError>> + Send + '_;
+}
+
+/// Capability for the extractor to report what it's doing through an [`ExtractorListener`](crate::events::ExtractorListener).
+pub trait EmitEvents {
+    /// Sets the listener that will receive every event emitted from now on.
+    fn set_event_listener(&mut self, listener: SharedExtractorListener);
+}
+
 #[derive(Debug, Clone)]
 pub enum PostFetchMethod {
     Single(u32),
@@ -73,14 +104,28 @@ pub trait SinglePostFetch {
 }
 
 pub trait PostFetchAsync {
+    /// Spawns a task that fetches every post of `method` by its ID and sends it through `post_channel`.
+    ///
+    /// Once `cancel` is triggered, the task stops before fetching the next post.
     fn setup_async_post_fetch(
         self,
-        post_channel: UnboundedSender<Post>,
+        post_channel: PostSender,
         method: PostFetchMethod,
         length_channel: Sender<u64>,
+        cancel: CancellationToken,
     ) -> JoinHandle<Result<u64, ExtractorError>>;
 }
 
+/// Name and description of a pool, as shown on the imageboard.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolInfo {
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    /// Page of the pool on the imageboard.
+    pub url: String,
+}
+
 pub trait PoolExtract {
     fn fetch_pool_idxs(
         &mut self,
@@ -90,5 +135,11 @@ pub trait PoolExtract {
 
     fn parse_pool_ids(&self, raw_json: String) -> Result<Vec<u64>, ExtractorError>;
 
+    /// Fetches the name and description of a pool.
+    fn fetch_pool_info(
+        &mut self,
+        pool_id: u32,
+    ) -> impl Future<Output = Result<PoolInfo, ExtractorError>> + Send;
+
     fn setup_pool_download(&mut self, pool_id: Option<u32>, last_first: bool);
 }
@@ -1,6 +1,16 @@
-use ibdl_common::log::debug;
+use futures::{pin_mut, stream, Stream, StreamExt, TryStreamExt};
+use ibdl_common::{
+    log::debug,
+    post::{channel::PostSender, Post},
+    tokio::sync::mpsc::Sender,
+    tokio_util::sync::CancellationToken,
+};
 use std::fmt::Display;
 
+use crate::error::ExtractorError;
+use crate::events::{EventSink, ExtractorEvent};
+use crate::extractor::caps::{PostFetchMethod, SinglePostFetch};
+
 pub fn convert_tags_to_string<S>(tags: &[S]) -> (Vec<String>, String)
 where
     S: ToString + Display,
@@ -15,3 +25,81 @@ where
     debug!("Tag List: {}", tag_string);
     (strvec, tag_string)
 }
+
+/// Turns a stream of pages into a stream of single posts.
+pub fn flatten_pages<S>(pages: S) -> impl Stream<Item = Result<Post, ExtractorError>>
+where
+    S: Stream<Item = Result<Vec<Post>, ExtractorError>>,
+{
+    pages
+        .map_ok(|posts| stream::iter(posts.into_iter().map(Ok)))
+        .try_flatten()
+}
+
+/// Sends every post of a stream of pages through a channel.
+///
+/// Cancellation is only checked between pages, so a page that was already fetched is always sent completely.
+pub async fn send_pages<S>(
+    pages: S,
+    sender_channel: &PostSender,
+    post_counter: Option<&Sender<u64>>,
+    cancel: &CancellationToken,
+    events: &EventSink,
+) -> Result<(), ExtractorError>
+where
+    S: Stream<Item = Result<Vec<Post>, ExtractorError>>,
+{
+    pin_mut!(pages);
+
+    loop {
+        if cancel.is_cancelled() {
+            debug!("Search cancelled");
+            events.emit(ExtractorEvent::Cancelled);
+            break;
+        }
+
+        let Some(page) = pages.next().await else {
+            break;
+        };
+
+        for post in page? {
+            sender_channel.send(post).await?;
+            if let Some(counter) = post_counter {
+                counter.send(1).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches every post of `method` by its ID and sends it through a channel.
+///
+/// Cancellation is checked before fetching each post, so a post that was already fetched is always sent.
+pub async fn send_posts<E>(
+    unit: &mut E,
+    method: PostFetchMethod,
+    post_channel: &PostSender,
+    length_channel: &Sender<u64>,
+    cancel: &CancellationToken,
+) -> Result<(), ExtractorError>
+where
+    E: SinglePostFetch,
+{
+    let ids = match method {
+        PostFetchMethod::Single(id) => vec![id],
+        PostFetchMethod::Multiple(ids) => ids,
+    };
+
+    for id in ids {
+        if cancel.is_cancelled() {
+            debug!("Post fetch cancelled");
+            break;
+        }
+
+        post_channel.send(unit.get_post(id).await?).await?;
+        length_channel.send(1).await?;
+    }
+
+    Ok(())
+}
@@ -10,7 +10,7 @@ use ibdl_common::{
     reqwest::Client,
     ImageBoards,
 };
-use std::{fmt::Display, future::Future};
+use std::{fmt::Display, future::Future, time::Duration};
 
 use crate::error::ExtractorError;
 use crate::extractor::caps::ExtractorFeatures;
@@ -65,6 +65,17 @@ pub trait Extractor {
     /// Forces the extractor to only map posts that have the specified extension
     fn force_extension(&mut self, extension: Extension) -> &mut Self;
 
+    /// Same as [`force_extension`](Self::force_extension), but allows posts with any of the specified extensions.
+    ///
+    /// An empty list allows all extensions.
+    fn allow_extensions(&mut self, extensions: &[Extension]) -> &mut Self;
+
+    /// Sets the time to wait between API requests when going through multiple pages.
+    fn set_page_delay(&mut self, delay: Duration) -> &mut Self;
+
+    /// Returns the time waited between API requests. A new extractor starts with the lowest delay recommended by the imageboard.
+    fn page_delay(&self) -> Duration;
+
     /// Pretty similar to `search`, but instead returns the raw post list instead of a [`PostQueue`](ibdl_common::post::PostQueue)
     fn get_post_list(
         &self,
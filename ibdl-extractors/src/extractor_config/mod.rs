@@ -1,6 +1,5 @@
 use crate::extractor::caps::ExtractorFeatures;
-use crate::extractor::Extractor;
-use crate::imageboards::prelude::*;
+use crate::imageboards::any::AnyExtractor;
 use crate::server_config;
 use ibdl_common::serde;
 use ibdl_common::{
@@ -190,12 +189,7 @@ impl ServerConfig {
     #[inline]
     #[must_use]
     pub fn extractor_features(&self) -> ExtractorFeatures {
-        match self.server {
-            ImageBoards::Danbooru => DanbooruExtractor::features(),
-            ImageBoards::E621 => E621Extractor::features(),
-            ImageBoards::Moebooru => MoebooruExtractor::features(),
-            ImageBoards::Gelbooru | ImageBoards::GelbooruV0_2 => GelbooruExtractor::features(),
-        }
+        AnyExtractor::server_features(self.server)
     }
 }
 
@@ -0,0 +1,369 @@
+//! Extractor for any supported imageboard, selected at runtime from a [`ServerConfig`].
+//!
+//! Front-ends that let the user pick the imageboard can build an [`AnyExtractor`] in one call instead of
+//! matching on [`ImageBoards`] themselves. Not every imageboard supports every capability, so the capability
+//! traits return [`ExtractorError::UnsupportedOperation`] when the selected one can't do it. Use
+//! [`AnyExtractor::supports`] or [`AnyExtractor::require`] to check beforehand.
+//!
+//! New extractors only need to be registered in this module: a variant, an arm in [`AnyExtractor::new`],
+//! [`AnyExtractor::server_features`] and the `dispatch!` macro, plus the capabilities they implement.
+use std::{fmt::Display, time::Duration};
+
+use ahash::HashMap;
+use futures::{Stream, StreamExt};
+use ibdl_common::{
+    log::debug,
+    post::{channel::PostSender, extension::Extension, rating::Rating, Post, PostQueue},
+    reqwest::Client,
+    tokio::{spawn, sync::mpsc::Sender, task::JoinHandle},
+    tokio_util::sync::CancellationToken,
+    ImageBoards,
+};
+
+use super::prelude::*;
+use crate::auth::ImageboardConfig;
+use crate::error::ExtractorError;
+use crate::events::SharedExtractorListener;
+use crate::extractor::caps::{
+    AsyncFetch, Auth, EmitEvents, ExtractorFeatures, PoolExtract, PoolInfo, PostFetchAsync,
+    PostFetchMethod, SinglePostFetch, StreamFetch,
+};
+use crate::extractor::Extractor;
+use crate::extractor_config::ServerConfig;
+
+/// Runs the same expression with the extractor of every variant.
+macro_rules! dispatch {
+    ($self:expr, $unit:ident => $body:expr) => {
+        match $self {
+            AnyExtractor::Danbooru($unit) => $body,
+            AnyExtractor::E621($unit) => $body,
+            AnyExtractor::Gelbooru($unit) => $body,
+            AnyExtractor::Moebooru($unit) => $body,
+        }
+    };
+}
+
+/// Holds the extractor for the imageboard selected in a [`ServerConfig`].
+pub enum AnyExtractor {
+    Danbooru(DanbooruExtractor),
+    E621(E621Extractor),
+    Gelbooru(GelbooruExtractor),
+    Moebooru(MoebooruExtractor),
+}
+
+impl AnyExtractor {
+    /// Sets up the extractor for `config.server` with the tags supplied.
+    pub fn new<S>(
+        tags: &[S],
+        download_ratings: &[Rating],
+        disable_blacklist: bool,
+        map_videos: bool,
+        config: ServerConfig,
+    ) -> Self
+    where
+        S: ToString + Display,
+    {
+        match config.server {
+            ImageBoards::Danbooru => Self::Danbooru(DanbooruExtractor::new_with_config(
+                tags,
+                download_ratings,
+                disable_blacklist,
+                map_videos,
+                config,
+            )),
+            ImageBoards::E621 => Self::E621(E621Extractor::new_with_config(
+                tags,
+                download_ratings,
+                disable_blacklist,
+                map_videos,
+                config,
+            )),
+            ImageBoards::Gelbooru | ImageBoards::GelbooruV0_2 => {
+                Self::Gelbooru(GelbooruExtractor::new_with_config(
+                    tags,
+                    download_ratings,
+                    disable_blacklist,
+                    map_videos,
+                    config,
+                ))
+            }
+            ImageBoards::Moebooru => Self::Moebooru(MoebooruExtractor::new_with_config(
+                tags,
+                download_ratings,
+                disable_blacklist,
+                map_videos,
+                config,
+            )),
+        }
+    }
+
+    /// Returns the features supported by the extractor used for `server`.
+    #[must_use]
+    pub fn server_features(server: ImageBoards) -> ExtractorFeatures {
+        match server {
+            ImageBoards::Danbooru => DanbooruExtractor::features(),
+            ImageBoards::E621 => E621Extractor::features(),
+            ImageBoards::Gelbooru | ImageBoards::GelbooruV0_2 => GelbooruExtractor::features(),
+            ImageBoards::Moebooru => MoebooruExtractor::features(),
+        }
+    }
+
+    /// Returns the features supported by this extractor.
+    #[must_use]
+    pub fn features(&self) -> ExtractorFeatures {
+        Self::server_features(self.imageboard())
+    }
+
+    /// Checks if this extractor supports all of `features`.
+    #[must_use]
+    pub fn supports(&self, features: ExtractorFeatures) -> bool {
+        self.features().contains(features)
+    }
+
+    /// Same as [`supports`](Self::supports), but returns [`ExtractorError::UnsupportedOperation`] if any of `features` is missing.
+    pub fn require(&self, features: ExtractorFeatures) -> Result<(), ExtractorError> {
+        if self.supports(features) {
+            Ok(())
+        } else {
+            Err(ExtractorError::UnsupportedOperation)
+        }
+    }
+
+    /// Adds additional tags to the [blacklist filter](crate::blacklist::BlacklistFilter)
+    pub fn exclude_tags(&mut self, tags: &[String]) -> &mut Self {
+        dispatch!(self, unit => {
+            unit.exclude_tags(tags);
+        });
+        self
+    }
+
+    /// Forces the extractor to only map posts that have the specified extension
+    pub fn force_extension(&mut self, extension: Extension) -> &mut Self {
+        dispatch!(self, unit => {
+            unit.force_extension(extension);
+        });
+        self
+    }
+
+    /// Allows posts with any of the specified extensions. An empty list allows all extensions.
+    pub fn allow_extensions(&mut self, extensions: &[Extension]) -> &mut Self {
+        dispatch!(self, unit => {
+            unit.allow_extensions(extensions);
+        });
+        self
+    }
+
+    /// Sets the time to wait between API requests when going through multiple pages.
+    pub fn set_page_delay(&mut self, delay: Duration) -> &mut Self {
+        dispatch!(self, unit => {
+            unit.set_page_delay(delay);
+        });
+        self
+    }
+
+    /// Returns the time waited between API requests.
+    #[must_use]
+    pub fn page_delay(&self) -> Duration {
+        dispatch!(self, unit => unit.page_delay())
+    }
+
+    /// See [`Extractor::search`].
+    pub async fn search(&mut self, page: u16) -> Result<PostQueue, ExtractorError> {
+        dispatch!(self, unit => unit.search(page).await)
+    }
+
+    /// See [`Extractor::full_search`].
+    pub async fn full_search(
+        &mut self,
+        start_page: Option<u16>,
+        limit: Option<u16>,
+    ) -> Result<PostQueue, ExtractorError> {
+        dispatch!(self, unit => unit.full_search(start_page, limit).await)
+    }
+
+    /// See [`Extractor::get_post_list`].
+    pub async fn get_post_list(
+        &self,
+        page: u16,
+        limit: Option<u16>,
+    ) -> Result<Vec<Post>, ExtractorError> {
+        dispatch!(self, unit => unit.get_post_list(page, limit).await)
+    }
+
+    /// See [`Extractor::map_posts`].
+    pub fn map_posts(&self, raw_json: String) -> Result<Vec<Post>, ExtractorError> {
+        dispatch!(self, unit => unit.map_posts(raw_json))
+    }
+
+    /// Returns the used client for external use.
+    #[must_use]
+    pub fn client(&self) -> Client {
+        dispatch!(self, unit => unit.client())
+    }
+
+    /// Get the total number of removed files by the internal blacklist.
+    #[must_use]
+    pub fn total_removed(&self) -> u64 {
+        dispatch!(self, unit => unit.total_removed())
+    }
+
+    /// Returns the [`ImageBoards`] variant of the configured server.
+    #[must_use]
+    pub fn imageboard(&self) -> ImageBoards {
+        dispatch!(self, unit => unit.config().server)
+    }
+
+    /// Return the current configured [server](crate::extractor_config) for this extractor
+    #[must_use]
+    pub fn config(&self) -> ServerConfig {
+        dispatch!(self, unit => unit.config())
+    }
+}
+
+impl Auth for AnyExtractor {
+    async fn auth(&mut self, config: ImageboardConfig) -> Result<(), ExtractorError> {
+        match self {
+            Self::Danbooru(unit) => unit.auth(config).await,
+            Self::E621(unit) => unit.auth(config).await,
+            Self::Gelbooru(_) | Self::Moebooru(_) => Err(ExtractorError::UnsupportedOperation),
+        }
+    }
+}
+
+impl AsyncFetch for AnyExtractor {
+    async fn async_fetch(
+        &mut self,
+        sender_channel: PostSender,
+        start_page: Option<u16>,
+        limit: Option<u16>,
+        post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
+    ) -> Result<u64, ExtractorError> {
+        dispatch!(self, unit => {
+            unit.async_fetch(sender_channel, start_page, limit, post_counter, cancel)
+                .await
+        })
+    }
+
+    fn setup_fetch_thread(
+        self,
+        sender_channel: PostSender,
+        start_page: Option<u16>,
+        limit: Option<u16>,
+        post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
+    ) -> JoinHandle<Result<u64, ExtractorError>> {
+        dispatch!(self, unit => {
+            unit.setup_fetch_thread(sender_channel, start_page, limit, post_counter, cancel)
+        })
+    }
+}
+
+impl StreamFetch for AnyExtractor {
+    fn post_stream(
+        &mut self,
+        start_page: Option<u16>,
+        limit: Option<u16>,
+    ) -> impl Stream<Item = Result<Post, ExtractorError>> + Send + '_ {
+        dispatch!(self, unit => unit.post_stream(start_page, limit).boxed())
+    }
+}
+
+impl EmitEvents for AnyExtractor {
+    fn set_event_listener(&mut self, listener: SharedExtractorListener) {
+        dispatch!(self, unit => unit.set_event_listener(listener));
+    }
+}
+
+impl SinglePostFetch for AnyExtractor {
+    fn map_post(&self, raw_json: String) -> Result<Post, ExtractorError> {
+        match self {
+            Self::Danbooru(unit) => unit.map_post(raw_json),
+            Self::E621(unit) => unit.map_post(raw_json),
+            Self::Gelbooru(unit) => unit.map_post(raw_json),
+            Self::Moebooru(_) => Err(ExtractorError::UnsupportedOperation),
+        }
+    }
+
+    async fn get_post(&mut self, post_id: u32) -> Result<Post, ExtractorError> {
+        match self {
+            Self::Danbooru(unit) => unit.get_post(post_id).await,
+            Self::E621(unit) => unit.get_post(post_id).await,
+            Self::Gelbooru(unit) => unit.get_post(post_id).await,
+            Self::Moebooru(_) => Err(ExtractorError::UnsupportedOperation),
+        }
+    }
+
+    async fn get_posts(&mut self, posts: &[u32]) -> Result<Vec<Post>, ExtractorError> {
+        match self {
+            Self::Danbooru(unit) => unit.get_posts(posts).await,
+            Self::E621(unit) => unit.get_posts(posts).await,
+            Self::Gelbooru(unit) => unit.get_posts(posts).await,
+            Self::Moebooru(_) => Err(ExtractorError::UnsupportedOperation),
+        }
+    }
+}
+
+impl PostFetchAsync for AnyExtractor {
+    fn setup_async_post_fetch(
+        self,
+        post_channel: PostSender,
+        method: PostFetchMethod,
+        length_channel: Sender<u64>,
+        cancel: CancellationToken,
+    ) -> JoinHandle<Result<u64, ExtractorError>> {
+        match self {
+            Self::Danbooru(unit) => {
+                unit.setup_async_post_fetch(post_channel, method, length_channel, cancel)
+            }
+            Self::E621(unit) => {
+                unit.setup_async_post_fetch(post_channel, method, length_channel, cancel)
+            }
+            Self::Gelbooru(unit) => {
+                unit.setup_async_post_fetch(post_channel, method, length_channel, cancel)
+            }
+            Self::Moebooru(_) => spawn(async { Err(ExtractorError::UnsupportedOperation) }),
+        }
+    }
+}
+
+impl PoolExtract for AnyExtractor {
+    async fn fetch_pool_idxs(
+        &mut self,
+        pool_id: u32,
+        limit: Option<u16>,
+    ) -> Result<HashMap<u64, usize>, ExtractorError> {
+        match self {
+            Self::Danbooru(unit) => unit.fetch_pool_idxs(pool_id, limit).await,
+            Self::E621(unit) => unit.fetch_pool_idxs(pool_id, limit).await,
+            Self::Gelbooru(_) | Self::Moebooru(_) => Err(ExtractorError::UnsupportedOperation),
+        }
+    }
+
+    fn parse_pool_ids(&self, raw_json: String) -> Result<Vec<u64>, ExtractorError> {
+        match self {
+            Self::Danbooru(unit) => unit.parse_pool_ids(raw_json),
+            Self::E621(unit) => unit.parse_pool_ids(raw_json),
+            Self::Gelbooru(_) | Self::Moebooru(_) => Err(ExtractorError::UnsupportedOperation),
+        }
+    }
+
+    async fn fetch_pool_info(&mut self, pool_id: u32) -> Result<PoolInfo, ExtractorError> {
+        match self {
+            Self::Danbooru(unit) => unit.fetch_pool_info(pool_id).await,
+            Self::E621(unit) => unit.fetch_pool_info(pool_id).await,
+            Self::Gelbooru(_) | Self::Moebooru(_) => Err(ExtractorError::UnsupportedOperation),
+        }
+    }
+
+    /// Does nothing if the extractor can't download pools, so check for [`ExtractorFeatures::PoolDownload`] first.
+    fn setup_pool_download(&mut self, pool_id: Option<u32>, last_first: bool) {
+        match self {
+            Self::Danbooru(unit) => unit.setup_pool_download(pool_id, last_first),
+            Self::E621(unit) => unit.setup_pool_download(pool_id, last_first),
+            Self::Gelbooru(_) | Self::Moebooru(_) => {
+                debug!("Pool downloads are not supported by this extractor");
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Builder for extractors with named options.
+//!
+//! [`ExtractorBuilder`] collects every option of a search, checks that they can be used together
+//! and applies them to an [`AnyExtractor`] in the right order.
+//!
+//! ```no_run
+//! use ibdl_extractors::extractor_config::DEFAULT_SERVERS;
+//! use ibdl_extractors::prelude::*;
+//! # use ibdl_extractors::error::ExtractorError;
+//! # async fn run() -> Result<(), ExtractorError> {
+//! let config = DEFAULT_SERVERS.get("danbooru").unwrap().clone();
+//!
+//! let mut search = ExtractorBuilder::new(config)
+//!     .tags(&["umbreon", "espeon"])
+//!     .exclude_tags(&["comic".to_string()])
+//!     .videos(VideoPolicy::Exclude)
+//!     .limit(Some(50))
+//!     .build()
+//!     .await?;
+//!
+//! let posts = search.extractor.full_search(search.start_page, search.limit).await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt::Display;
+use std::time::Duration;
+
+use futures::Stream;
+use ibdl_common::{
+    all_ratings,
+    post::{channel::PostSender, extension::Extension, rating::Rating, Post},
+    tokio::sync::mpsc::Sender,
+    tokio_util::sync::CancellationToken,
+};
+
+use super::any::AnyExtractor;
+use crate::auth::ImageboardConfig;
+use crate::error::ExtractorError;
+use crate::events::SharedExtractorListener;
+use crate::extractor::caps::{
+    AsyncFetch, Auth, EmitEvents, ExtractorFeatures, ExtractorThreadHandle, PoolExtract,
+    StreamFetch,
+};
+use crate::extractor_config::ServerConfig;
+
+/// What to do with animated posts and videos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VideoPolicy {
+    /// Download videos and animated posts along with images.
+    #[default]
+    Include,
+    /// Remove videos and animated posts. Needs the blacklist enabled.
+    Exclude,
+}
+
+/// Sets up an extractor from named options.
+///
+/// The blacklist is made from three sources: the blacklist file (global and server sections), the tags from
+/// [`exclude_tags`](Self::exclude_tags) and the account blacklist loaded by [`auth`](Self::auth).
+/// [`blacklist(false)`](Self::blacklist) disables all of them.
+#[derive(Debug, Clone)]
+pub struct ExtractorBuilder {
+    config: ServerConfig,
+    tags: Vec<String>,
+    ratings: Vec<Rating>,
+    blacklist: bool,
+    excluded_tags: Vec<String>,
+    extensions: Vec<Extension>,
+    videos: VideoPolicy,
+    auth: Option<ImageboardConfig>,
+    page_delay: Option<Duration>,
+    pool: Option<(u32, bool)>,
+    start_page: Option<u16>,
+    limit: Option<u16>,
+    listener: Option<SharedExtractorListener>,
+}
+
+impl ExtractorBuilder {
+    /// Starts a builder for the extractor of `config.server`.
+    ///
+    /// By default, all ratings and extensions are downloaded, videos included, and the blacklist is enabled.
+    #[must_use]
+    pub const fn new(config: ServerConfig) -> Self {
+        Self {
+            config,
+            tags: Vec::new(),
+            ratings: Vec::new(),
+            blacklist: true,
+            excluded_tags: Vec::new(),
+            extensions: Vec::new(),
+            videos: VideoPolicy::Include,
+            auth: None,
+            page_delay: None,
+            pool: None,
+            start_page: None,
+            limit: None,
+            listener: None,
+        }
+    }
+
+    /// Tags to search.
+    #[must_use]
+    pub fn tags<S>(mut self, tags: &[S]) -> Self
+    where
+        S: ToString + Display,
+    {
+        self.tags = tags.iter().map(ToString::to_string).collect();
+        self
+    }
+
+    /// Only download posts with these ratings. An empty list downloads all ratings.
+    #[must_use]
+    pub fn ratings(mut self, ratings: &[Rating]) -> Self {
+        self.ratings = ratings.to_vec();
+        self
+    }
+
+    /// Enables or disables the blacklist. Enabled by default.
+    ///
+    /// Excluded tags and [`VideoPolicy::Exclude`] are applied by the blacklist, so [`build`](Self::build) fails
+    /// if they are set while it's disabled.
+    #[must_use]
+    pub const fn blacklist(mut self, enabled: bool) -> Self {
+        self.blacklist = enabled;
+        self
+    }
+
+    /// Removes posts with any of these tags, in addition to the blacklist file. Needs the blacklist enabled.
+    #[must_use]
+    pub fn exclude_tags(mut self, tags: &[String]) -> Self {
+        self.excluded_tags = tags.to_vec();
+        self
+    }
+
+    /// Only download posts with one of these extensions. An empty list downloads all extensions.
+    #[must_use]
+    pub fn extensions<I>(mut self, extensions: I) -> Self
+    where
+        I: IntoIterator<Item = Extension>,
+    {
+        self.extensions = extensions.into_iter().collect();
+        self
+    }
+
+    /// Whether to download videos and animated posts. [`VideoPolicy::Exclude`] needs the blacklist enabled.
+    #[must_use]
+    pub const fn videos(mut self, policy: VideoPolicy) -> Self {
+        self.videos = policy;
+        self
+    }
+
+    /// Authenticates with these credentials, which also adds the account blacklist.
+    #[must_use]
+    pub fn auth(mut self, config: ImageboardConfig) -> Self {
+        self.auth = Some(config);
+        self
+    }
+
+    /// Time to wait between API requests. Can't be lower than the default delay of the imageboard.
+    #[must_use]
+    pub const fn page_delay(mut self, delay: Duration) -> Self {
+        self.page_delay = Some(delay);
+        self
+    }
+
+    /// Downloads a pool instead of searching tags, optionally starting from the last posts.
+    #[must_use]
+    pub const fn pool(mut self, pool_id: u32, latest_first: bool) -> Self {
+        self.pool = Some((pool_id, latest_first));
+        self
+    }
+
+    /// Page to start the search from. Resumed jobs should pass their saved cursor here.
+    #[must_use]
+    pub const fn start_page(mut self, page: Option<u16>) -> Self {
+        self.start_page = page;
+        self
+    }
+
+    /// Max number of posts to find.
+    #[must_use]
+    pub const fn limit(mut self, limit: Option<u16>) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Listener that receives the events of the extractor.
+    #[must_use]
+    pub fn listener(mut self, listener: SharedExtractorListener) -> Self {
+        self.listener = Some(listener);
+        self
+    }
+
+    fn validate(&self, unit: &AnyExtractor) -> Result<(), ExtractorError> {
+        let invalid = |option, reason: &str| {
+            Err(ExtractorError::InvalidOption {
+                option,
+                reason: reason.to_string(),
+            })
+        };
+
+        if self.limit == Some(0) {
+            return invalid("limit", "must be at least 1");
+        }
+
+        if !self.blacklist && !self.excluded_tags.is_empty() {
+            return invalid(
+                "exclude_tags",
+                "excluded tags are ignored while the blacklist is disabled",
+            );
+        }
+
+        if !self.blacklist && self.videos == VideoPolicy::Exclude {
+            return invalid(
+                "videos",
+                "videos can only be excluded while the blacklist is enabled",
+            );
+        }
+
+        if self.auth.is_some() && !unit.supports(ExtractorFeatures::Auth) {
+            return invalid("auth", "this imageboard doesn't support authentication");
+        }
+
+        if self.pool.is_some() {
+            if !unit.supports(ExtractorFeatures::PoolDownload) {
+                return invalid("pool", "this imageboard doesn't support pool downloads");
+            }
+
+            if !self.tags.is_empty() {
+                return invalid("pool", "pool downloads can't be combined with tags");
+            }
+        }
+
+        if let Some(delay) = self.page_delay {
+            if delay < unit.page_delay() {
+                return invalid(
+                    "page_delay",
+                    &format!(
+                        "must be at least {:?} for {}",
+                        unit.page_delay(),
+                        self.config.pretty_name
+                    ),
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the options and sets up the extractor, authenticating if credentials were set.
+    pub async fn build(self) -> Result<ConfiguredExtractor, ExtractorError> {
+        let ratings: &[Rating] = if self.ratings.is_empty() {
+            all_ratings!()
+        } else {
+            &self.ratings
+        };
+
+        let mut unit = AnyExtractor::new(
+            &self.tags,
+            ratings,
+            !self.blacklist,
+            self.videos == VideoPolicy::Include,
+            self.config.clone(),
+        );
+
+        self.validate(&unit)?;
+
+        unit.exclude_tags(&self.excluded_tags);
+        unit.allow_extensions(&self.extensions);
+
+        if let Some(delay) = self.page_delay {
+            unit.set_page_delay(delay);
+        }
+
+        if let Some((pool_id, latest_first)) = self.pool {
+            unit.setup_pool_download(Some(pool_id), latest_first);
+        }
+
+        if let Some(listener) = self.listener {
+            unit.set_event_listener(listener);
+        }
+
+        // Authenticating adds the account blacklist to the excluded tags, so it must come last.
+        if let Some(auth) = self.auth {
+            unit.auth(auth).await?;
+        }
+
+        Ok(ConfiguredExtractor {
+            extractor: unit,
+            start_page: self.start_page,
+            limit: self.limit,
+        })
+    }
+}
+
+/// An extractor set up by [`ExtractorBuilder`], along with where the search starts and how many posts it should find.
+pub struct ConfiguredExtractor {
+    pub extractor: AnyExtractor,
+    pub start_page: Option<u16>,
+    pub limit: Option<u16>,
+}
+
+impl ConfiguredExtractor {
+    /// See [`StreamFetch::post_stream`].
+    pub fn post_stream(&mut self) -> impl Stream<Item = Result<Post, ExtractorError>> + Send + '_ {
+        self.extractor.post_stream(self.start_page, self.limit)
+    }
+
+    /// See [`AsyncFetch::setup_fetch_thread`].
+    #[must_use]
+    pub fn setup_fetch_thread(
+        self,
+        sender_channel: PostSender,
+        post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
+    ) -> ExtractorThreadHandle {
+        self.extractor.setup_fetch_thread(
+            sender_channel,
+            self.start_page,
+            self.limit,
+            post_counter,
+            cancel,
+        )
+    }
+}
@@ -9,6 +9,7 @@ use self::models::DanbooruPost;
 use crate::auth::{AuthState, ImageboardConfig};
 use crate::extractor::caps::{Auth, ExtractorFeatures, SinglePostFetch};
 use crate::extractor::Extractor;
+use crate::events::EventSink;
 use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 use ibdl_common::post::extension::Extension;
@@ -29,6 +30,10 @@ mod models;
 mod pool;
 mod unsync;
 
+/// Fields requested for every post. The frames of Ugoira posts are only sent when asked for.
+const POST_FIELDS: &str = "id,md5,file_url,tag_string,tag_string_general,tag_string_character,\
+tag_string_copyright,tag_string_artist,tag_string_meta,file_ext,rating,score,pixiv_ugoira_frame_data";
+
 /// Main object to download posts
 #[derive(Debug, Clone)]
 pub struct DanbooruExtractor {
@@ -42,11 +47,13 @@ pub struct DanbooruExtractor {
     total_removed: u64,
     map_videos: bool,
     excluded_tags: Vec<String>,
-    selected_extension: Option<Extension>,
+    selected_extensions: Vec<Extension>,
+    page_delay: Duration,
     extra_tags: Vec<String>,
     pool_id: Option<u32>,
     pool_last_items_first: bool,
     server_cfg: ServerConfig,
+    events: EventSink,
 }
 
 impl Extractor for DanbooruExtractor {
@@ -97,11 +104,13 @@ impl Extractor for DanbooruExtractor {
             total_removed: 0,
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::ZERO,
             extra_tags,
             pool_id: None,
             pool_last_items_first: false,
             server_cfg: config,
+            events: EventSink::default(),
         }
     }
 
@@ -151,11 +160,13 @@ impl Extractor for DanbooruExtractor {
             total_removed: 0,
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::ZERO,
             extra_tags,
             pool_id: None,
             pool_last_items_first: false,
             server_cfg: config,
+            events: EventSink::default(),
         }
     }
 
@@ -190,7 +201,7 @@ impl Extractor for DanbooruExtractor {
             &self.download_ratings,
             self.disable_blacklist,
             !self.map_videos,
-            self.selected_extension,
+            &self.selected_extensions,
         )
         .await?;
 
@@ -234,6 +245,11 @@ impl Extractor for DanbooruExtractor {
             }
 
             page += 1;
+
+            if !self.page_delay.is_zero() {
+                debug!("Debouncing API calls by {:?}", self.page_delay);
+                sleep(self.page_delay).await;
+            }
         }
 
         if fvec.is_empty() {
@@ -258,10 +274,24 @@ impl Extractor for DanbooruExtractor {
     }
 
     fn force_extension(&mut self, extension: Extension) -> &mut Self {
-        self.selected_extension = Some(extension);
+        self.selected_extensions = vec![extension];
         self
     }
 
+    fn allow_extensions(&mut self, extensions: &[Extension]) -> &mut Self {
+        self.selected_extensions = extensions.to_vec();
+        self
+    }
+
+    fn set_page_delay(&mut self, delay: Duration) -> &mut Self {
+        self.page_delay = delay;
+        self
+    }
+
+    fn page_delay(&self) -> Duration {
+        self.page_delay
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -293,11 +323,13 @@ impl Extractor for DanbooruExtractor {
             })
         };
 
-        let req = request.query(&[
-            ("page", &page.to_string()),
-            ("limit", &page_post_count.to_string()),
-            ("tags", &self.tag_string),
-        ]);
+        let req = request
+            .query(&[
+                ("page", &page.to_string()),
+                ("limit", &page_post_count.to_string()),
+                ("tags", &self.tag_string),
+            ])
+            .query(&[("only", POST_FIELDS)]);
 
         let post_array = req.send().await?.text().await?;
 
@@ -336,6 +368,11 @@ impl Extractor for DanbooruExtractor {
                 extension: Extension::guess_format(&c.file_ext.unwrap()),
                 tags: tag_list,
                 rating,
+                score: c.score,
+                ugoira_frames: c
+                    .pixiv_ugoira_frame_data
+                    .map(|frames| frames.data)
+                    .unwrap_or_default(),
             }
         });
 
@@ -397,6 +434,11 @@ impl SinglePostFetch for DanbooruExtractor {
             extension: Extension::guess_format(&parsed_json.file_ext.unwrap()),
             tags: tag_list,
             rating,
+            score: parsed_json.score,
+            ugoira_frames: parsed_json
+                .pixiv_ugoira_frame_data
+                .map(|frames| frames.data)
+                .unwrap_or_default(),
         };
 
         Ok(post)
@@ -422,7 +464,8 @@ impl SinglePostFetch for DanbooruExtractor {
         } else {
             debug!("Fetching post {}", post_id);
             self.client.get(url)
-        };
+        }
+        .query(&[("only", POST_FIELDS)]);
 
         let post_array = req.send().await?.text().await?;
 
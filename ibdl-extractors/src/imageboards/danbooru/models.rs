@@ -1,5 +1,8 @@
 use ibdl_common::{
-    post::tags::{Tag, TagType},
+    post::{
+        tags::{Tag, TagType},
+        UgoiraFrame,
+    },
     serde::{self, Deserialize, Serialize},
 };
 
@@ -7,6 +10,10 @@ use ibdl_common::{
 #[serde(crate = "self::serde")]
 pub struct DanbooruPoolList {
     pub post_ids: Vec<u64>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -23,6 +30,14 @@ pub struct DanbooruPost {
     pub tag_string_meta: Option<String>,
     pub file_ext: Option<String>,
     pub rating: Option<String>,
+    pub score: Option<i64>,
+    pub pixiv_ugoira_frame_data: Option<DanbooruUgoiraFrameData>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "self::serde")]
+pub struct DanbooruUgoiraFrameData {
+    pub data: Vec<UgoiraFrame>,
 }
 
 impl DanbooruPost {
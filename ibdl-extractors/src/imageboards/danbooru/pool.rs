@@ -6,7 +6,7 @@ use ibdl_common::{
 
 use super::{models::DanbooruPoolList, DanbooruExtractor};
 use crate::error::ExtractorError;
-use crate::extractor::caps::PoolExtract;
+use crate::extractor::caps::{PoolExtract, PoolInfo};
 
 impl PoolExtract for DanbooruExtractor {
     async fn fetch_pool_idxs(
@@ -14,28 +14,7 @@ impl PoolExtract for DanbooruExtractor {
         pool_id: u32,
         limit: Option<u16>,
     ) -> Result<HashMap<u64, usize>, ExtractorError> {
-        if self.server_cfg.pool_idx_url.is_none() {
-            return Err(ExtractorError::UnsupportedOperation);
-        }
-
-        let url = format!(
-            "{}/{}.json",
-            self.server_cfg.pool_idx_url.as_ref().unwrap(),
-            pool_id
-        );
-
-        // Fetch item list from page
-        let req = if self.auth_state.is_auth() {
-            debug!("[AUTH] Fetching post ids from pool {}", pool_id);
-            self.client
-                .get(url)
-                .basic_auth(&self.auth.username, Some(&self.auth.api_key))
-        } else {
-            debug!("Fetching post ids from pool {}", pool_id);
-            self.client.get(url)
-        };
-
-        let post_array = req.send().await?.text().await?;
+        let post_array = self.fetch_pool(pool_id).await?;
 
         let mut mtx = self.parse_pool_ids(post_array)?;
 
@@ -65,8 +44,52 @@ impl PoolExtract for DanbooruExtractor {
         Ok(parsed_json.post_ids)
     }
 
+    async fn fetch_pool_info(&mut self, pool_id: u32) -> Result<PoolInfo, ExtractorError> {
+        let raw_json = self.fetch_pool(pool_id).await?;
+        let pool = serde_json::from_str::<DanbooruPoolList>(raw_json.as_str())?;
+
+        Ok(PoolInfo {
+            id: pool_id,
+            name: pool.name.replace('_', " "),
+            description: pool.description,
+            url: self.pool_url(pool_id),
+        })
+    }
+
     fn setup_pool_download(&mut self, pool_id: Option<u32>, last_first: bool) {
         self.pool_id = pool_id;
         self.pool_last_items_first = last_first;
     }
 }
+
+impl DanbooruExtractor {
+    fn pool_url(&self, pool_id: u32) -> String {
+        format!(
+            "{}/{}",
+            self.server_cfg.pool_idx_url.as_deref().unwrap_or_default(),
+            pool_id
+        )
+    }
+
+    /// Fetches the raw JSON of a pool, with its name, description and post ids.
+    async fn fetch_pool(&self, pool_id: u32) -> Result<String, ExtractorError> {
+        if self.server_cfg.pool_idx_url.is_none() {
+            return Err(ExtractorError::UnsupportedOperation);
+        }
+
+        let url = format!("{}.json", self.pool_url(pool_id));
+
+        // Fetch item list from page
+        let req = if self.auth_state.is_auth() {
+            debug!("[AUTH] Fetching pool {}", pool_id);
+            self.client
+                .get(url)
+                .basic_auth(&self.auth.username, Some(&self.auth.api_key))
+        } else {
+            debug!("Fetching pool {}", pool_id);
+            self.client.get(url)
+        };
+
+        Ok(req.send().await?.text().await?)
+    }
+}
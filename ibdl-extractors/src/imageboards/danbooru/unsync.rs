@@ -1,126 +1,174 @@
 use ahash::{HashMap, HashMapExt};
+use async_stream::try_stream;
+use futures::Stream;
 use ibdl_common::{
     log::debug,
-    post::Post,
-    tokio::{
-        spawn,
-        sync::mpsc::{Sender, UnboundedSender},
-        task::JoinHandle,
-    },
+    post::{channel::PostSender, Post},
+    tokio::{spawn, sync::mpsc::Sender, task::JoinHandle, time::sleep},
+    tokio_util::sync::CancellationToken,
 };
 
 use super::DanbooruExtractor;
-use crate::extractor::caps::{
-    AsyncFetch, PoolExtract, PostFetchAsync, PostFetchMethod, SinglePostFetch,
-};
+use crate::events::{EventSink, ExtractorEvent, FilterRule, SharedExtractorListener};
+use crate::extractor::caps::{AsyncFetch, PoolExtract, PostFetchAsync, PostFetchMethod};
+use crate::extractor::caps::{EmitEvents, StreamFetch};
+use crate::extractor::common::{flatten_pages, send_pages, send_posts};
 use crate::extractor::Extractor;
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 
 // A quick alias so I can copy-paste stuff faster
 type ExtractorUnit = DanbooruExtractor;
 
-impl AsyncFetch for ExtractorUnit {
-    async fn async_fetch(
+impl ExtractorUnit {
+    /// Removes the posts without the searched tags that couldn't be sent to the API.
+    fn retain_extra_tags(&self, posts: &mut Vec<Post>) {
+        posts.retain(|post| {
+            let keep = post
+                .tags
+                .iter()
+                .all(|tag| self.extra_tags.contains(&tag.tag()));
+            if !keep {
+                self.events.emit(ExtractorEvent::PostFiltered {
+                    post_id: post.id,
+                    rule: FilterRule::MissingTag,
+                });
+            }
+            keep
+        });
+    }
+
+    /// Fetches the search one page at a time. Every page is already filtered and cut to fit `limit`.
+    fn page_stream(
         &mut self,
-        sender_channel: UnboundedSender<Post>,
         start_page: Option<u16>,
         limit: Option<u16>,
-        post_counter: Option<Sender<u64>>,
-    ) -> Result<u64, ExtractorError> {
-        debug!("Async extractor thread initialized");
-
-        let blacklist = BlacklistFilter::new(
-            self.server_cfg.clone(),
-            &self.excluded_tags,
-            &self.download_ratings,
-            self.disable_blacklist,
-            !self.map_videos,
-            self.selected_extension,
-        )
-        .await?;
-
-        let mut pool_idxs = HashMap::with_capacity(512);
+    ) -> impl Stream<Item = Result<Vec<Post>, ExtractorError>> + Send + '_ {
+        try_stream! {
+            debug!("Async extractor thread initialized");
+
+            let blacklist = BlacklistFilter::new(
+                self.server_cfg.clone(),
+                &self.excluded_tags,
+                &self.download_ratings,
+                self.disable_blacklist,
+                !self.map_videos,
+                &self.selected_extensions,
+            )
+            .await?;
+
+            let mut pool_idxs = HashMap::with_capacity(512);
+
+            if let Some(p_id) = self.pool_id {
+                self.tag_string = format!("pool:{p_id}");
+                pool_idxs = self.fetch_pool_idxs(p_id, limit).await?;
+            }
 
-        if let Some(p_id) = self.pool_id {
-            self.tag_string = format!("pool:{p_id}");
-            pool_idxs = self.fetch_pool_idxs(p_id, limit).await?;
-        }
+            let mut has_posts: bool = false;
+            let mut remaining = limit.map(usize::from);
 
-        let mut has_posts: bool = false;
-        let mut total_posts_sent: u16 = 0;
+            let mut page = 1;
 
-        let mut page = 1;
+            loop {
+                let position = start_page.map_or(page, |n| page + n);
 
-        loop {
-            let position = start_page.map_or(page, |n| page + n);
+                let mut posts = self.get_post_list(position, limit).await?;
+                let size = posts.len();
 
-            let mut posts = self.get_post_list(position, limit).await?;
-            let size = posts.len();
+                if size == 0 {
+                    if !has_posts {
+                        Err(ExtractorError::ZeroPosts)?;
+                    }
 
-            if size == 0 {
-                if !has_posts {
-                    return Err(ExtractorError::ZeroPosts);
+                    break;
                 }
 
-                break;
-            }
-
-            if !self.extra_tags.is_empty() {
-                posts.retain(|post| {
-                    post.tags
-                        .iter()
-                        .all(|tag| self.extra_tags.contains(&tag.tag()))
+                self.events.emit(ExtractorEvent::PageFetched {
+                    page: position,
+                    posts: size,
                 });
-            }
 
-            let mut list = if self.disable_blacklist || self.download_ratings.is_empty() {
-                posts
-            } else {
-                let (removed, posts) = blacklist.filter(posts);
-                self.total_removed += removed;
-                posts
-            };
+                if self.events.is_active() {
+                    for post in &posts {
+                        self.events
+                            .emit(ExtractorEvent::PostDiscovered { post_id: post.id });
+                    }
+                }
 
-            if !has_posts && !list.is_empty() {
-                has_posts = true;
-            }
+                if !self.extra_tags.is_empty() {
+                    self.retain_extra_tags(&mut posts);
+                }
 
-            for i in &mut list {
-                if let Some(num) = limit {
-                    if total_posts_sent >= num {
-                        break;
-                    }
+                let mut list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
+                    let (removed, posts) = blacklist.filter_with_events(posts, &self.events);
+                    self.total_removed += removed;
+                    posts
+                } else {
+                    posts
+                };
+
+                if !has_posts && !list.is_empty() {
+                    has_posts = true;
                 }
 
                 if self.pool_id.is_some() {
-                    if let Some(page_num) = pool_idxs.get(&i.id) {
-                        i.id = *page_num as u64;
-                    } else {
-                        continue;
-                    }
+                    // Pool posts are numbered by their position in the pool
+                    list = list
+                        .into_iter()
+                        .filter_map(|mut post| {
+                            let page_num = pool_idxs.get(&post.id)?;
+                            post.id = *page_num as u64;
+                            Some(post)
+                        })
+                        .collect();
                 }
 
-                sender_channel.send(i.clone())?;
-                total_posts_sent += 1;
-                if let Some(counter) = &post_counter {
-                    counter.send(1).await?;
+                if let Some(remaining) = &mut remaining {
+                    list.truncate(*remaining);
+                    *remaining -= list.len();
                 }
-            }
 
-            if let Some(num) = limit {
-                if total_posts_sent >= num {
-                    debug!("Target post count of {} reached.", num);
+                yield list;
+
+                if remaining == Some(0) {
+                    debug!("Target post count of {} reached.", limit.unwrap_or_default());
                     break;
                 }
-            }
 
-            if page == 100 {
-                debug!("Max number of pages reached");
-                break;
-            }
+                if page == 100 {
+                    debug!("Max number of pages reached");
+                    break;
+                }
 
-            page += 1;
+                page += 1;
+                if !self.page_delay.is_zero() {
+                    debug!("Debouncing API calls by {:?}", self.page_delay);
+                    sleep(self.page_delay).await;
+                }
+            }
         }
+    }
+}
+
+impl AsyncFetch for ExtractorUnit {
+    async fn async_fetch(
+        &mut self,
+        sender_channel: PostSender,
+        start_page: Option<u16>,
+        limit: Option<u16>,
+        post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
+    ) -> Result<u64, ExtractorError> {
+        let events = self.events.clone();
+        let pages = self.page_stream(start_page, limit);
+
+        send_pages(
+            pages,
+            &sender_channel,
+            post_counter.as_ref(),
+            &cancel,
+            &events,
+        )
+        .await?;
 
         debug!("Terminating thread.");
         Ok(self.total_removed)
@@ -129,14 +177,15 @@ impl AsyncFetch for ExtractorUnit {
     #[inline]
     fn setup_fetch_thread(
         self,
-        sender_channel: UnboundedSender<Post>,
+        sender_channel: PostSender,
         start_page: Option<u16>,
         limit: Option<u16>,
         post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
     ) -> JoinHandle<Result<u64, ExtractorError>> {
         spawn(async move {
             let mut ext = self;
-            ext.async_fetch(sender_channel, start_page, limit, post_counter)
+            ext.async_fetch(sender_channel, start_page, limit, post_counter, cancel)
                 .await
         })
     }
@@ -145,25 +194,31 @@ impl AsyncFetch for ExtractorUnit {
 impl PostFetchAsync for ExtractorUnit {
     fn setup_async_post_fetch(
         self,
-        post_channel: UnboundedSender<Post>,
+        post_channel: PostSender,
         method: PostFetchMethod,
         length_channel: Sender<u64>,
+        cancel: CancellationToken,
     ) -> JoinHandle<Result<u64, ExtractorError>> {
         spawn(async move {
             let mut unit = self;
-            match method {
-                PostFetchMethod::Single(p_id) => {
-                    post_channel.send(unit.get_post(p_id).await?)?;
-                    length_channel.send(1).await?;
-                }
-                PostFetchMethod::Multiple(p_ids) => {
-                    for p_id in p_ids {
-                        post_channel.send(unit.get_post(p_id).await?)?;
-                        length_channel.send(1).await?;
-                    }
-                }
-            }
+            send_posts(&mut unit, method, &post_channel, &length_channel, &cancel).await?;
             Ok(0)
         })
     }
 }
+
+impl StreamFetch for ExtractorUnit {
+    fn post_stream(
+        &mut self,
+        start_page: Option<u16>,
+        limit: Option<u16>,
+    ) -> impl Stream<Item = Result<Post, ExtractorError>> + Send + '_ {
+        flatten_pages(self.page_stream(start_page, limit))
+    }
+}
+
+impl EmitEvents for ExtractorUnit {
+    fn set_event_listener(&mut self, listener: SharedExtractorListener) {
+        self.events = EventSink::new(listener);
+    }
+}
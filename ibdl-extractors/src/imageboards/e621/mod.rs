@@ -5,6 +5,7 @@
 //! - Native blacklist (defined in user profile page)
 //!
 use crate::auth::{AuthState, ImageboardConfig};
+use crate::events::EventSink;
 use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
 use ibdl_common::post::extension::Extension;
 use ibdl_common::reqwest::{Client, Method};
@@ -46,10 +47,12 @@ pub struct E621Extractor {
     total_removed: u64,
     map_videos: bool,
     excluded_tags: Vec<String>,
-    selected_extension: Option<Extension>,
+    selected_extensions: Vec<Extension>,
+    page_delay: Duration,
     pool_id: Option<u32>,
     pool_last_items_first: bool,
     server_cfg: ServerConfig,
+    events: EventSink,
 }
 
 impl Extractor for E621Extractor {
@@ -90,10 +93,12 @@ impl Extractor for E621Extractor {
             total_removed: 0,
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::from_millis(500),
             pool_id: None,
             pool_last_items_first: false,
             server_cfg: config,
+            events: EventSink::default(),
         }
     }
 
@@ -133,10 +138,12 @@ impl Extractor for E621Extractor {
             total_removed: 0,
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::from_millis(500),
             pool_id: None,
             pool_last_items_first: false,
             server_cfg: config,
+            events: EventSink::default(),
         }
     }
 
@@ -171,7 +178,7 @@ impl Extractor for E621Extractor {
             &self.download_ratings,
             self.disable_blacklist,
             !self.map_videos,
-            self.selected_extension,
+            &self.selected_extensions,
         )
         .await?;
 
@@ -214,9 +221,10 @@ impl Extractor for E621Extractor {
 
             page += 1;
 
-            // debounce
-            debug!("Debouncing API calls by 500 ms");
-            sleep(Duration::from_millis(500)).await;
+            if !self.page_delay.is_zero() {
+                debug!("Debouncing API calls by {:?}", self.page_delay);
+                sleep(self.page_delay).await;
+            }
         }
 
         if fvec.is_empty() {
@@ -242,10 +250,24 @@ impl Extractor for E621Extractor {
     }
 
     fn force_extension(&mut self, extension: Extension) -> &mut Self {
-        self.selected_extension = Some(extension);
+        self.selected_extensions = vec![extension];
+        self
+    }
+
+    fn allow_extensions(&mut self, extensions: &[Extension]) -> &mut Self {
+        self.selected_extensions = extensions.to_vec();
         self
     }
 
+    fn set_page_delay(&mut self, delay: Duration) -> &mut Self {
+        self.page_delay = delay;
+        self
+    }
+
+    fn page_delay(&self) -> Duration {
+        self.page_delay
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -317,6 +339,8 @@ impl Extractor for E621Extractor {
                 extension: Extension::guess_format(&c.file.ext.clone().unwrap()),
                 tags: tag_list,
                 rating: Rating::from_rating_str(&c.rating),
+                score: c.score.as_ref().map(|score| score.total),
+                ugoira_frames: Vec::new(),
             };
 
             post_list.push(unit);
@@ -376,6 +400,8 @@ impl SinglePostFetch for E621Extractor {
                 extension: Extension::guess_format(&c.post.file.ext.clone().unwrap()),
                 tags: tag_list,
                 rating: Rating::from_rating_str(&c.post.rating),
+                score: c.post.score.as_ref().map(|score| score.total),
+                ugoira_frames: Vec::new(),
             };
             Ok(unit)
         } else {
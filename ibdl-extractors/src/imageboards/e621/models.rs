@@ -22,6 +22,13 @@ pub struct E621Post {
     pub file: E621File,
     pub tags: Tags,
     pub rating: String,
+    pub score: Option<E621Score>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "self::serde")]
+pub struct E621Score {
+    pub total: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -81,4 +88,8 @@ impl Tags {
 #[serde(crate = "self::serde")]
 pub struct E621PoolList {
     pub post_ids: Vec<u64>,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
 }
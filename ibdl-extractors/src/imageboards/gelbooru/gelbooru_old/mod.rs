@@ -34,7 +34,8 @@ pub struct GelbooruV0_2Extractor {
     download_ratings: Vec<Rating>,
     map_videos: bool,
     excluded_tags: Vec<String>,
-    selected_extension: Option<Extension>,
+    selected_extensions: Vec<Extension>,
+    page_delay: Duration,
     server_cfg: ServerConfig,
 }
 
@@ -77,7 +78,8 @@ impl Extractor for GelbooruV0_2Extractor {
             download_ratings: download_ratings.to_vec(),
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::from_millis(500),
             server_cfg: config,
         }
     }
@@ -119,7 +121,8 @@ impl Extractor for GelbooruV0_2Extractor {
             download_ratings: download_ratings.to_vec(),
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::from_millis(500),
             server_cfg: config,
         }
     }
@@ -155,7 +158,7 @@ impl Extractor for GelbooruV0_2Extractor {
             &self.download_ratings,
             self.disable_blacklist,
             !self.map_videos,
-            self.selected_extension,
+            &self.selected_extensions,
         )
         .await?;
 
@@ -199,9 +202,10 @@ impl Extractor for GelbooruV0_2Extractor {
 
             page += 1;
 
-            //debounce
-            debug!("Debouncing API calls by 500 ms");
-            sleep(Duration::from_millis(500)).await;
+            if !self.page_delay.is_zero() {
+                debug!("Debouncing API calls by {:?}", self.page_delay);
+                sleep(self.page_delay).await;
+            }
         }
 
         if fvec.is_empty() {
@@ -227,10 +231,24 @@ impl Extractor for GelbooruV0_2Extractor {
     }
 
     fn force_extension(&mut self, extension: Extension) -> &mut Self {
-        self.selected_extension = Some(extension);
+        self.selected_extensions = vec![extension];
         self
     }
 
+    fn allow_extensions(&mut self, extensions: &[Extension]) -> &mut Self {
+        self.selected_extensions = extensions.to_vec();
+        self
+    }
+
+    fn set_page_delay(&mut self, delay: Duration) -> &mut Self {
+        self.page_delay = delay;
+        self
+    }
+
+    fn page_delay(&self) -> Duration {
+        self.page_delay
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -317,6 +335,8 @@ impl Extractor for GelbooruV0_2Extractor {
                     extension: Extension::guess_format(&ext),
                     rating,
                     tags,
+                    score: post["score"].as_i64(),
+                    ugoira_frames: Vec::new(),
                 };
 
                 post_mtx.push(unit);
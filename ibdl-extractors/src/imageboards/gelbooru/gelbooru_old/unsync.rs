@@ -1,19 +1,13 @@
-use std::time::Duration;
-
 use ibdl_common::{
     log::debug,
-    post::Post,
-    tokio::{
-        spawn,
-        sync::mpsc::{Sender, UnboundedSender},
-        task::JoinHandle,
-        time::sleep,
-    },
+    post::channel::PostSender,
+    tokio::{spawn, sync::mpsc::Sender, task::JoinHandle, time::sleep},
+    tokio_util::sync::CancellationToken,
 };
 
 use super::GelbooruV0_2Extractor;
 use crate::extractor::Extractor;
-use crate::prelude::{AsyncFetch};
+use crate::prelude::AsyncFetch;
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 
 // A quick alias so I can copy-paste stuff faster
@@ -22,10 +16,11 @@ type ExtractorUnit = GelbooruV0_2Extractor;
 impl AsyncFetch for ExtractorUnit {
     async fn async_fetch(
         &mut self,
-        sender_channel: UnboundedSender<Post>,
+        sender_channel: PostSender,
         start_page: Option<u16>,
         limit: Option<u16>,
         post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
     ) -> Result<u64, ExtractorError> {
         let blacklist = BlacklistFilter::new(
             self.server_cfg.clone(),
@@ -33,7 +28,7 @@ impl AsyncFetch for ExtractorUnit {
             &self.download_ratings,
             self.disable_blacklist,
             !self.map_videos,
-            self.selected_extension,
+            &self.selected_extensions,
         )
         .await?;
 
@@ -45,6 +40,11 @@ impl AsyncFetch for ExtractorUnit {
         debug!("Async extractor thread initialized");
 
         loop {
+            if cancel.is_cancelled() {
+                debug!("Search cancelled");
+                break;
+            }
+
             let position = start_page.map_or(page - 1, |n| page + n - 1);
 
             let posts = self.get_post_list(position, limit).await?;
@@ -77,7 +77,7 @@ impl AsyncFetch for ExtractorUnit {
                     }
                 }
 
-                sender_channel.send(i)?;
+                sender_channel.send(i).await?;
                 total_posts_sent += 1;
                 if let Some(counter) = &post_counter {
                     counter.send(1).await?;
@@ -97,9 +97,10 @@ impl AsyncFetch for ExtractorUnit {
 
             page += 1;
 
-            //debounce
-            debug!("Debouncing API calls by 500 ms");
-            sleep(Duration::from_millis(500)).await;
+            if !self.page_delay.is_zero() {
+                debug!("Debouncing API calls by {:?}", self.page_delay);
+                sleep(self.page_delay).await;
+            }
         }
 
         debug!("Terminating thread.");
@@ -109,15 +110,16 @@ impl AsyncFetch for ExtractorUnit {
     #[inline]
     fn setup_fetch_thread(
         self,
-        sender_channel: UnboundedSender<Post>,
+        sender_channel: PostSender,
         start_page: Option<u16>,
         limit: Option<u16>,
         post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
     ) -> JoinHandle<Result<u64, ExtractorError>> {
         spawn(async move {
             let mut ext = self;
-            ext.async_fetch(sender_channel, start_page, limit, post_counter)
+            ext.async_fetch(sender_channel, start_page, limit, post_counter, cancel)
                 .await
         })
     }
-}
\ No newline at end of file
+}
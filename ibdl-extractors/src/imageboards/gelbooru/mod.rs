@@ -27,6 +27,7 @@ use std::time::Duration;
 use crate::extractor::caps::ExtractorFeatures;
 use crate::extractor::common::convert_tags_to_string;
 use crate::extractor::Extractor;
+use crate::events::EventSink;
 use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
 use crate::imageboards::gelbooru::models::GelbooruTopLevel;
 use crate::prelude::SinglePostFetch;
@@ -45,8 +46,10 @@ pub struct GelbooruExtractor {
     download_ratings: Vec<Rating>,
     map_videos: bool,
     excluded_tags: Vec<String>,
-    selected_extension: Option<Extension>,
+    selected_extensions: Vec<Extension>,
+    page_delay: Duration,
     server_cfg: ServerConfig,
+    events: EventSink,
     // auth: ImageboardConfig,
     // auth_state: AuthState
 }
@@ -80,8 +83,10 @@ impl Extractor for GelbooruExtractor {
             download_ratings: download_ratings.to_vec(),
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::from_millis(500),
             server_cfg: config,
+            events: EventSink::default(),
             // auth_state: AuthState::NotAuthenticated,
             // auth: ImageboardConfig::default()
         }
@@ -114,8 +119,10 @@ impl Extractor for GelbooruExtractor {
             download_ratings: download_ratings.to_vec(),
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::from_millis(500),
             server_cfg: config,
+            events: EventSink::default(),
             // auth_state: AuthState::NotAuthenticated,
             // auth: ImageboardConfig::default()
         }
@@ -152,7 +159,7 @@ impl Extractor for GelbooruExtractor {
             &self.download_ratings,
             self.disable_blacklist,
             !self.map_videos,
-            self.selected_extension,
+            &self.selected_extensions,
         )
         .await?;
 
@@ -196,9 +203,10 @@ impl Extractor for GelbooruExtractor {
 
             page += 1;
 
-            //debounce
-            debug!("Debouncing API calls by 500 ms");
-            sleep(Duration::from_millis(500)).await;
+            if !self.page_delay.is_zero() {
+                debug!("Debouncing API calls by {:?}", self.page_delay);
+                sleep(self.page_delay).await;
+            }
         }
 
         if fvec.is_empty() {
@@ -224,10 +232,24 @@ impl Extractor for GelbooruExtractor {
     }
 
     fn force_extension(&mut self, extension: Extension) -> &mut Self {
-        self.selected_extension = Some(extension);
+        self.selected_extensions = vec![extension];
+        self
+    }
+
+    fn allow_extensions(&mut self, extensions: &[Extension]) -> &mut Self {
+        self.selected_extensions = extensions.to_vec();
         self
     }
 
+    fn set_page_delay(&mut self, delay: Duration) -> &mut Self {
+        self.page_delay = delay;
+        self
+    }
+
+    fn page_delay(&self) -> Duration {
+        self.page_delay
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -292,6 +314,8 @@ impl Extractor for GelbooruExtractor {
                 extension: Extension::guess_format(&extension),
                 tags: tag_list,
                 rating,
+                score: c.score,
+                ugoira_frames: Vec::new(),
             }
         });
 
@@ -17,6 +17,7 @@ pub struct GelbooruPost {
     pub file_url: Option<String>,
     pub tags: Option<String>,
     pub rating: Option<String>,
+    pub score: Option<i64>,
 }
 
 impl GelbooruPost {
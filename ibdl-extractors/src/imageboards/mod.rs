@@ -66,6 +66,10 @@
 //!
 #![deny(clippy::nursery)]
 
+pub mod any;
+
+pub mod builder;
+
 pub mod danbooru;
 
 pub mod e621;
@@ -7,13 +7,15 @@ use ibdl_common::{
     log::debug,
     post::{rating::Rating, Post, PostQueue},
     serde_json,
-    tokio::time::Instant,
+    tokio::time::{sleep, Instant},
     ImageBoards,
 };
 use std::fmt::Display;
+use std::time::Duration;
 
 use crate::extractor::caps::ExtractorFeatures;
 use crate::extractor::Extractor;
+use crate::events::EventSink;
 use crate::extractor_config::{ServerConfig, DEFAULT_SERVERS};
 use crate::{
     blacklist::BlacklistFilter, error::ExtractorError, imageboards::moebooru::models::KonachanPost,
@@ -31,8 +33,10 @@ pub struct MoebooruExtractor {
     total_removed: u64,
     map_videos: bool,
     excluded_tags: Vec<String>,
-    selected_extension: Option<Extension>,
+    selected_extensions: Vec<Extension>,
+    page_delay: Duration,
     server_cfg: ServerConfig,
+    events: EventSink,
 }
 
 impl Extractor for MoebooruExtractor {
@@ -71,8 +75,10 @@ impl Extractor for MoebooruExtractor {
             total_removed: 0,
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::ZERO,
             server_cfg: config,
+            events: EventSink::default(),
         }
     }
 
@@ -110,8 +116,10 @@ impl Extractor for MoebooruExtractor {
             total_removed: 0,
             map_videos,
             excluded_tags: vec![],
-            selected_extension: None,
+            selected_extensions: vec![],
+            page_delay: Duration::ZERO,
             server_cfg: config,
+            events: EventSink::default(),
         }
     }
 
@@ -146,7 +154,7 @@ impl Extractor for MoebooruExtractor {
             &self.download_ratings,
             self.disable_blacklist,
             !self.map_videos,
-            self.selected_extension,
+            &self.selected_extensions,
         )
         .await?;
 
@@ -188,6 +196,11 @@ impl Extractor for MoebooruExtractor {
             }
 
             page += 1;
+
+            if !self.page_delay.is_zero() {
+                debug!("Debouncing API calls by {:?}", self.page_delay);
+                sleep(self.page_delay).await;
+            }
         }
 
         if fvec.is_empty() {
@@ -213,10 +226,24 @@ impl Extractor for MoebooruExtractor {
     }
 
     fn force_extension(&mut self, extension: Extension) -> &mut Self {
-        self.selected_extension = Some(extension);
+        self.selected_extensions = vec![extension];
+        self
+    }
+
+    fn allow_extensions(&mut self, extensions: &[Extension]) -> &mut Self {
+        self.selected_extensions = extensions.to_vec();
         self
     }
 
+    fn set_page_delay(&mut self, delay: Duration) -> &mut Self {
+        self.page_delay = delay;
+        self
+    }
+
+    fn page_delay(&self) -> Duration {
+        self.page_delay
+    }
+
     async fn get_post_list(
         &self,
         page: u16,
@@ -262,7 +289,7 @@ impl Extractor for MoebooruExtractor {
     }
 
     fn map_posts(&self, raw_json: String) -> Result<Vec<Post>, ExtractorError> {
-        let items = serde_json::from_str::<Vec<KonachanPost>>(raw_json.as_str()).unwrap();
+        let items = serde_json::from_str::<Vec<KonachanPost>>(raw_json.as_str())?;
 
         let post_iter = items.iter().filter(|c| c.file_url.is_some());
 
@@ -289,6 +316,8 @@ impl Extractor for MoebooruExtractor {
                 extension: Extension::guess_format(&ext),
                 tags,
                 rating: Rating::from_rating_str(&c.rating),
+                score: c.score,
+                ugoira_frames: Vec::new(),
             };
 
             post_mtx.push(unit);
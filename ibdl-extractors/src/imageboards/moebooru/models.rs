@@ -8,4 +8,5 @@ pub struct KonachanPost {
     pub file_url: Option<String>,
     pub rating: String,
     pub tags: String,
+    pub score: Option<i64>,
 }
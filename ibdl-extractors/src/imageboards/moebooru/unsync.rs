@@ -1,99 +1,133 @@
+use async_stream::try_stream;
+use futures::Stream;
 use ibdl_common::{
     log::debug,
-    post::Post,
-    tokio::{
-        spawn,
-        sync::mpsc::{Sender, UnboundedSender},
-        task::JoinHandle,
-    },
+    post::{channel::PostSender, Post},
+    tokio::{spawn, sync::mpsc::Sender, task::JoinHandle, time::sleep},
+    tokio_util::sync::CancellationToken,
 };
 
 use super::MoebooruExtractor;
+use crate::events::{EventSink, ExtractorEvent, SharedExtractorListener};
 use crate::extractor::caps::AsyncFetch;
+use crate::extractor::caps::{EmitEvents, StreamFetch};
+use crate::extractor::common::{flatten_pages, send_pages};
 use crate::extractor::Extractor;
 use crate::{blacklist::BlacklistFilter, error::ExtractorError};
 
 // A quick alias so I can copy paste stuff faster
 type ExtractorUnit = MoebooruExtractor;
 
-impl AsyncFetch for ExtractorUnit {
-    async fn async_fetch(
+impl ExtractorUnit {
+    /// Fetches the search one page at a time. Every page is already filtered and cut to fit `limit`.
+    fn page_stream(
         &mut self,
-        sender_channel: UnboundedSender<Post>,
         start_page: Option<u16>,
         limit: Option<u16>,
-        post_counter: Option<Sender<u64>>,
-    ) -> Result<u64, ExtractorError> {
-        let blacklist = BlacklistFilter::new(
-            self.server_cfg.clone(),
-            &self.excluded_tags,
-            &self.download_ratings,
-            self.disable_blacklist,
-            !self.map_videos,
-            self.selected_extension,
-        )
-        .await?;
-
-        let mut has_posts: bool = false;
-        let mut total_posts_sent: u16 = 0;
-
-        let mut page = 1;
-
-        debug!("Async extractor thread initialized");
+    ) -> impl Stream<Item = Result<Vec<Post>, ExtractorError>> + Send + '_ {
+        try_stream! {
+            let blacklist = BlacklistFilter::new(
+                self.server_cfg.clone(),
+                &self.excluded_tags,
+                &self.download_ratings,
+                self.disable_blacklist,
+                !self.map_videos,
+                &self.selected_extensions,
+            )
+            .await?;
+
+            let mut has_posts: bool = false;
+            let mut remaining = limit.map(usize::from);
+
+            let mut page = 1;
+
+            debug!("Async extractor thread initialized");
+
+            loop {
+                let position = start_page.map_or(page, |n| page + n);
+
+                let posts = self.get_post_list(position, limit).await?;
+                let size = posts.len();
+
+                if size == 0 {
+                    if !has_posts {
+                        Err(ExtractorError::ZeroPosts)?;
+                    }
 
-        loop {
-            let position = start_page.map_or(page, |n| page + n);
+                    break;
+                }
 
-            let posts = self.get_post_list(position, limit).await?;
-            let size = posts.len();
+                self.events.emit(ExtractorEvent::PageFetched {
+                    page: position,
+                    posts: size,
+                });
 
-            if size == 0 {
-                if !has_posts {
-                    return Err(ExtractorError::ZeroPosts);
+                if self.events.is_active() {
+                    for post in &posts {
+                        self.events
+                            .emit(ExtractorEvent::PostDiscovered { post_id: post.id });
+                    }
                 }
 
-                break;
-            }
-
-            let list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
-                let (removed, posts) = blacklist.filter(posts);
-                self.total_removed += removed;
-                posts
-            } else {
-                posts
-            };
+                let mut list = if !self.disable_blacklist || !self.download_ratings.is_empty() {
+                    let (removed, posts) = blacklist.filter_with_events(posts, &self.events);
+                    self.total_removed += removed;
+                    posts
+                } else {
+                    posts
+                };
 
-            if !has_posts && !list.is_empty() {
-                has_posts = true;
-            }
+                if !has_posts && !list.is_empty() {
+                    has_posts = true;
+                }
 
-            for i in list {
-                if let Some(num) = limit {
-                    if total_posts_sent >= num {
-                        break;
-                    }
+                if let Some(remaining) = &mut remaining {
+                    list.truncate(*remaining);
+                    *remaining -= list.len();
                 }
 
-                sender_channel.send(i)?;
-                total_posts_sent += 1;
-                if let Some(counter) = &post_counter {
-                    counter.send(1).await?;
+                yield list;
+
+                if remaining == Some(0) {
+                    debug!("Target post count of {} reached.", limit.unwrap_or_default());
+                    break;
                 }
-            }
 
-            if let Some(num) = limit {
-                if total_posts_sent >= num {
-                    debug!("Target post count of {} reached.", num);
+                if page == 100 {
+                    debug!("Max number of pages reached");
                     break;
                 }
-            }
 
-            if page == 100 {
-                break;
+                page += 1;
+                if !self.page_delay.is_zero() {
+                    debug!("Debouncing API calls by {:?}", self.page_delay);
+                    sleep(self.page_delay).await;
+                }
             }
-
-            page += 1;
         }
+    }
+}
+
+impl AsyncFetch for ExtractorUnit {
+    async fn async_fetch(
+        &mut self,
+        sender_channel: PostSender,
+        start_page: Option<u16>,
+        limit: Option<u16>,
+        post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
+    ) -> Result<u64, ExtractorError> {
+        let events = self.events.clone();
+        let pages = self.page_stream(start_page, limit);
+
+        send_pages(
+            pages,
+            &sender_channel,
+            post_counter.as_ref(),
+            &cancel,
+            &events,
+        )
+        .await?;
 
         debug!("Terminating thread.");
         Ok(self.total_removed)
@@ -102,15 +136,32 @@ impl AsyncFetch for ExtractorUnit {
     #[inline]
     fn setup_fetch_thread(
         self,
-        sender_channel: UnboundedSender<Post>,
+        sender_channel: PostSender,
         start_page: Option<u16>,
         limit: Option<u16>,
         post_counter: Option<Sender<u64>>,
+        cancel: CancellationToken,
     ) -> JoinHandle<Result<u64, ExtractorError>> {
         spawn(async move {
             let mut ext = self;
-            ext.async_fetch(sender_channel, start_page, limit, post_counter)
+            ext.async_fetch(sender_channel, start_page, limit, post_counter, cancel)
                 .await
         })
     }
 }
+
+impl StreamFetch for ExtractorUnit {
+    fn post_stream(
+        &mut self,
+        start_page: Option<u16>,
+        limit: Option<u16>,
+    ) -> impl Stream<Item = Result<Post, ExtractorError>> + Send + '_ {
+        flatten_pages(self.page_stream(start_page, limit))
+    }
+}
+
+impl EmitEvents for ExtractorUnit {
+    fn set_event_listener(&mut self, listener: SharedExtractorListener) {
+        self.events = EventSink::new(listener);
+    }
+}
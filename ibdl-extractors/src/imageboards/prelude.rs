@@ -1,3 +1,5 @@
+pub use super::any::AnyExtractor;
+pub use super::builder::{ConfiguredExtractor, ExtractorBuilder, VideoPolicy};
 pub use super::danbooru::DanbooruExtractor;
 pub use super::e621::E621Extractor;
 pub use super::gelbooru::GelbooruExtractor;
@@ -11,6 +11,7 @@ extern crate ibdl_common;
 pub mod auth;
 pub mod blacklist;
 pub mod error;
+pub mod events;
 mod extractor;
 pub mod extractor_config;
 pub mod imageboards;
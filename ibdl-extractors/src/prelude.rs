@@ -1,9 +1,14 @@
 pub use crate::extractor::caps::AsyncFetch;
 pub use crate::extractor::caps::Auth;
+pub use crate::extractor::caps::EmitEvents;
 pub use crate::extractor::caps::ExtractorFeatures;
 pub use crate::extractor::caps::ExtractorThreadHandle;
 pub use crate::extractor::caps::PoolExtract;
+pub use crate::extractor::caps::PoolInfo;
 pub use crate::extractor::caps::PostFetchAsync;
 pub use crate::extractor::caps::PostFetchMethod;
 pub use crate::extractor::caps::SinglePostFetch;
+pub use crate::extractor::caps::StreamFetch;
 pub use crate::extractor::Extractor;
+pub use crate::imageboards::any::AnyExtractor;
+pub use crate::imageboards::builder::{ConfiguredExtractor, ExtractorBuilder, VideoPolicy};
@@ -1,8 +1,16 @@
 #![cfg(test)]
+use crate::error::ExtractorError;
+use crate::extractor::caps::{PostFetchMethod, SinglePostFetch};
+use crate::extractor::common::send_posts;
 use crate::extractor::Extractor;
 use crate::extractor_config::DEFAULT_SERVERS;
 use crate::imageboards::danbooru::DanbooruExtractor;
-use ibdl_common::{post::rating::Rating, ImageBoards};
+use ibdl_common::{
+    post::{channel::post_channel, extension::Extension, rating::Rating, Post},
+    tokio::sync::mpsc::channel,
+    tokio_util::sync::CancellationToken,
+    ImageBoards,
+};
 
 #[tokio::test]
 async fn danbooru_test_post_api() {
@@ -142,3 +150,74 @@ async fn moebooru_test_post_api() {
     assert_ne!(first_post.rating, Rating::Unknown);
     assert!(first_post.tags.iter().any(|tag| tag.tag() == "1girl"));
 }
+
+/// Answers every post ID with an empty post, and cancels the fetch once `cancel_after` posts were fetched.
+struct CancellingFetch {
+    fetched: Vec<u32>,
+    cancel_after: usize,
+    cancel: CancellationToken,
+}
+
+impl SinglePostFetch for CancellingFetch {
+    fn map_post(&self, _raw_json: String) -> Result<Post, ExtractorError> {
+        Err(ExtractorError::UnsupportedOperation)
+    }
+
+    async fn get_post(&mut self, post_id: u32) -> Result<Post, ExtractorError> {
+        self.fetched.push(post_id);
+        if self.fetched.len() == self.cancel_after {
+            self.cancel.cancel();
+        }
+        Ok(Post {
+            id: post_id.into(),
+            website: ImageBoards::Danbooru,
+            url: String::new(),
+            md5: String::new(),
+            extension: Extension::PNG,
+            rating: Rating::Safe,
+            tags: Vec::new(),
+            score: None,
+            ugoira_frames: Vec::new(),
+        })
+    }
+
+    async fn get_posts(&mut self, _posts: &[u32]) -> Result<Vec<Post>, ExtractorError> {
+        Err(ExtractorError::UnsupportedOperation)
+    }
+}
+
+#[tokio::test]
+async fn post_fetch_stops_once_cancelled() {
+    let cancel = CancellationToken::new();
+    let mut unit = CancellingFetch {
+        fetched: Vec::new(),
+        cancel_after: 2,
+        cancel: cancel.clone(),
+    };
+    let (post_channel, mut posts) = post_channel(Some(10));
+    let (length_channel, mut lengths) = channel(10);
+
+    send_posts(
+        &mut unit,
+        PostFetchMethod::Multiple(vec![1, 2, 3, 4]),
+        &post_channel,
+        &length_channel,
+        &cancel,
+    )
+    .await
+    .unwrap();
+    drop((post_channel, length_channel));
+
+    // The post fetched right before cancelling is still sent
+    assert_eq!(unit.fetched, [1, 2]);
+    let mut sent = Vec::new();
+    while let Some(post) = posts.recv().await {
+        sent.push(post.id);
+    }
+    assert_eq!(sent, [1, 2]);
+    let mut queued = 0;
+    while let Some(length) = lengths.recv().await {
+        queued += length;
+    }
+    assert_eq!(queued, 2);
+}
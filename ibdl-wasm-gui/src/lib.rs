@@ -1,6 +1,7 @@
 use std::sync::Arc;
 
 use ibdl_common::tokio::task::{JoinHandle, spawn_local};
+use ibdl_common::tokio_util::sync::CancellationToken;
 use ibdl_common::{all_ratings, post::rating::Rating};
 use ibdl_extractors::error::ExtractorError;
 use serde::{Deserialize, Serialize};
@@ -20,13 +21,8 @@ use ibdl_common::tokio::sync::{
 
 use ibdl_extractors::prelude::*;
 
-// Your extractors - you'll need to figure out how to instantiate and use them.
-// This is a simplified example. The actual extractor API from your project will be used.
-use ibdl_extractors::imageboards::{
-    // ... other extractors
-    danbooru::DanbooruExtractor,
-    e621::E621Extractor,
-};
+// Any server from the default list can be selected by name.
+use ibdl_extractors::extractor_config::DEFAULT_SERVERS;
 
 // Helper for logging to the browser console
 #[wasm_bindgen]
@@ -117,35 +113,23 @@ pub async fn fetch_links(
         console_log!("Rust (WASM): Receiver task finished.");
     });
 
+    let Some(config) = DEFAULT_SERVERS.get(&site_name.to_lowercase()).cloned() else {
+        // If site is unsupported, drop the sender immediately to close the channel
+        drop(sender);
+        // Return an error immediately
+        return Err(JsValue::from_str(&format!(
+            "Unsupported site: {}",
+            site_name
+        )));
+    };
+
+    console_log!("Rust (WASM): Using {} extractor", config.pretty_name);
+    let extractor = AnyExtractor::new(&tags, all_ratings!(), true, false, config);
+
     // Spawn the extractor task using setup_fetch_thread
+    // Start page 1, pass limit, no post_counter
     let extractor_handle: JoinHandle<Result<u64, ExtractorError>> =
-        match site_name.to_lowercase().as_str() {
-            "danbooru" => {
-                // Example: Instantiate and use DanbooruExtractor
-                // The exact method signature and parameters will depend on your actual extractor implementation.
-                // The extractor's search/fetch method MUST be async and use a WASM-compatible reqwest client.
-                let extractor = DanbooruExtractor::new(&tags, all_ratings!(), true, false); // Adapt constructor
-                console_log!("Rust (WASM): Using DanbooruExtractor");
-                // Use setup_fetch_thread to spawn the async_fetch logic
-                extractor.setup_fetch_thread(sender, Some(1), limit, None) // Start page 1, pass limit, no post_counter
-            }
-            "e621" => {
-                let extractor = E621Extractor::new(&tags, all_ratings!(), true, false); // Adapt constructor
-                console_log!("Rust (WASM): Using E621Extractor");
-                // Use setup_fetch_thread to spawn the async_fetch logic
-                extractor.setup_fetch_thread(sender, Some(1), limit, None) // Start page 1, pass limit, no post_counter
-            }
-            // Add other sites your `ibdl-extractors` support
-            _ => {
-                // If site is unsupported, drop the sender immediately to close the channel
-                drop(sender);
-                // Return an error immediately
-                return Err(JsValue::from_str(&format!(
-                    "Unsupported site: {}",
-                    site_name
-                )));
-            }
-        };
+        extractor.setup_fetch_thread(sender.into(), Some(1), limit, None, CancellationToken::new());
 
     // Wait for the extractor task to finish. This will also cause the sender to be dropped.
     let extractor_result = extractor_handle.await;
@@ -1,18 +1,22 @@
 #![deny(clippy::all)]
-use color_eyre::eyre::{Result, bail};
+use color_eyre::eyre::Result;
 use color_eyre::owo_colors::OwoColorize;
 use dialoguer::Confirm;
+use ibdl_cli::cli::commands::stats::{print_tag_report, write_tag_stats};
+use ibdl_cli::cli::job::ExtractorJob;
 use ibdl_cli::cli::{AVAILABLE_SERVERS, Cli, Commands};
-use ibdl_cli::progress_bars::IndicatifProgressHandler; // Import the CLI progress handler
-use ibdl_core::async_queue::Queue;
+use ibdl_cli::progress_bars::{IndicatifProgressHandler, ProgressMode}; // Import the CLI progress handlers
+use ibdl_common::tokio_util::sync::CancellationToken;
 use ibdl_core::async_queue::QueueOpts;
 use ibdl_core::clap::Parser;
-use ibdl_core::progress::ProgressListener;
+use ibdl_core::events::SharedEventListener;
+use ibdl_core::job::JobState;
+use ibdl_core::report::{DownloadReport, SessionReport};
+use ibdl_core::session::{OutputOptions, PostSource, Session};
 use ibdl_extractors::prelude::ExtractorFeatures;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::sync::Arc;
-use tokio::sync::mpsc::{channel, unbounded_channel};
-use tokio::{self, join};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -25,9 +29,33 @@ async fn main() -> Result<()> {
     env_logger::builder().format_timestamp(None).init();
     color_eyre::install()?;
 
+    if let Commands::Stats(com) = &args.mode {
+        let (report, path) = com.run(args.stats_top).await?;
+        print_tag_report(&report, args.stats_top, &path);
+        return Ok(());
+    }
+
     let dirname = args.generate_save_path()?;
 
-    if (dirname.exists() && (dirname.is_file() || dirname.read_dir()?.next().is_some()))
+    let job_path = args.job_state_path(&dirname);
+    let resumed = if args.resume {
+        Some(args.load_job_state(&job_path).await?)
+    } else {
+        None
+    };
+
+    let json_progress =
+        (args.progress == ProgressMode::Json).then(|| Arc::new(args.json_progress_handler()));
+
+    #[cfg(feature = "s3")]
+    let s3_sink = args.s3_sink().await?;
+
+    if args.export.is_none()
+        && !args.upload_to_s3()
+        && !args.save_as_cbz()
+        && json_progress.is_none()
+        && resumed.is_none()
+        && (dirname.exists() && (dirname.is_file() || dirname.read_dir()?.next().is_some()))
         && !args.overwrite
     {
         let conf_exists = Confirm::new()
@@ -44,75 +72,212 @@ async fn main() -> Result<()> {
         }
     }
 
-    // Channel for posts from extractor to queue
-    let (posts_sender, posts_receiver) = unbounded_channel();
-    // Channel for total post count from extractor to progress handler
-    let (length_sender, mut length_receiver) = channel::<u64>(1);
-    let mut is_pool = false;
-
     // Create the progress handler instance
     // The initial length will be set by the extractor via the listener
-    let progress_handler = Arc::new(IndicatifProgressHandler::new(
-        0,
-        args.imageboard.clone().server,
-    ));
-
-    // Task to update the main progress bar's total length
-    let progress_total_updater_task = tokio::spawn({
-        let progress_handler = progress_handler.clone();
-        async move {
-            while let Some(total_posts) = length_receiver.recv().await {
-                progress_handler.inc_main_total(total_posts);
+    let progress_handler: SharedEventListener = match &json_progress {
+        Some(json) => json.clone(),
+        None => Arc::new(IndicatifProgressHandler::new(
+            0,
+            args.imageboard.clone().server,
+        )),
+    };
+
+    let job = ExtractorJob {
+        start_page: resumed
+            .as_ref()
+            .map_or_else(|| args.start_page(), |state| state.cursor),
+        limit: resumed
+            .as_ref()
+            .map_or_else(|| args.limit(), |state| state.limit),
+    };
+
+    let mut source = match &args.mode {
+        _ if resumed.as_ref().is_some_and(|state| state.cursor.is_none()) => {
+            // The extractor already went through all pages, only the pending posts are left
+            PostSource::List {
+                posts: Vec::new(),
+                filtered: 0,
             }
-            // Receiver will be dropped when sender is dropped by the extractor or extractor finishes
         }
-    });
+        Commands::Search(com) => com.post_source(&args, &job).await?,
+        Commands::Pool(com) => com.post_source(&args, &job).await?,
+        Commands::Post(com) => com.post_source(&args).await?,
+        Commands::Import(com) => com.post_source().await?,
+        Commands::Replay(com) => com.post_source(&args).await?,
+        Commands::Stats(_) => unreachable!("Stats doesn't download anything"),
+    };
+
+    let pool_info = match &args.mode {
+        Commands::Pool(com) if args.save_as_cbz() => com.pool_info(&mut source).await?,
+        _ => None,
+    };
 
-    let (ext, client) = match &args.mode {
-        Commands::Search(com) => {
-            com.init_extractor(&args, posts_sender, length_sender)
-                .await?
+    let output_options = OutputOptions {
+        queue: QueueOpts {
+            save_as_cbz: args.save_as_cbz(),
+            pool_download: matches!(args.mode, Commands::Pool(_)),
+            name_type: args.name_type(),
+            annotate: args.annotate,
+        },
+        simultaneous_downloads: args.simultaneous_downloads,
+        channel_size: args.post_channel_size(),
+    };
+
+    let mut session = Session::new(args.imageboard.clone(), source, output_options)
+        .events(progress_handler)
+        .query(args.job_query())
+        .pool_info(pool_info)
+        .caption(args.caption_config())
+        .tag_stats(args.tag_stats);
+
+    #[cfg(feature = "cbz")]
+    {
+        session = session
+            .volumes(args.cbz_volumes())
+            .compression(args.zip_compression())
+            .existing_archive(args.existing_archive(resumed.is_some()));
+    }
+
+    #[cfg(feature = "image")]
+    {
+        session = session.process(args.image_processor());
+    }
+
+    if let Some(state) = &resumed {
+        session = session.resume(state.pending.clone());
+    }
+
+    if let Some(format) = args.export {
+        let (list_path, output_dir) = args.export_paths(format)?;
+        let exported = session
+            .export(format, list_path.clone(), output_dir)
+            .await?;
+
+        match json_progress {
+            Some(json) => json.export_summary(exported, &list_path),
+            None => print_export_results(exported, &list_path),
         }
-        Commands::Pool(com) => {
-            is_pool = true;
-            com.init_extractor(&args, posts_sender, length_sender)
-                .await?
+        return Ok(());
+    }
+
+    handle_interrupts(session.cancel_token());
+
+    let failed_list = args.failed_list_path(&dirname);
+    let tag_stats_path = args.tag_stats_path(&dirname);
+    let report = 'run: {
+        #[cfg(feature = "s3")]
+        if let Some(sink) = s3_sink {
+            break 'run session.run_with_sink(sink).await?;
         }
-        Commands::Post(com) => {
-            com.init_extractor(&args, posts_sender, length_sender)
-                .await?
+        #[cfg(feature = "tar")]
+        if let Some(sink) = args.tar_sink(&dirname).await? {
+            break 'run session.run_with_sink(sink).await?;
         }
+        #[cfg(feature = "tar")]
+        if let Some(sink) = args.webdataset_sink(&dirname).await? {
+            break 'run session.run_with_sink(sink).await?;
+        }
+        if let Some(sink) = args.image_folder_sink(&dirname).await? {
+            break 'run session.run_with_sink(sink).await?;
+        }
+        session.run(dirname).await?
     };
+    report.download.write_failed_list(&failed_list).await?;
 
-    let output_options = QueueOpts {
-        #[cfg(feature = "cbz")]
-        save_as_cbz: args.cbz,
-        #[cfg(not(feature = "cbz"))]
-        save_as_cbz: false, // If CBZ feature is off, this must be false
-        pool_download: is_pool,
-        name_type: args.name_type(),
-        annotate: args.annotate,
+    let tag_report = match &report.download.tag_stats {
+        Some(stats) => Some(write_tag_stats(stats, args.stats_top, &tag_stats_path).await?),
+        None => None,
     };
 
-    let qw = Queue::new(
-        args.imageboard.clone(),
-        args.simultaneous_downloads,
-        Some(client),
-        output_options,
-        Some(progress_handler.clone()), // Pass the progress handler
-    );
+    let job_state = save_job_state(&args, &job, &report, &job_path).await?;
 
-    let downloader_task = qw.setup_async_downloader(dirname, posts_receiver);
+    match json_progress {
+        Some(json) => json.summary(&report, &failed_list, job_state.as_deref()),
+        None => {
+            print_results(report.download.downloaded, report.filtered);
+            print_failures(&report.download, &failed_list);
+            if report.download.cancelled {
+                print_interrupted(report.download.pending.len(), job_state.as_deref());
+            }
+            if let Some(tag_report) = &tag_report {
+                print_tag_report(tag_report, args.stats_top, &tag_stats_path);
+            }
+        }
+    }
 
-    let (Ok(removed), Ok(results), Ok(_)) =
-        join!(ext, downloader_task, progress_total_updater_task)
-    else {
-        bail!("Failed starting threads!")
-    };
+    Ok(())
+}
 
-    print_results(results?, removed?);
+/// The first Ctrl-C stops the download and keeps what's left for `--resume`, a second one exits immediately.
+fn handle_interrupts(cancel: CancellationToken) {
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        cancel.cancel();
 
-    Ok(())
+        if tokio::signal::ctrl_c().await.is_ok() {
+            exit(130);
+        }
+    });
+}
+
+/// Saves the state of an interrupted run so it can be resumed, or removes the one left by a previous run.
+///
+/// Returns the path of the saved state.
+async fn save_job_state(
+    args: &Cli,
+    job: &ExtractorJob,
+    report: &SessionReport,
+    job_path: &Path,
+) -> Result<Option<PathBuf>> {
+    if report.download.cancelled && args.supports_resume() {
+        let limit = job
+            .limit
+            .map(|limit| limit.saturating_sub(u16::try_from(report.queued).unwrap_or(u16::MAX)));
+        let cursor = report.cursor.filter(|_| limit != Some(0));
+
+        if cursor.is_some() || !report.download.pending.is_empty() {
+            let state = JobState {
+                server: args.imageboard.name.clone(),
+                query: args.job_query(),
+                cursor,
+                limit,
+                pending: report.download.pending.clone(),
+            };
+            state.save(job_path).await?;
+            return Ok(Some(job_path.to_path_buf()));
+        }
+    }
+
+    JobState::remove(job_path).await?;
+    Ok(None)
+}
+
+fn print_interrupted(pending: usize, job_state: Option<&Path>) {
+    println!(
+        "{} {} {}",
+        "Download interrupted,".bold().yellow(),
+        pending.to_string().bold().blue(),
+        "posts were not downloaded.".bold()
+    );
+
+    if let Some(path) = job_state {
+        println!(
+            "{} {}",
+            "Run the same command with --resume to continue. Progress saved in".bold(),
+            path.display().bold().blue()
+        );
+    }
+}
+
+fn print_export_results(total_exported: u64, list_path: &Path) {
+    println!(
+        "{} {} {}",
+        total_exported.to_string().bold().blue(),
+        "posts exported to".bold(),
+        list_path.display().bold().blue()
+    );
 }
 
 fn print_results(total_down: u64, total_black: u64) {
@@ -134,6 +299,34 @@ fn print_results(total_down: u64, total_black: u64) {
     }
 }
 
+fn print_failures(report: &DownloadReport, failed_list: &Path) {
+    if report.failed.is_empty() {
+        return;
+    }
+
+    println!(
+        "{} {}",
+        report.failed.len().to_string().bold().red(),
+        "posts failed to download:".bold().red()
+    );
+
+    for (reason, posts) in report.failures_by_reason() {
+        let ids: Vec<String> = posts.iter().map(|f| f.post.id.to_string()).collect();
+        println!(
+            " - {} {}: {}",
+            posts.len().to_string().bold().red(),
+            reason.bold(),
+            ids.join(", ")
+        );
+    }
+
+    println!(
+        "{} {}",
+        "Retry them with: imageboard_downloader import".bold(),
+        failed_list.display().bold().blue()
+    );
+}
+
 fn print_servers() {
     println!(
         "{}\n----------------",